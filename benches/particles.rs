@@ -0,0 +1,55 @@
+// Run with `cargo bench`. The game is a single binary with no library to link against, so the
+// particle modules are compiled in here by path.
+
+#![allow(dead_code, unused_imports)]   // The game uses more of these modules than the bench does
+
+#[path = "../src/emitter.rs"]
+mod emitter;
+#[path = "../src/keyvalue.rs"]
+mod keyvalue;
+#[path = "../src/particle_pool.rs"]
+mod particle_pool;
+
+use std::time::Instant;
+
+use raylib::{math::Vector2, color::Color};
+use rand::Rng;
+
+use crate::{particle_pool::ParticlePool, emitter::EmitterConfig};
+
+const BENCH_PARTICLE_COUNTS: [usize; 5] = [1_000, 2_000, 4_000, 10_000, 100_000];
+const BENCH_FRAMES: u32 = 500;
+const BENCH_DT: f32 = 1.0/144.0;
+
+// Average time (in microseconds) per frame to kill and update a full pool of particles
+fn time_pool_update(count: usize, parallel_threshold: usize) -> f64 {
+	let mut rng = rand::thread_rng();
+	let config = EmitterConfig { growth: 15.0, drag: 0.5, ..Default::default() };
+	let mut pool = ParticlePool::with_budget(count);
+	pool.parallel_threshold = parallel_threshold;
+
+	for _ in 0..count {
+		let vel = Vector2 { x: rng.gen_range(-100.0..100.0), y: rng.gen_range(-100.0..100.0) };
+		pool.spawn(Vector2::zero(), vel, rng.gen_range(1.0..10.0), 0.0, f64::MAX, Color::BLACK);  // Never die, so count stays fixed
+	}
+
+	let start = Instant::now();
+	let mut time = 0.0;
+	for _ in 0..BENCH_FRAMES {
+		time += BENCH_DT as f64;
+		pool.kill_dead(time);
+		pool.update(BENCH_DT, time, &config, Vector2::zero());
+	}
+	start.elapsed().as_secs_f64() * 1e6 / BENCH_FRAMES as f64
+}
+
+fn main() {
+	println!("Particle update cost per frame ({} frames each):", BENCH_FRAMES);
+	println!("{:>10} {:>14} {:>14} {:>14}", "particles", "serial (us)", "parallel (us)", "auto (us)");
+	for &count in BENCH_PARTICLE_COUNTS.iter() {
+		let serial = time_pool_update(count, usize::MAX);
+		let parallel = time_pool_update(count, 0);
+		let auto = time_pool_update(count, crate::particle_pool::DEF_PARALLEL_THRESHOLD);
+		println!("{:>10} {:>14.1} {:>14.1} {:>14.1}", count, serial, parallel, auto);
+	}
+}
@@ -0,0 +1,161 @@
+// Computer driver. Works out an Input each tick the same way a player would press keys, so the car
+// can't do anything a player couldn't. Follows the level's gymkhana course if it has one, otherwise
+// orbits each pillar in turn, alternating direction.
+//
+// It aims for a target orbit round the pillar: far away it heads for the tangent of the orbit
+// circle, close in it holds the car's nose DRIFT_ANGLE inside its velocity and turns at the rate
+// the orbit needs, steering in or out as the radius drifts. Throttle keeps the speed up and, since
+// it pushes the car along its nose, pulls the car back when `perp` says it's spinning out.
+//
+// Chasing in tandem it orbits whichever pillar the leader is on, the way the leader is turning,
+// and uses the throttle to hold its gap.
+
+use raylib::math::Vector2;
+
+use crate::{
+	car::{self, Car},
+	course::Direction,
+	input::Input,
+	level::Level,
+	misc,
+	POINT_DIST_THRESHOLD, TWO_PI,
+};
+
+const HALF_PI: f32 = TWO_PI/4.0;
+const ORBIT_RADIUS: f32 = POINT_DIST_THRESHOLD * 0.5;
+const CAPTURE_RADIUS: f32 = POINT_DIST_THRESHOLD;   // Counts as orbiting inside this, where it would score
+const DRIFT_ANGLE: f32 = 0.5;       // Radians the nose is held inside the velocity while orbiting
+const RADIUS_GAIN: f32 = 1.0;       // Radians of heading correction per orbit radius of error
+const HEADING_GAIN: f32 = 4.0;      // Angular velocity wanted per radian of heading error
+const STEER_DEADBAND: f32 = 0.05;   // Angular velocity error that's left alone, stops the steering flickering
+const TARGET_SPEED: f32 = 300.0;    // Pixels per second
+const MAX_SPEED: f32 = 450.0;       // Brakes above this
+const MAX_PERP: f32 = 0.8;          // Beyond this the car is spinning out, so throttle to pull it straight
+const CHASE_GAP: f32 = car::CAR_H * 1.5;
+
+#[derive(Default, Clone)]
+pub struct AiDriver {
+	step: usize,               // Through the course, or the pillars if the level has no course
+	swept: f32,                // Radians orbited round the current pillar the right way
+	last_angle: Option<f32>,   // Angle of the car from the pillar on the last tick, while orbiting
+}
+
+impl AiDriver {
+	pub fn update(&mut self, car: &Car, level: &Level) -> Input {
+		let (pillar, direction, rotations) = match self.get_target(level) {
+			Some(target) => target,
+			None => return Input { accelerate: car.vel_mag < TARGET_SPEED, ..Input::default() },
+		};
+		let centre = level.pillars[pillar].pos;
+		if car.pos.distance_to(centre) < CAPTURE_RADIUS {
+			let angle = centre.angle_to(car.pos);
+			if let Some(last) = self.last_angle {
+				self.swept += misc::wrap_angle(angle - last) * direction.sign();
+			}
+			self.last_angle = Some(angle);
+			if self.swept >= rotations * TWO_PI {
+				self.next_step();
+			}
+		} else {
+			self.last_angle = None;
+		}
+		drive(car, centre, direction)
+	}
+
+	pub fn update_chase(&mut self, car: &Car, leader: &Car, level: &Level) -> Input {
+		let centre = match level.pillars.iter().min_by(|a, b| a.distance_to(leader.pos).total_cmp(&b.distance_to(leader.pos))) {
+			Some(p) => p.pos,
+			None => return self.update(car, level),
+		};
+		// Turning right, so lowering its angle, means the leader is going clockwise
+		let direction = if leader.angular_vel < 0.0 { Direction::Clockwise } else { Direction::Anticlockwise };
+		let mut input = drive(car, centre, direction);
+
+		let gap = car.pos.distance_to(leader.pos);
+		if gap > CHASE_GAP * 1.5 {
+			input.accelerate = true;
+			input.brake = false;
+		} else if gap < CHASE_GAP * 0.7 {
+			input.accelerate = false;
+		}
+		input
+	}
+
+	// Pillar index, direction and number of orbits
+	fn get_target(&self, level: &Level) -> Option<(usize, Direction, f32)> {
+		if !level.course.is_empty() {
+			let step = &level.course[self.step % level.course.len()];
+			Some((step.pillar, step.direction, step.rotations))
+		} else if !level.pillars.is_empty() {
+			let direction = if self.step % 2 == 0 { Direction::Clockwise } else { Direction::Anticlockwise };
+			Some((self.step % level.pillars.len(), direction, 1.0))
+		} else {
+			None
+		}
+	}
+
+	#[inline]
+	fn next_step(&mut self) {
+		self.step += 1;
+		self.swept = 0.0;
+		self.last_angle = None;
+	}
+}
+
+// Steering and throttle for an orbit round centre
+fn drive(car: &Car, centre: Vector2, direction: Direction) -> Input {
+	let to_pillar = centre - car.pos;
+	let dist = to_pillar.length();
+	let orbiting = dist < CAPTURE_RADIUS;
+
+	// Clockwise on screen is turning right, which lowers the car's angle
+	let turn = -direction.sign();
+	let offset = if dist > ORBIT_RADIUS {
+		(ORBIT_RADIUS/dist).asin()   // Along the tangent to the orbit
+	} else {
+		HALF_PI + RADIUS_GAIN * (ORBIT_RADIUS - dist)/ORBIT_RADIUS
+	};
+	let vel_heading = misc::get_heading(to_pillar) - turn * offset;
+	let (nose, orbit_rate) = if orbiting {
+		(vel_heading + turn * DRIFT_ANGLE, turn * car.vel_mag/ORBIT_RADIUS)
+	} else {
+		(vel_heading, 0.0)
+	};
+	let target_angular_vel = orbit_rate + HEADING_GAIN * misc::wrap_angle(nose - car.angle);
+
+	let accelerate = car.vel_mag < TARGET_SPEED || car.perp.abs() > MAX_PERP;
+	Input {
+		accelerate,
+		brake: !accelerate && car.vel_mag > MAX_SPEED,
+		steer_left: car.angular_vel < target_angular_vel - STEER_DEADBAND,
+		steer_right: car.angular_vel > target_angular_vel + STEER_DEADBAND,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{car_spec::CarSpec, level, particle_pool::DEF_PARTICLE_BUDGET, TICK_DT, TICK_RATE};
+
+	const MIN_DRIFT_SHARE: f32 = 0.4;   // Every shipped level does better than 0.5 with the default car
+
+	// Guards the physics tuning: if a change stops the car holding a drift, this fails
+	#[test]
+	fn holds_a_drift_on_every_level() {
+		let levels = level::load_all(level::LEVEL_DIR);
+		assert!(!levels.is_empty());
+		for level in levels.iter() {
+			let mut car = Car::new(&CarSpec::default(), level.start_pos, level.start_angle, 1, DEF_PARTICLE_BUDGET);
+			let mut ai = AiDriver::default();
+			let ticks = TICK_RATE * 20;
+			let mut drifting = 0;
+			for t in 0..ticks {
+				let input = ai.update(&car, level);
+				car.update(&input, TICK_DT, (t as f32 * TICK_DT) as f64, level);
+				if car.drifting { drifting += 1 }
+			}
+			let share = drifting as f32/ticks as f32;
+			assert!(share >= MIN_DRIFT_SHARE, "{} only drifted {:.0}% of the time", level.name, share * 100.0);
+		}
+	}
+}
@@ -0,0 +1,108 @@
+use std::os::raw::c_void;
+
+use raylib::{core::audio::{RaylibAudio, AudioStream}, ffi, RaylibThread};
+
+use crate::{
+	car::Car,
+	synth::{Synth, SynthParams, OneShot, SAMPLE_RATE, to_i16},
+};
+
+const STREAM_BUFFER_FRAMES: i32 = 2048;   // Split in two by raylib, so each update writes half of this
+const IDLE_RPM: f32 = 900.0;
+const REDLINE_RPM: f32 = 7200.0;
+const GEAR_TOP_SPEEDS: [f32; 5] = [120.0, 220.0, 330.0, 450.0, 600.0];  // Pixels per second
+const WHEELSPIN_RPM: f32 = 2500.0;        // Extra revs at full throttle while fully sideways
+const SQUEAL_MIN_PERP: f32 = 0.2;
+const SQUEAL_FULL_SPEED: f32 = 300.0;
+
+pub struct Audio {
+	stream: AudioStream,    // Fields drop in order, and the stream has to go before the device closes
+	device: RaylibAudio,
+	synth: Synth,
+	float_buffer: Vec<f32>,
+	sample_buffer: Vec<i16>,
+}
+
+impl Audio {
+	pub fn new(rl_thread: &RaylibThread) -> Option<Audio> {
+		let mut device = RaylibAudio::init_audio_device();
+		if !device.is_audio_device_ready() {
+			return None;
+		}
+
+		unsafe { ffi::SetAudioStreamBufferSizeDefault(STREAM_BUFFER_FRAMES) }
+		let mut stream = AudioStream::init_audio_stream(rl_thread, SAMPLE_RATE, 16, 1);
+		device.play_audio_stream(&mut stream);
+
+		let half = (STREAM_BUFFER_FRAMES/2) as usize;
+		Some(Audio {
+			stream,
+			device,
+			synth: Synth::default(),
+			float_buffer: vec![0.0; half],
+			sample_buffer: vec![0; half],
+		})
+	}
+
+	pub fn update(&mut self, car: &Car) {
+		self.synth.set_params(get_car_params(car));
+
+		while self.device.is_audio_stream_processed(&self.stream) {
+			self.synth.render(&mut self.float_buffer);
+			for (out, s) in self.sample_buffer.iter_mut().zip(self.float_buffer.iter()) {
+				*out = to_i16(*s);
+			}
+
+			// The safe wrapper passes the length in bytes, but raylib wants it in samples
+			unsafe {
+				ffi::UpdateAudioStream(*self.stream.as_ref(), self.sample_buffer.as_ptr() as *const c_void, self.sample_buffer.len() as i32);
+			}
+		}
+	}
+
+	pub fn set_paused(&mut self, paused: bool) {
+		if paused {
+			self.device.pause_audio_stream(&mut self.stream);
+		} else {
+			self.device.resume_audio_stream(&mut self.stream);
+		}
+	}
+
+	#[inline]
+	pub fn set_volume(&mut self, volume: f32) {   // 0 -> 1
+		self.device.set_master_volume(volume);
+	}
+
+	#[inline]
+	pub fn play(&mut self, shot: OneShot) {
+		self.synth.trigger(shot);
+	}
+}
+
+fn get_car_params(car: &Car) -> SynthParams {
+	// Pretend gearbox: revs climb through each gear then drop at the change
+	let speed = car.vel_mag;
+	let mut gear_start = 0.0;
+	let mut gear_frac = 1.0;
+	for top in GEAR_TOP_SPEEDS.iter() {
+		if speed < *top {
+			gear_frac = (speed - gear_start)/(top - gear_start);
+			break;
+		}
+		gear_start = *top;
+	}
+
+	let throttle = car.throttle.abs();
+	let wheelspin = throttle * car.perp.abs() * WHEELSPIN_RPM;
+	let rpm = (IDLE_RPM + (REDLINE_RPM - IDLE_RPM) * gear_frac * 0.7 + wheelspin).min(REDLINE_RPM);
+
+	let slide = ((car.perp.abs() - SQUEAL_MIN_PERP)/(1.0 - SQUEAL_MIN_PERP)).max(0.0);
+	let squeal = slide * (speed/SQUEAL_FULL_SPEED).min(1.0);
+
+	SynthParams {
+		rpm,
+		throttle,
+		squeal,
+		squeal_pitch: 0.8 + 0.4 * car.perp.abs() + speed/2000.0,
+	}
+}
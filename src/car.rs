@@ -1,15 +1,19 @@
-use raylib::{consts, math::{Vector2, Rectangle}, drawing::{RaylibDraw, RaylibDrawHandle}, RaylibHandle, RaylibThread, color::Color, texture::Texture2D};
+use raylib::{consts, math::{Vector2, Rectangle}, drawing::RaylibDraw, RaylibHandle, RaylibThread, texture::Texture2D};
 
 use crate::{
 	misc,
+	car_spec::CarSpec,
 	drift_trail,
 	dust_system,
-	CHARCOAL,
+	emitter::EmitterConfig,
+	level::Level,
+	input::Input,
+	surface::SurfaceType,
+	tyre::Tyre,
+	weather::{Weather, AQUAPLANE_GRIP, SPRAY_FULL_SPEED},
 	traits::*,
 };
 
-const CAR_ACC: f32 = 500.0;
-
 pub const CAR_W: f32 = 36.0;
 pub const CAR_H: f32 = 56.0;
 pub const HALF_CAR_W: f32 = CAR_W/2.0;
@@ -19,75 +23,128 @@ const WHEEL_X_OFF: f32 = 5.0;
 const BACK_WHEEL_Y_OFF: f32 = 15.0;
 const FRONT_WHEEL_Y_OFF: f32 = 8.0;
 
-const CAR_TURN_SPD: f32 = 7.0 * consts::PI as f32;
 const CAR_RESISTANCE: f32 = 2.718;
 const HALF_PI: f32 = (consts::PI/2.0) as f32;
 const TRAIL_DURATION: f64 = 2.0; // In seconds
 const TRAIL_PLACEMENT_INTERVAL: f32 = 0.02; //0.007;  // Place a trail every x seconds.
 pub const DRIFT_TRAIL_WIDTH: f32 = 3.5;
-
+const CAR_RESTITUTION: f32 = 0.4;   // Fraction of the closing speed cars bounce apart with
+const DUST_EMITTER_PATH: &str = "emitters/dust.emitter";
+const SPRAY_EMITTER_PATH: &str = "emitters/spray.emitter";
+const SMOKE_EMITTER_PATH: &str = "emitters/smoke.emitter";
+const WHEELSPIN_SLIP: f32 = 300.0;  // Slip of the rear tyres at full throttle while fully sideways
+const TRACTION_CONTROL_CUT: f32 = 0.6;  // Fraction of the throttle taken away when fully sideways
+
+
+// Everything that moves the car, without the particles and trails. For rolling back online games.
+#[derive(Clone, Copy)]
+pub struct Snapshot {
+	pos: Vector2,
+	vel: Vector2,
+	vel_mag: f32,
+	throttle: f32,
+	angle: f32,
+	angular_vel: f32,
+	angular_acc: f32,
+	lateral_acc: Vector2,
+	perp: f32,
+	drifting: bool,
+	tyres: [Tyre; 4],
+}
 
 pub struct Car {
 	pub pos: Vector2,
-	vel: Vector2,
+	pub vel: Vector2,
 	pub vel_mag: f32,
 	pub throttle: f32,
 	pub angle: f32,
 	pub angular_vel: f32,
 	angular_acc: f32,
+	pub lateral_acc: Vector2,   // Sideways grip from apply_resistance on the last update, per second
 	pub perp: f32,   // How perpendicular the car is to it's velocity
 	pub drifting: bool,
-	texture: Texture2D,
+	pub traction_control: bool,
+	pub effects: bool,   // Particles and trails, off while re-simulating ticks that were already shown
+	pub spec: CarSpec,
+	texture: Option<Texture2D>,   // Not loaded when running without a window
 
 	trail_nodes: Vec<drift_trail::DriftTrailSet>,
 	front_dust_sys: dust_system::CarDustSystems,
 	back_dust_sys: dust_system::CarDustSystems,
+	spray_sys: dust_system::CarDustSystems,
+	smoke_sys: dust_system::CarDustSystems,
+	pub tyres: [Tyre; 4],   // Same order as the wheel positions
 	trail_timer: f32,
+	trail_duration: f64,
 }
 
 impl Car {
-	pub fn new(rl: &mut RaylibHandle, rl_thread: &RaylibThread, p: Vector2) -> Car {
+	// The seed and budget (per wheel, for each effect) are for the particle effects. Call load_texture before drawing.
+	pub fn new(spec: &CarSpec, p: Vector2, angle: f32, seed: u64, particle_budget: usize) -> Car {
+		let dust_config = EmitterConfig::load(DUST_EMITTER_PATH).expect("Couldn't load dust emitter.");
+		let spray_config = EmitterConfig::load(SPRAY_EMITTER_PATH).expect("Couldn't load spray emitter.");
+		let smoke_config = EmitterConfig::load(SMOKE_EMITTER_PATH).expect("Couldn't load smoke emitter.");
+
 		Car {
 			pos: p,
 			vel: Vector2::zero(),
 			vel_mag: 0.0,
 			throttle: 0.0,
-			angle: consts::PI as f32,
+			angle,
 			angular_vel: 0.0,
 			angular_acc: 0.0,
+			lateral_acc: Vector2::zero(),
 			perp: 0.0,
 			drifting: false,
-			texture: rl.load_texture(rl_thread, "textures/car/car_body.png").expect("Could't load car texture."),
+			traction_control: false,
+			effects: true,
+			spec: spec.clone(),
+			texture: None,
 
 			trail_nodes: vec![],
-			front_dust_sys: dust_system::CarDustSystems::default(),
-			back_dust_sys: dust_system::CarDustSystems::default(),
+			front_dust_sys: dust_system::CarDustSystems::new(&dust_config, 2, particle_budget, seed),
+			back_dust_sys: dust_system::CarDustSystems::new(&dust_config, 2, particle_budget, seed.wrapping_add(2)),
+			spray_sys: dust_system::CarDustSystems::new(&spray_config, 2, particle_budget, seed.wrapping_add(4)),
+			smoke_sys: dust_system::CarDustSystems::new(&smoke_config, 4, particle_budget, seed.wrapping_add(6)),
+			tyres: [Tyre::default(); 4],
 			trail_timer: 0.0,
+			trail_duration: TRAIL_DURATION,
 		}
 	}
 
-	pub fn reset(&mut self) {
-		self.pos = Vector2 { x: 100.0, y: 600.0 };
+	pub fn load_texture(&mut self, rl: &mut RaylibHandle, rl_thread: &RaylibThread) {
+		self.texture = Some(rl.load_texture(rl_thread, &self.spec.texture).expect("Could't load car texture."));
+	}
+
+	pub fn reset(&mut self, pos: Vector2, angle: f32) {
+		self.pos = pos;
 		self.vel = Vector2::zero();
+		self.vel_mag = 0.0;
 		self.throttle = 0.0;
-		self.angle = consts::PI as f32;
+		self.angle = angle;
+		self.perp = 0.0;
+		self.drifting = false;
+		self.trail_nodes.clear();
 		self.angular_vel = 0.0;
 		self.angular_acc = 0.0;
+		self.lateral_acc = Vector2::zero();
+		self.tyres = [Tyre::default(); 4];
 	}
 
-	pub fn update(&mut self, rl: &RaylibHandle, dt: f32) {
-		let curr_time = rl.get_time();
+	pub fn update(&mut self, input: &Input, dt: f32, curr_time: f64, level: &Level) {
 		self.trail_timer += dt;
+		self.trail_duration = TRAIL_DURATION * level.weather.trail_duration_multiplier();
 
-		let up_key = rl.is_key_down(consts::KeyboardKey::KEY_W);
-		let down_key = rl.is_key_down(consts::KeyboardKey::KEY_S);
-		if up_key || down_key {
-			if up_key {
+		if input.accelerate || input.brake {
+			if input.accelerate {
 				self.throttle = 1.0;
 			}
-			if down_key {
+			if input.brake {
 				self.throttle = -1.0;
 			}
+			if self.traction_control {
+				self.throttle *= 1.0 - TRACTION_CONTROL_CUT * self.perp.abs().min(1.0);
+			}
 
 			self.accelerate(dt, self.throttle);
 		} else {
@@ -111,63 +168,153 @@ impl Car {
 
 		self.vel_mag = self.vel.length();
 		self.angular_acc = 0.0;
+		self.lateral_acc = Vector2::zero();
 
 		self.kill_dead_trail_nodes(curr_time);
 
-		self.front_dust_sys.update(dt, curr_time);
-		self.back_dust_sys.update(dt, curr_time);
+		if self.effects {
+			self.front_dust_sys.update(dt, curr_time, level.wind);
+			self.back_dust_sys.update(dt, curr_time, level.wind);
+			self.spray_sys.update(dt, curr_time, level.wind);
+			self.smoke_sys.update(dt, curr_time, level.wind);
+		}
+
+		let mut wheel_slip = [0.0; 4];
 
 		if self.vel_mag > 0.0 {
 			self.perp = self.get_perp_value();
 			self.drifting = self.perp.abs() > 0.35 && self.vel_mag > 10.0;
 
-			self.apply_resistance(dt);
+			let wheel_positions: [Vector2; 4] = self.get_wheel_positions();
+			let wheel_surfaces: [SurfaceType; 4] = [
+				level.surface_at(wheel_positions[0]),
+				level.surface_at(wheel_positions[1]),
+				level.surface_at(wheel_positions[2]),
+				level.surface_at(wheel_positions[3]),
+			];
 
-			if rl.is_key_down(consts::KeyboardKey::KEY_A) {
+			let wheel_vels: [Vector2; 4] = self.get_wheel_velocities(&wheel_positions);
+			wheel_slip = self.get_wheel_slip(&wheel_vels);
+
+			let (grip, rolling_resistance) = self.get_traction(level, &wheel_positions, &wheel_surfaces);
+			self.apply_resistance(dt, grip, rolling_resistance);
+
+			if input.steer_left {
 				self.angular_acc = (self.vel_mag/200.0).min(1.0);
 				self.turn(dt, self.angular_acc);
 			}
-			if rl.is_key_down(consts::KeyboardKey::KEY_D) {
+			if input.steer_right {
 				self.angular_acc = -(self.vel_mag/200.0).min(1.0);
 				self.turn(dt, self.angular_acc);
 			}
 
-			if self.drifting {
-				let wheel_positions: [Vector2; 4] = self.get_wheel_positions();
-				
-				let dust_perp_mult = self.perp.abs().powi(2);
-				let dust_amount = dust_perp_mult * self.throttle.abs();
-				self.front_dust_sys.emit(dt, curr_time, self.angle, (dust_amount/3.0) * self.angular_acc.abs(), wheel_positions[0], wheel_positions[1]);
-				self.back_dust_sys.emit(dt, curr_time, self.angle, dust_amount, wheel_positions[2], wheel_positions[3]);
+			let dust_perp_mult = self.perp.abs().powi(2);
+			let dust_amount = dust_perp_mult * self.throttle.abs();
+			let emit_angle = self.angle + consts::PI as f32;  // Out of the back of the car
+			if level.weather == Weather::Rain && self.effects {
+				// Rear wheels throw up spray whenever the car is moving, more so when sliding
+				let spray_amount = (self.vel_mag/SPRAY_FULL_SPEED).min(1.0) * (0.3 + dust_amount);
+				self.spray_sys.emit(dt, curr_time, emit_angle, &[spray_amount; 2], &wheel_positions[2..], &wheel_vels[2..]);
+			}
+
+			if self.drifting && self.effects {
+				if level.weather == Weather::Dry {
+					self.front_dust_sys.set_surfaces(&wheel_surfaces[..2]);
+					self.back_dust_sys.set_surfaces(&wheel_surfaces[2..]);
+
+					self.front_dust_sys.emit(dt, curr_time, emit_angle, &[(dust_amount/3.0) * self.angular_acc.abs(); 2], &wheel_positions[..2], &wheel_vels[..2]);
+					self.back_dust_sys.emit(dt, curr_time, emit_angle, &[dust_amount; 2], &wheel_positions[2..], &wheel_vels[2..]);
+				}
+
+				let smoke_amounts: [f32; 4] = [
+					self.tyres[0].get_smoke_amount(),
+					self.tyres[1].get_smoke_amount(),
+					self.tyres[2].get_smoke_amount(),
+					self.tyres[3].get_smoke_amount(),
+				];
+				self.smoke_sys.emit(dt, curr_time, emit_angle, &smoke_amounts, &wheel_positions, &wheel_vels);
 
-				self.place_trails(curr_time, &wheel_positions);
+				self.place_trails(curr_time, &wheel_positions, &wheel_surfaces);
 			}
 
 			self.pos = self.pos + self.vel.scale_by(dt);
 		}
 
 		self.angle += self.angular_vel * dt;
+
+		for (t, slip) in self.tyres.iter_mut().zip(wheel_slip.iter()) {
+			t.update(dt, *slip);
+		}
+	}
+
+	pub fn save(&self) -> Snapshot {
+		Snapshot {
+			pos: self.pos,
+			vel: self.vel,
+			vel_mag: self.vel_mag,
+			throttle: self.throttle,
+			angle: self.angle,
+			angular_vel: self.angular_vel,
+			angular_acc: self.angular_acc,
+			lateral_acc: self.lateral_acc,
+			perp: self.perp,
+			drifting: self.drifting,
+			tyres: self.tyres,
+		}
+	}
+
+	pub fn restore(&mut self, s: &Snapshot) {
+		self.pos = s.pos;
+		self.vel = s.vel;
+		self.vel_mag = s.vel_mag;
+		self.throttle = s.throttle;
+		self.angle = s.angle;
+		self.angular_vel = s.angular_vel;
+		self.angular_acc = s.angular_acc;
+		self.lateral_acc = s.lateral_acc;
+		self.perp = s.perp;
+		self.drifting = s.drifting;
+		self.tyres = s.tyres;
 	}
 
 	#[inline]
 	fn accelerate(&mut self, dt: f32, power: f32) {
-		let dv = dt * power * CAR_ACC;
+		let dv = dt * power * self.spec.acceleration;
 		self.vel += misc::get_components(dv, self.angle);
 	}
 
 	#[inline]
 	fn turn(&mut self, dt: f32, amount: f32) {
-		self.angular_vel += dt * amount * CAR_TURN_SPD;
+		self.angular_vel += dt * amount * self.spec.turn_speed;
 	}
 
-	fn apply_resistance(&mut self, dt: f32) {
+	fn get_traction(&self, level: &Level, wheel_positions: &[Vector2; 4], wheel_surfaces: &[SurfaceType; 4]) -> (f32, f32) {
+		// Average the surfaces under each wheel, so the car can straddle two surfaces
+		let weather_grip = level.weather.grip_multiplier(self.vel_mag);
+		let (mut grip, mut rolling_resistance) = (0.0, 0.0);
+		for ((pos, surface), tyre) in wheel_positions.iter().zip(wheel_surfaces.iter()).zip(self.tyres.iter()) {
+			let props = surface.properties();
+			let mut wheel_grip = props.grip * weather_grip * tyre.get_grip();
+			if level.is_aquaplaning(*pos, self.vel_mag) {
+				wheel_grip *= AQUAPLANE_GRIP;
+			}
+
+			grip += wheel_grip/4.0;
+			rolling_resistance += props.rolling_resistance/4.0;
+		}
+		(grip, rolling_resistance)
+	}
+
+	fn apply_resistance(&mut self, dt: f32, grip: f32, rolling_resistance: f32) {
 		self.angular_vel *= (100.0 as f32).powf(-dt * (2.0 - self.perp.abs()));
 
-		let d_hor_v = -self.perp * dt * 500.0;
+		let d_hor_v = -self.perp * dt * self.spec.lateral_grip * grip;
 		let ang = self.angle + HALF_PI; // Angle perpendicular to car to apply resistive vel on
 
-		self.vel += Vector2 { x: d_hor_v * ang.sin(), y: d_hor_v * ang.cos() };
-		self.vel.scale(CAR_RESISTANCE.powf(-dt));   // All deceleration
+		self.lateral_acc = Vector2 { x: d_hor_v * ang.sin(), y: d_hor_v * ang.cos() };
+		self.vel += self.lateral_acc;
+		self.lateral_acc.scale(1.0/dt);
+		self.vel.scale(CAR_RESISTANCE.powf(-dt * rolling_resistance));   // All deceleration
 	}
 
 	fn get_perp_value(&self) -> f32 {
@@ -175,42 +322,79 @@ impl Car {
 		(self.vel/self.vel_mag).dot(Vector2 { x: ang.sin(), y: ang.cos() })
 	}
 
-	fn get_wheel_positions(&self) -> [Vector2; 4] {
+	pub fn get_wheel_positions(&self) -> [Vector2; 4] {
 		[misc::rotate_vec(Vector2 { x: -HALF_CAR_W + WHEEL_X_OFF, y: HALF_CAR_H - COM_OFF - FRONT_WHEEL_Y_OFF }, -self.angle) + self.pos, // Left front
 		 misc::rotate_vec(Vector2 { x: HALF_CAR_W - WHEEL_X_OFF, y: HALF_CAR_H - COM_OFF - FRONT_WHEEL_Y_OFF }, -self.angle) + self.pos,  // Right front
 		 misc::rotate_vec(Vector2 { x: -HALF_CAR_W + WHEEL_X_OFF, y: -HALF_CAR_H - COM_OFF + BACK_WHEEL_Y_OFF }, -self.angle) + self.pos,  // Left back
 		 misc::rotate_vec(Vector2 { x: HALF_CAR_W - WHEEL_X_OFF, y: -HALF_CAR_H - COM_OFF + BACK_WHEEL_Y_OFF }, -self.angle) + self.pos]   // Right back
 	}
 
-	pub fn draw_trails(&self, d: &mut RaylibDrawHandle, time: f64) {
+	#[inline]
+	pub fn get_rear_pos(&self) -> Vector2 {   // Middle of the rear axle
+		let wheels = self.get_wheel_positions();
+		(wheels[2] + wheels[3])/2.0
+	}
+
+	fn get_wheel_velocities(&self, wheel_positions: &[Vector2; 4]) -> [Vector2; 4] {
+		// Velocity of the car plus the velocity from the car spinning about its centre of mass
+		let wheel_vel = |p: Vector2| {
+			let r = p - self.pos;
+			self.vel + Vector2 { x: r.y, y: -r.x }.scale_by(self.angular_vel)
+		};
+		[wheel_vel(wheel_positions[0]), wheel_vel(wheel_positions[1]), wheel_vel(wheel_positions[2]), wheel_vel(wheel_positions[3])]
+	}
+
+	fn get_wheel_slip(&self, wheel_vels: &[Vector2; 4]) -> [f32; 4] {
+		// Sideways sliding of each wheel, plus wheelspin on the driven rear wheels
+		let ang = self.angle + HALF_PI;
+		let side = Vector2 { x: ang.sin(), y: ang.cos() };
+		let wheelspin = self.throttle.abs() * self.perp.abs() * WHEELSPIN_SLIP;
+
+		let mut slip = [0.0; 4];
+		for (i, (s, v)) in slip.iter_mut().zip(wheel_vels.iter()).enumerate() {
+			*s = v.dot(side).abs();
+			if i >= 2 {
+				*s += wheelspin;
+			}
+		}
+		slip
+	}
+
+	pub fn draw_trails<D: RaylibDraw>(&self, d: &mut D, time: f64) {
 		for (i, t) in self.trail_nodes.iter().enumerate() {
 			if i > 0 && self.trail_nodes[i-1].left_front.distance_to(t.left_front) < 10.0 {
-				let mut col = CHARCOAL;
-				col.a = ((3.0 * ((t.time_created - time)/TRAIL_DURATION) + 4.0).log2() * 255.0).min(255.0) as u8;  // Alpha value for this line
-
-				d.draw_line_ex(self.trail_nodes[i-1].left_front, t.left_front, DRIFT_TRAIL_WIDTH, col);  // Left front
-				d.draw_line_ex(self.trail_nodes[i-1].right_front, t.right_front, DRIFT_TRAIL_WIDTH, col);  // Right front
-				d.draw_line_ex(self.trail_nodes[i-1].left_back, t.left_back, DRIFT_TRAIL_WIDTH, col);  // Left back
-				d.draw_line_ex(self.trail_nodes[i-1].right_back, t.right_back, DRIFT_TRAIL_WIDTH, col);  // Right back
+				let fade = (3.0 * ((t.time_created - time)/self.trail_duration) + 4.0).log2().min(1.0) as f32;  // Alpha multiplier for this line
+				let mut draw_mark = |from: Vector2, to: Vector2, surface: SurfaceType| {
+					let props = surface.properties();
+					let mut col = props.trail_colour;
+					col.a = (col.a as f32 * fade) as u8;
+					d.draw_line_ex(from, to, props.trail_width, col);
+				};
+
+				draw_mark(self.trail_nodes[i-1].left_front, t.left_front, t.surfaces[0]);  // Left front
+				draw_mark(self.trail_nodes[i-1].right_front, t.right_front, t.surfaces[1]);  // Right front
+				draw_mark(self.trail_nodes[i-1].left_back, t.left_back, t.surfaces[2]);  // Left back
+				draw_mark(self.trail_nodes[i-1].right_back, t.right_back, t.surfaces[3]);  // Right back
 			}
 		}
 	}
 
-	fn place_trails(&mut self, time: f64, wheel_positions: &[Vector2; 4]) {
+	fn place_trails(&mut self, time: f64, wheel_positions: &[Vector2; 4], wheel_surfaces: &[SurfaceType; 4]) {
 		if self.trail_timer >= TRAIL_PLACEMENT_INTERVAL {
-			self.trail_nodes.push(drift_trail::DriftTrailSet::new(time, wheel_positions));
+			self.trail_nodes.push(drift_trail::DriftTrailSet::new(time, wheel_positions, wheel_surfaces));
 			self.trail_timer -= TRAIL_PLACEMENT_INTERVAL;
 		}
 	}
 
 	#[inline]
 	fn kill_dead_trail_nodes(&mut self, time: f64) {
-		self.trail_nodes.retain(|i|time - i.time_created <= TRAIL_DURATION);
+		let duration = self.trail_duration;
+		self.trail_nodes.retain(|i|time - i.time_created <= duration);
 	}
 
 	#[inline]
 	pub fn get_particle_count(&self) -> usize {
-		self.front_dust_sys.get_particle_count() + self.back_dust_sys.get_particle_count()
+		self.front_dust_sys.get_particle_count() + self.back_dust_sys.get_particle_count() + self.spray_sys.get_particle_count() + self.smoke_sys.get_particle_count()
 	}
 
 	#[inline]
@@ -218,12 +402,18 @@ impl Car {
 		self.trail_nodes.len()
 	}
 
-	pub fn draw(&self, rl: &mut RaylibDrawHandle) {
+	pub fn draw<D: RaylibDraw>(&self, rl: &mut D) {
 		self.front_dust_sys.draw(rl);
 		self.back_dust_sys.draw(rl);
+		self.spray_sys.draw(rl);
+		self.smoke_sys.draw(rl);
 
+		let texture = match &self.texture {
+			Some(t) => t,
+			None => return,
+		};
 		rl.draw_texture_pro(
-			&self.texture,
+			texture,
             Rectangle {
 				x: 0.0,
 				y: 0.0,
@@ -241,8 +431,32 @@ impl Car {
 				y: HALF_CAR_H + COM_OFF
 			},
 			-self.angle * consts::RAD2DEG as f32,
-			Color::WHITE
+			self.spec.tint
 		);
 	}
 }
 
+
+// Cars touch as circles, the same as against pillars. Pushes them apart and bounces them off each
+// other as equal masses, losing some speed to the crash. Returns whether they were touching.
+pub fn collide(a: &mut Car, b: &mut Car) -> bool {
+	let offset = b.pos - a.pos;
+	let dist = offset.length();
+	if dist >= CAR_W || dist == 0.0 {
+		return false;
+	}
+	let normal = offset/dist;
+	let push = normal.scale_by((CAR_W - dist)/2.0);
+	a.pos -= push;
+	b.pos += push;
+
+	let closing = (a.vel - b.vel).dot(normal);
+	if closing > 0.0 {
+		let impulse = normal.scale_by(closing * (1.0 + CAR_RESTITUTION)/2.0);
+		a.vel -= impulse;
+		b.vel += impulse;
+		a.vel_mag = a.vel.length();
+		b.vel_mag = b.vel.length();
+	}
+	true
+}
@@ -0,0 +1,68 @@
+use std::fs;
+
+use raylib::{color::Color, consts};
+
+use crate::keyvalue;
+
+pub const CAR_DIR: &str = "cars";
+const CAR_EXTENSION: &str = "car";
+
+// How a car looks and handles. Loaded from `key = value` files, see the `cars` folder.
+#[derive(Clone, Debug)]
+pub struct CarSpec {
+	pub name: String,
+	pub texture: String,     // Path to the body texture
+	pub tint: Color,
+	pub acceleration: f32,   // Pixels per second per second at full throttle
+	pub turn_speed: f32,     // Radians per second per second at full lock
+	pub lateral_grip: f32,   // Sideways resistance on a surface with a grip of 1
+}
+
+impl Default for CarSpec {
+	fn default() -> CarSpec {
+		CarSpec {
+			name: "Hatch".to_string(),
+			texture: "textures/car/car_body.png".to_string(),
+			tint: Color::WHITE,
+			acceleration: 500.0,
+			turn_speed: 7.0 * consts::PI as f32,
+			lateral_grip: 500.0,
+		}
+	}
+}
+
+impl CarSpec {
+	pub fn load(path: &str) -> Result<CarSpec, String> {
+		let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+		CarSpec::parse(&text).map_err(|e| format!("{}: {}", path, e))
+	}
+
+	pub fn parse(text: &str) -> Result<CarSpec, String> {
+		let mut spec = CarSpec::default();
+
+		for entry in keyvalue::entries(text) {
+			let e = entry?;
+			match e.key {
+				"name" => spec.name = e.value.to_string(),
+				"texture" => spec.texture = e.value.to_string(),
+				"tint" => spec.tint = e.colour()?,
+				"acceleration" => spec.acceleration = e.single()?,
+				"turn_speed" => spec.turn_speed = e.single()?.to_radians(),   // Degrees in the file
+				"lateral_grip" => spec.lateral_grip = e.single()?,
+				_ => return Err(e.err(&format!("unknown key `{}`", e.key))),
+			}
+		}
+
+		if spec.acceleration <= 0.0 || spec.turn_speed <= 0.0 || spec.lateral_grip <= 0.0 {
+			return Err("acceleration, turn_speed and lateral_grip must be positive".to_string());
+		}
+		Ok(spec)
+	}
+}
+
+// Loads every car in the folder. Broken files are reported and skipped.
+pub fn load_all(dir: &str) -> Vec<CarSpec> {
+	keyvalue::list_files(dir, CAR_EXTENSION).iter()
+		.filter_map(|p| CarSpec::load(&p.to_string_lossy()).map_err(|e| println!("{}", e)).ok())
+		.collect()
+}
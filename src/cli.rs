@@ -0,0 +1,127 @@
+// Command line flags. Everything is optional, with no flags the game starts at the title screen.
+
+pub const USAGE: &str = "\
+Usage: drift [options]
+
+  --level <file>          Start straight into a level file
+  --car <file>            Drive a car spec file
+  --mode <mode>           free_drift, score_attack, gymkhana, time_trial or tandem
+  --window <WxH>          Window size, e.g. 1280x720
+  --fps <n>               Frame rate cap, 0 for uncapped
+  --seed <n>              Seed for particle effects
+  --particle-budget <n>   Most particles per wheel for each effect, instead of the setting
+  --replay <file>         Watch a recorded run
+  --ai                    Let the computer drive
+  --headless              Run without a window, printing the results
+  --ticks <n>             Ticks to simulate when headless (defaults to the length of the replay), or per --env episode
+  --env                   Serve a training environment over stdin and stdout
+  --env-listen <addr>     Serve a training environment on a TCP address, e.g. 127.0.0.1:5555
+  --env-ticks <n>         Ticks per environment step
+  --host [addr]           Host an online game, on 0.0.0.0:7777 unless given. Uses --level, --car and --mode.
+  --join <addr>           Join an online game, e.g. 127.0.0.1:7777
+  --net-latency <ms>      Delay everything sent online, for testing
+  --net-loss <percent>    Drop some of everything sent online, for testing
+  --export-trails <file>  Write the run's trails to an SVG when it ends
+  --telemetry <file>      Write the car's state every tick to a CSV when the run ends
+  --internal-res <WxH>    Resolution the world is rendered at
+  --ui-scale <x>          Size of the HUD and menus
+  --render-audio [file]   Write a demo of the engine sound to a WAV and exit
+  --help                  Show this
+";
+
+const DEF_AUDIO_PATH: &str = "audio_demo.wav";
+
+#[derive(Default, Debug)]
+pub struct Options {
+	pub level: Option<String>,
+	pub car: Option<String>,
+	pub mode: Option<String>,
+	pub window_size: Option<(i32, i32)>,
+	pub fps: Option<u32>,
+	pub seed: Option<u64>,
+	pub particle_budget: Option<usize>,
+	pub replay: Option<String>,
+	pub ai: bool,
+	pub headless: bool,
+	pub ticks: Option<u64>,
+	pub env: bool,
+	pub env_listen: Option<String>,
+	pub env_ticks: Option<u32>,
+	pub host: Option<String>,
+	pub join: Option<String>,
+	pub net_latency: Option<u32>,
+	pub net_loss: Option<f32>,
+	pub export_trails: Option<String>,
+	pub telemetry: Option<String>,
+	pub internal_res: Option<(u32, u32)>,
+	pub ui_scale: Option<f32>,
+	pub render_audio: Option<String>,
+	pub help: bool,
+}
+
+// Arguments without the program name
+pub fn parse(args: &[String]) -> Result<Options, String> {
+	let mut opts = Options::default();
+	let mut args = args.iter().peekable();
+
+	while let Some(arg) = args.next() {
+		let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+		match arg.as_str() {
+			"--level" => opts.level = Some(value()?.clone()),
+			"--car" => opts.car = Some(value()?.clone()),
+			"--mode" => opts.mode = Some(value()?.clone()),
+			"--window" => opts.window_size = Some(parse_resolution(value()?)
+				.map(|(w, h)| (w as i32, h as i32))
+				.ok_or("--window must be like 1280x720")?),
+			"--fps" => opts.fps = Some(value()?.parse().map_err(|_| "--fps must be a whole number")?),
+			"--seed" => opts.seed = Some(value()?.parse().map_err(|_| "--seed must be a whole number")?),
+			"--particle-budget" => opts.particle_budget = Some(value()?.parse().map_err(|_| "--particle-budget must be a whole number")?),
+			"--replay" => opts.replay = Some(value()?.clone()),
+			"--ai" => opts.ai = true,
+			"--headless" => opts.headless = true,
+			"--ticks" => opts.ticks = Some(value()?.parse().map_err(|_| "--ticks must be a whole number")?),
+			"--env" => opts.env = true,
+			"--env-listen" => opts.env_listen = Some(value()?.clone()),
+			"--env-ticks" => opts.env_ticks = Some(value()?.parse().map_err(|_| "--env-ticks must be a whole number")?),
+			"--host" => {   // The address is optional
+				let addr = args.next_if(|a| !a.starts_with("--")).map_or(format!("0.0.0.0:{}", crate::net::DEF_PORT), |a| a.clone());
+				opts.host = Some(addr);
+			},
+			"--join" => opts.join = Some(value()?.clone()),
+			"--net-latency" => opts.net_latency = Some(value()?.parse().map_err(|_| "--net-latency must be a whole number")?),
+			"--net-loss" => opts.net_loss = Some(value()?.parse().ok().filter(|p| (0.0..=100.0).contains(p)).ok_or("--net-loss must be 0 to 100")?),
+			"--export-trails" => opts.export_trails = Some(value()?.clone()),
+			"--telemetry" => opts.telemetry = Some(value()?.clone()),
+			"--internal-res" => opts.internal_res = Some(parse_resolution(value()?).ok_or("--internal-res must be like 2000x1600")?),
+			"--ui-scale" => opts.ui_scale = Some(value()?.parse().map_err(|_| "--ui-scale must be a number")?),
+			"--render-audio" => {   // The path is optional
+				let path = args.next_if(|a| !a.starts_with("--")).map_or(DEF_AUDIO_PATH.to_string(), |p| p.clone());
+				opts.render_audio = Some(path);
+			},
+			"--help" | "-h" => opts.help = true,
+			_ => return Err(format!("unknown option `{}`", arg)),
+		}
+	}
+
+	if opts.headless && opts.ticks.is_none() && opts.replay.is_none() {
+		return Err("--headless needs --ticks or --replay".to_string());
+	}
+	if opts.env && opts.env_listen.is_some() {
+		return Err("--env and --env-listen can't be used together".to_string());
+	}
+	if opts.host.is_some() && opts.join.is_some() {
+		return Err("--host and --join can't be used together".to_string());
+	}
+	if (opts.host.is_some() || opts.join.is_some()) && (opts.headless || opts.env || opts.env_listen.is_some() || opts.replay.is_some()) {
+		return Err("online games need a window, without --headless, --env or --replay".to_string());
+	}
+	Ok(opts)
+}
+
+pub fn parse_resolution(text: &str) -> Option<(u32, u32)> {   // e.g. "1920x1080"
+	let (w, h) = text.split_once('x')?;
+	match (w.parse(), h.parse()) {
+		(Ok(w), Ok(h)) if w > 0 && h > 0 => Some((w, h)),
+		_ => None,
+	}
+}
@@ -0,0 +1,72 @@
+const COMBO_GRACE: f32 = 1.0;          // Seconds without scoring before the combo is banked
+const MULTIPLIER_INTERVAL: f32 = 2.5;  // Seconds of continuous scoring for each multiplier increase
+const MAX_MULTIPLIER: u32 = 5;
+
+pub enum ComboEvent {
+	MultiplierUp(u32),
+	Banked(u32, u32),   // Points and the multiplier they earned
+}
+
+#[derive(Clone)]
+pub struct Combo {    // Points built up from one continuous drift, multiplied and banked when the drift ends
+	pub points: u32,
+	pub multiplier: u32,
+	time_scoring: f32,
+	time_since_scored: f32,
+}
+
+impl Default for Combo {
+	fn default() -> Combo {
+		Combo {
+			points: 0,
+			multiplier: 1,
+			time_scoring: 0.0,
+			time_since_scored: 0.0,
+		}
+	}
+}
+
+impl Combo {
+	#[inline]
+	pub fn is_active(&self) -> bool {
+		self.points > 0
+	}
+
+	#[inline]
+	pub fn add(&mut self, points: u32) {
+		self.points += points;
+		self.time_since_scored = 0.0;
+	}
+
+	pub fn update(&mut self, dt: f32, scoring: bool) -> Option<ComboEvent> {
+		if !self.is_active() { return None }
+
+		if scoring {
+			self.time_scoring += dt;
+			let multiplier = (1 + (self.time_scoring/MULTIPLIER_INTERVAL) as u32).min(MAX_MULTIPLIER);
+			if multiplier > self.multiplier {
+				self.multiplier = multiplier;
+				return Some(ComboEvent::MultiplierUp(multiplier));
+			}
+		} else {
+			self.time_since_scored += dt;
+			if self.time_since_scored >= COMBO_GRACE {
+				let (points, multiplier) = self.bank();
+				return Some(ComboEvent::Banked(points, multiplier));
+			}
+		}
+		None
+	}
+
+	// Ends the combo, returning its points and multiplier
+	pub fn bank(&mut self) -> (u32, u32) {
+		let banked = (self.points, self.multiplier);
+		*self = Combo::default();
+		banked
+	}
+
+	#[inline]
+	pub fn get_grace_left(&self) -> f32 {   // 1 while scoring, falls to 0 as the combo is about to be banked
+		1.0 - (self.time_since_scored/COMBO_GRACE).min(1.0)
+	}
+}
@@ -0,0 +1,255 @@
+// Gymkhana courses: an ordered list of pillars to orbit, each in a set direction a set number of times.
+
+use raylib::{math::Vector2, drawing::RaylibDraw, color::Color};
+
+use crate::{
+	misc,
+	pillar::Pillar,
+	POINT_DIST_THRESHOLD, RED_1, RED_2, TWO_PI,
+};
+
+const SKIP_PENALTY: f32 = 10.0;             // Seconds added for each step missed out
+const WRONG_DIRECTION_PENALTY: f32 = 5.0;   // Seconds added for a full orbit the wrong way round the target
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Direction {
+	Clockwise,
+	Anticlockwise,
+}
+
+impl Direction {
+	pub fn from_name(name: &str) -> Option<Direction> {   // As written in level files
+		match name {
+			"clockwise" | "cw" => Some(Direction::Clockwise),
+			"anticlockwise" | "acw" => Some(Direction::Anticlockwise),
+			_ => None,
+		}
+	}
+
+	#[inline]
+	pub fn sign(&self) -> f32 {   // Screen y points down, so angles increase clockwise
+		match self {
+			Direction::Clockwise => 1.0,
+			Direction::Anticlockwise => -1.0,
+		}
+	}
+
+	#[inline]
+	pub fn name(&self) -> &'static str {
+		match self {
+			Direction::Clockwise => "clockwise",
+			Direction::Anticlockwise => "anticlockwise",
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct CourseStep {
+	pub pillar: usize,   // Index into the level's pillars
+	pub direction: Direction,
+	pub rotations: f32,
+}
+
+pub enum CourseEvent {
+	StepDone,
+	Skipped(u32),   // Number of steps missed out
+	WrongDirection,
+	Finished,
+}
+
+pub struct CourseRun {    // Progress through a course during one run
+	pub current: usize,   // Index of the step being attempted
+	pub penalty_time: f32,
+	pub skipped: u32,
+	pub wrong_direction: u32,
+	orbits: Vec<Option<(f32, f32)>>,   // Per pillar while the car is in range: its last angle around the pillar, and the signed angle travelled
+}
+
+impl CourseRun {
+	pub fn new(pillar_count: usize) -> CourseRun {
+		CourseRun {
+			current: 0,
+			penalty_time: 0.0,
+			skipped: 0,
+			wrong_direction: 0,
+			orbits: vec![None; pillar_count],
+		}
+	}
+
+	#[inline]
+	pub fn is_finished(&self, course: &[CourseStep]) -> bool {
+		self.current >= course.len()
+	}
+
+	pub fn get_progress(&self, course: &[CourseStep]) -> f32 {   // 0 -> 1 through the current step
+		if self.is_finished(course) { return 1.0 }
+
+		let step = &course[self.current];
+		self.orbits[step.pillar].map_or(0.0, |(_, travelled)| {
+			(travelled * step.direction.sign()/(step.rotations * TWO_PI)).clamp(0.0, 1.0)
+		})
+	}
+
+	pub fn update(&mut self, car_pos: Vector2, pillars: &[Pillar], course: &[CourseStep]) -> Option<CourseEvent> {
+		for (orbit, p) in self.orbits.iter_mut().zip(pillars.iter()) {
+			if p.distance_to(car_pos) > POINT_DIST_THRESHOLD {
+				*orbit = None;
+				continue;
+			}
+			let angle = p.pos.angle_to(car_pos);
+			let (last, travelled) = orbit.unwrap_or((angle, 0.0));
+			*orbit = Some((angle, travelled + misc::wrap_angle(angle - last)));
+		}
+
+		if self.is_finished(course) { return None }
+		let step = &course[self.current];
+
+		if let Some((last, travelled)) = self.orbits[step.pillar] {
+			let signed = travelled * step.direction.sign();
+			if signed >= step.rotations * TWO_PI {
+				self.orbits[step.pillar] = None;   // Starts again if the next step uses the same pillar
+				self.current += 1;
+				return Some(if self.is_finished(course) { CourseEvent::Finished } else { CourseEvent::StepDone });
+			}
+			if signed <= -TWO_PI {
+				self.orbits[step.pillar] = Some((last, 0.0));
+				self.wrong_direction += 1;
+				self.penalty_time += WRONG_DIRECTION_PENALTY;
+				return Some(CourseEvent::WrongDirection);
+			}
+		}
+
+		// A full orbit around a pillar further along the course means the steps before it were skipped
+		for (i, later) in course.iter().enumerate().skip(self.current + 1) {
+			if later.pillar == step.pillar { continue }
+			if let Some((_, travelled)) = self.orbits[later.pillar] {
+				if travelled.abs() >= TWO_PI {
+					let skipped = (i - self.current) as u32;
+					self.skipped += skipped;
+					self.penalty_time += skipped as f32 * SKIP_PENALTY;
+					self.current = i;
+					return Some(CourseEvent::Skipped(skipped));
+				}
+			}
+		}
+		None
+	}
+
+	pub fn get_objective(&self, course: &[CourseStep]) -> String {
+		if self.is_finished(course) { return "Course complete".to_string() }
+
+		let step = &course[self.current];
+		let mut text = format!("Pillar {}/{}: {} x{}", self.current + 1, course.len(), step.direction.name(), step.rotations);
+		if self.penalty_time > 0.0 {
+			text += &format!("  +{:.0}s", self.penalty_time);
+		}
+		text
+	}
+
+	// Rings the current target with its progress, and outlines the one after
+	pub fn draw<D: RaylibDraw>(&self, d: &mut D, pillars: &[Pillar], course: &[CourseStep]) {
+		if self.is_finished(course) { return }
+
+		if let Some(next) = course.get(self.current + 1) {
+			let p = &pillars[next.pillar];
+			d.draw_ring_lines(p.pos, p.radius + 6.0, p.radius + 10.0, 0.0, 360.0, 32, Color::new(38, 38, 38, 80));
+		}
+
+		let step = &course[self.current];
+		let p = &pillars[step.pillar];
+		d.draw_ring(p.pos, p.radius + 6.0, p.radius + 10.0, 0.0, 360.0, 32, Color::new(232, 89, 79, 90));
+
+		// Raylib's angles go anticlockwise from straight down, so clockwise progress sweeps backwards from the top
+		let sweep = self.get_progress(course) * 360.0 * step.direction.sign();
+		let (from, to) = if sweep > 0.0 { (180.0 - sweep, 180.0) } else { (180.0, 180.0 - sweep) };
+		d.draw_ring(p.pos, p.radius + 12.0, p.radius + 17.0, from, to, 48, RED_1);
+
+		let arrow = if step.direction == Direction::Clockwise { ">" } else { "<" };
+		d.draw_text(arrow, (p.pos.x - 4.0) as i32, (p.pos.y - p.radius - 38.0) as i32, 20, RED_2);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const ORBIT_RADIUS: f32 = 100.0;
+	const ANGLE_STEP: f32 = 0.1;   // Radians per tick
+
+	fn pillars() -> Vec<Pillar> {   // Far enough apart that only one is ever in range
+		(0..3).map(|i| Pillar::new(Vector2::new(i as f32 * 1000.0, 0.0), 7.0)).collect()
+	}
+
+	fn step(pillar: usize, direction: Direction) -> CourseStep {
+		CourseStep { pillar, direction, rotations: 1.0 }
+	}
+
+	// Circles a pillar by the given signed number of turns (positive is clockwise), carrying on from wherever the car is around it
+	fn orbit(run: &mut CourseRun, pillars: &[Pillar], course: &[CourseStep], pillar: usize, turns: f32) -> Vec<CourseEvent> {
+		let ticks = (turns.abs() * TWO_PI/ANGLE_STEP).ceil() as usize + 1;
+		let centre = pillars[pillar].pos;
+		let start = run.orbits[pillar].map_or(0.0, |(last, _)| last);
+		(0..ticks).filter_map(|t| {
+			let angle = start + t as f32 * ANGLE_STEP * turns.signum();
+			run.update(centre + Vector2::new(angle.cos(), angle.sin()) * ORBIT_RADIUS, pillars, course)
+		}).collect()
+	}
+
+	#[test]
+	fn steps_complete_in_order() {
+		let pillars = pillars();
+		let course = vec![step(0, Direction::Clockwise), step(0, Direction::Anticlockwise), step(1, Direction::Clockwise)];
+		let mut run = CourseRun::new(pillars.len());
+
+		let events = orbit(&mut run, &pillars, &course, 0, 0.9);
+		assert!(events.is_empty());
+		assert!(run.get_progress(&course) > 0.85 && run.get_progress(&course) < 0.95);
+
+		let events = orbit(&mut run, &pillars, &course, 0, 0.15);
+		assert!(matches!(events[..], [CourseEvent::StepDone]));
+		assert_eq!(run.current, 1);
+
+		// The same pillar again, now the other way round
+		let events = orbit(&mut run, &pillars, &course, 0, -1.05);
+		assert!(matches!(events[..], [CourseEvent::StepDone]));
+		let events = orbit(&mut run, &pillars, &course, 1, 1.05);
+		assert!(matches!(events[..], [CourseEvent::Finished]));
+
+		assert!(run.is_finished(&course));
+		assert_eq!(run.penalty_time, 0.0);
+		assert_eq!((run.skipped, run.wrong_direction), (0, 0));
+		assert!(orbit(&mut run, &pillars, &course, 1, 1.05).is_empty());
+	}
+
+	#[test]
+	fn orbiting_a_later_pillar_skips_the_steps_before_it() {
+		let pillars = pillars();
+		let course = vec![step(0, Direction::Clockwise), step(1, Direction::Clockwise), step(2, Direction::Clockwise)];
+		let mut run = CourseRun::new(pillars.len());
+
+		let events = orbit(&mut run, &pillars, &course, 2, 1.05);
+		assert!(matches!(events[..], [CourseEvent::Skipped(2), CourseEvent::Finished]));
+		assert_eq!(run.skipped, 2);
+		assert_eq!(run.penalty_time, 2.0 * SKIP_PENALTY);
+		assert!(run.is_finished(&course));
+	}
+
+	#[test]
+	fn orbiting_the_target_the_wrong_way_is_penalised() {
+		let pillars = pillars();
+		let course = vec![step(0, Direction::Clockwise)];
+		let mut run = CourseRun::new(pillars.len());
+
+		let events = orbit(&mut run, &pillars, &course, 0, -2.05);
+		assert!(matches!(events[..], [CourseEvent::WrongDirection, CourseEvent::WrongDirection]));
+		assert_eq!(run.wrong_direction, 2);
+		assert_eq!(run.penalty_time, 2.0 * WRONG_DIRECTION_PENALTY);
+		assert_eq!(run.current, 0);
+		assert_eq!(run.get_progress(&course), 0.0);
+
+		// The wrong way round doesn't count towards the step
+		let events = orbit(&mut run, &pillars, &course, 0, 1.05);
+		assert!(matches!(events[..], [CourseEvent::Finished]));
+		assert_eq!(run.penalty_time, 2.0 * WRONG_DIRECTION_PENALTY);
+	}
+}
@@ -0,0 +1,127 @@
+// Debug drawing of the physics in the world, split into layers that can each be turned on and off
+// with the number keys while the debug overlay is up.
+
+use raylib::{math::Vector2, drawing::RaylibDraw, color::Color, consts::KeyboardKey};
+
+use crate::{car::{self, Car}, pillar::Pillar, misc, POINT_DIST_THRESHOLD};
+
+const VEL_SCALE: f32 = 0.25;       // Seconds of travel the velocity arrow shows
+const HEADING_LEN: f32 = 60.0;
+const LATERAL_SCALE: f32 = 0.1;    // Seconds of the sideways grip the arrow shows
+const WHEEL_RADIUS: f32 = 4.0;
+const ARROW_HEAD: f32 = 8.0;
+
+static VEL_COLOR: Color = Color { r: 40, g: 90, b: 170, a: 255 };
+static HEADING_COLOR: Color = Color { r: 38, g: 38, b: 38, a: 255 };
+static LATERAL_COLOR: Color = Color { r: 200, g: 120, b: 20, a: 255 };
+static COLLISION_COLOR: Color = Color { r: 190, g: 36, b: 25, a: 160 };
+static THRESHOLD_COLOR: Color = Color { r: 0, g: 100, b: 0, a: 100 };
+static FAR_THRESHOLD_COLOR: Color = Color { r: 0, g: 100, b: 0, a: 40 };
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Layer {
+	Velocity,
+	Heading,
+	Wheels,
+	LateralForce,
+	CentreOfMass,
+	Collision,
+	PillarRadii,   // Scoring threshold around every pillar, and a line to the one being scored on
+}
+
+impl Layer {
+	pub const ALL: [Layer; 7] = [Layer::Velocity, Layer::Heading, Layer::Wheels, Layer::LateralForce,
+		Layer::CentreOfMass, Layer::Collision, Layer::PillarRadii];
+	const KEYS: [KeyboardKey; 7] = [KeyboardKey::KEY_ONE, KeyboardKey::KEY_TWO, KeyboardKey::KEY_THREE, KeyboardKey::KEY_FOUR,
+		KeyboardKey::KEY_FIVE, KeyboardKey::KEY_SIX, KeyboardKey::KEY_SEVEN];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Layer::Velocity => "Velocity",
+			Layer::Heading => "Heading",
+			Layer::Wheels => "Wheels",
+			Layer::LateralForce => "Lateral force",
+			Layer::CentreOfMass => "Centre of mass",
+			Layer::Collision => "Collision",
+			Layer::PillarRadii => "Pillar radii",
+		}
+	}
+
+	#[inline]
+	pub fn key(&self) -> KeyboardKey {
+		Layer::KEYS[*self as usize]
+	}
+}
+
+pub struct DebugLayers {
+	enabled: [bool; 7],   // Indexed by Layer
+}
+
+impl Default for DebugLayers {
+	fn default() -> DebugLayers {   // Starts with what the debug view always used to show
+		let mut enabled = [false; 7];
+		enabled[Layer::PillarRadii as usize] = true;
+		DebugLayers { enabled }
+	}
+}
+
+impl DebugLayers {
+	#[inline]
+	pub fn is_on(&self, layer: Layer) -> bool {
+		self.enabled[layer as usize]
+	}
+
+	#[inline]
+	pub fn toggle(&mut self, layer: Layer) {
+		self.enabled[layer as usize] = !self.enabled[layer as usize];
+	}
+
+	// closest_pillar is the pillar's index and whether the car is scoring on it
+	pub fn draw<D: RaylibDraw>(&self, d: &mut D, car: &Car, pillars: &[Pillar], closest_pillar: (usize, bool)) {
+		if self.is_on(Layer::PillarRadii) {
+			for (i, p) in pillars.iter().enumerate() {
+				let col = if i == closest_pillar.0 { THRESHOLD_COLOR } else { FAR_THRESHOLD_COLOR };
+				d.draw_circle_v(p.pos, POINT_DIST_THRESHOLD, col);
+			}
+			if let (Some(p), true) = (pillars.get(closest_pillar.0), closest_pillar.1) {
+				d.draw_line_ex(p.pos, car.pos, 2.0, Color::BLUE);
+			}
+		}
+		if self.is_on(Layer::Collision) {   // Contact is checked as a circle around the car against each pillar
+			d.draw_circle_lines(car.pos.x as i32, car.pos.y as i32, car::HALF_CAR_W, COLLISION_COLOR);
+			for p in pillars.iter() {
+				d.draw_circle_lines(p.pos.x as i32, p.pos.y as i32, p.radius, COLLISION_COLOR);
+			}
+		}
+		if self.is_on(Layer::Wheels) {
+			for wheel in car.get_wheel_positions().iter() {
+				d.draw_circle_v(*wheel, WHEEL_RADIUS, if car.drifting { LATERAL_COLOR } else { HEADING_COLOR });
+			}
+		}
+		if self.is_on(Layer::Heading) {
+			draw_arrow(d, car.pos, misc::get_components(HEADING_LEN, car.angle), HEADING_COLOR);
+		}
+		if self.is_on(Layer::Velocity) {
+			draw_arrow(d, car.pos, car.vel.scale_by(VEL_SCALE), VEL_COLOR);
+		}
+		if self.is_on(Layer::LateralForce) {
+			draw_arrow(d, car.pos, car.lateral_acc.scale_by(LATERAL_SCALE), LATERAL_COLOR);
+		}
+		if self.is_on(Layer::CentreOfMass) {
+			d.draw_line_ex(car.pos - Vector2::new(6.0, 0.0), car.pos + Vector2::new(6.0, 0.0), 2.0, HEADING_COLOR);
+			d.draw_line_ex(car.pos - Vector2::new(0.0, 6.0), car.pos + Vector2::new(0.0, 6.0), 2.0, HEADING_COLOR);
+		}
+	}
+}
+
+fn draw_arrow<D: RaylibDraw>(d: &mut D, from: Vector2, v: Vector2, col: Color) {
+	let len = v.length();
+	if len < 1.0 {
+		return;
+	}
+	let to = from + v;
+	let back = v.scale_by(-ARROW_HEAD.min(len)/len);
+	d.draw_line_ex(from, to, 2.0, col);
+	d.draw_line_ex(to, to + misc::rotate_vec(back, 0.5), 2.0, col);
+	d.draw_line_ex(to, to + misc::rotate_vec(back, -0.5), 2.0, col);
+}
@@ -1,126 +1,89 @@
-use raylib::{math::Vector2, RaylibHandle, drawing::{RaylibDrawHandle, RaylibDraw}, consts::PI};
-use rand::{rngs::ThreadRng, Rng};
-use rayon::prelude::*;
+use raylib::{math::Vector2, drawing::{RaylibDraw, RaylibBlendModeExt}, color::Color, consts::PI};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use crate::{CHARCOAL, misc::get_components};
+use crate::{
+	misc::get_components,
+	emitter::{EmitterConfig, EmitterShape, ParticleBlend},
+	particle_pool::ParticlePool,
+	surface::SurfaceType,
+};
 
-const DUST_PARTICLE_MAX_RAD: f32 = 10.0;  // Starting radius
-const DUST_PARTICLE_MIN_RAD: f32 = 1.0;
-const DUST_PARTICLE_EXPANSION_RATE: f32 = 15.0;  // Pixels per second increase of radius
-
-const DUST_PARTICLE_MAX_LIFESPAN: f64 = 0.25;  // In seconds, per each 1 pixel of radius
-const DUST_PARTICLE_MIN_LIFESPAN: f64 = 0.08;  // In seconds
-
-pub const DUST_PARTICLES_EMM_RATE: f32 = 500.0; // How many emitted per sec, for every pixel per second the player is moving at, and the acceleration multiplier
-
-const DUST_PARTICLE_MIN_SPEED: f32 = 10.0;
-const DUST_PARTICLE_MAX_SPEED: f32 = 100.0;
-
-const DUST_PARTICLE_ANGULAR_VARIATION: f32 = PI as f32/4.0;
-
-
-struct Particle {
-	pos: Vector2,
-	vel: Vector2,
-	radius: f32,
-	time_created: f64,
-	lifespan: f64,
-	alpha: u8
-}
-
-impl Default for Particle {
-	fn default() -> Particle {
-		Particle {
-			pos: Vector2::zero(),
-			vel: Vector2::zero(),
-			radius: 8.0,
-			time_created: 0.0,
-			lifespan: 1.0,
-			alpha: 255
-		}
-	}
-}
-
-impl Particle {
-	fn new(p: Vector2, v: Vector2, time: f64, life: f64, rad: f32) -> Particle {
-		Particle {
-			pos: p,
-			vel: v,
-			radius: rad,
-			time_created: time,
-			lifespan: life * rad as f64,
-			..Default::default()
-		}
-	}
-
-	fn update(&mut self, dt: f32, time: f64) {
-		let norm_life: f32 = (1.0 - (time - self.time_created)/self.lifespan) as f32;
-		self.alpha = (norm_life.powi(2) * 230.0).ceil() as u8;
-		self.radius += dt * DUST_PARTICLE_EXPANSION_RATE;
-		self.pos += self.vel.scale_by(dt);
-	}
-
-	fn draw(&self, d: &mut RaylibDrawHandle) {
-		let mut col = CHARCOAL;
-		col.a = self.alpha;
-		d.draw_circle_v(self.pos, self.radius, col);
-	}
-}
+const MIN_MAX_RAD: f32 = 0.5;  // Smallest the max radius can be scaled down to by a low emission amount
+const HALF_PI: f32 = (PI/2.0) as f32;
 
 pub struct ParticleSystem {
-	particles: Vec<Particle>,
+	particles: ParticlePool,
+	config: EmitterConfig,
 	max_rad: f32,
 	em_rate: f32,
 	em_period: f32,
 	spawn_pos: Vector2,
+	spawn_vel: Vector2,   // Velocity of whatever is emitting, e.g. a wheel
 	spawn_angle: f32,
 	spawn_timer: f32,
-	rand_thread: ThreadRng
+	tint: Color,
+	size_mult: f32,      // From the surface currently being emitted onto
+	lifespan_mult: f64,
+	rate_mult: f32,
+	rng: StdRng,   // Seeded, so effects come out the same for a given seed
 }
 
-impl Default for ParticleSystem {
-	fn default() -> ParticleSystem {
+impl ParticleSystem {
+	pub fn new(config: EmitterConfig, budget: usize, seed: u64) -> ParticleSystem {
 		ParticleSystem {
-			particles: vec![],
-			em_rate: DUST_PARTICLES_EMM_RATE,
-			em_period: 1.0/DUST_PARTICLES_EMM_RATE,
-			max_rad: DUST_PARTICLE_MAX_RAD,
+			particles: ParticlePool::with_budget(budget),
+			max_rad: config.size.1,
+			em_rate: config.rate,
+			em_period: 1.0/config.rate,
+			config,
 			spawn_pos: Vector2::zero(),
+			spawn_vel: Vector2::zero(),
 			spawn_angle: 0.0,
 			spawn_timer: 0.0,
-			rand_thread: rand::thread_rng()
-		}
-	}
-}
-
-impl ParticleSystem {
-	pub fn new(p: Vector2, angle: f32) -> ParticleSystem {
-		ParticleSystem {
-			spawn_pos: p,
-			spawn_angle: angle,
-			..Default::default()
+			tint: Color::WHITE,
+			size_mult: 1.0,
+			lifespan_mult: 1.0,
+			rate_mult: 1.0,
+			rng: StdRng::seed_from_u64(seed),
 		}
 	}
 
-	pub fn update(&mut self, dt: f32, time: f64) {
+	pub fn update(&mut self, dt: f32, time: f64, wind: Vector2) {
 		self.spawn_timer += dt;
-		self.kill_particles(time);
-
-		self.particles.par_iter_mut()
-			.for_each(|p| p.update(dt, time));
+		self.particles.kill_dead(time);
+		self.particles.update(dt, time, &self.config, wind);
 	}
 
-	pub fn draw(&self, d: &mut RaylibDrawHandle) {
-		for p in self.particles.iter() {
-			p.draw(d);
+	pub fn draw<D: RaylibDraw>(&self, d: &mut D) {
+		match self.config.blend {
+			ParticleBlend::Alpha => self.particles.draw(d),
+			blend => self.particles.draw(&mut d.begin_blend_mode(blend.to_raylib())),
 		}
 	}
 
 	#[inline]
-	fn get_particle_count(&self) -> usize {
+	pub fn get_particle_count(&self) -> usize {
 		self.particles.len()
 	}
 
+	pub fn set_surface(&mut self, surface: SurfaceType) {
+		let props = surface.properties();
+		self.tint = props.dust_colour;
+		self.size_mult = props.dust_size;
+		self.lifespan_mult = props.dust_lifespan as f64;
+		self.rate_mult = props.dust_rate;
+	}
+
+	// Amount scales both the emission rate and the size of particles, 1.0 being the config's values
+	pub fn emit(&mut self, dt: f32, time: f64, pos: Vector2, vel: Vector2, angle: f32, amount: f32) {
+		self.em_rate = self.config.rate * amount * self.rate_mult;
+		self.max_rad = (self.config.size.1 * amount * self.size_mult).max(MIN_MAX_RAD);
+		self.spawn_pos = pos;
+		self.spawn_vel = vel;
+		self.spawn_angle = angle;
+		self.spawn_particles(dt, time);
+	}
+
 	fn spawn_particles(&mut self, dt: f32, time: f64) {
 		let p_num = (self.em_rate * dt).floor() as u32;
 		if self.spawn_timer >= self.em_period {
@@ -133,77 +96,80 @@ impl ParticleSystem {
 		}
 	}
 
-	fn spawn_single_particle(&mut self, time: f64) {
-		let vel = get_components(self.rand_thread.gen_range(DUST_PARTICLE_MIN_SPEED..DUST_PARTICLE_MAX_SPEED),                   // Random speed
-		self.spawn_angle + self.rand_thread.gen_range(-DUST_PARTICLE_ANGULAR_VARIATION..DUST_PARTICLE_ANGULAR_VARIATION)); // Random angle
+	#[inline]
+	fn rand_in(&mut self, range: (f32, f32)) -> f32 {
+		if range.0 < range.1 { self.rng.gen_range(range.0..range.1) } else { range.0 }
+	}
 
-		let mut rad = DUST_PARTICLE_MIN_RAD;
-		if rad < self.max_rad {
-			rad = self.rand_thread.gen_range(DUST_PARTICLE_MIN_RAD..self.max_rad);
+	fn spawn_single_particle(&mut self, time: f64) {
+		let speed = self.rand_in(self.config.speed);                                     // Random speed
+		let spread = self.config.angle_spread;
+		let vel = get_components(speed, self.spawn_angle + self.rand_in((-spread, spread))) // Random angle
+			+ self.spawn_vel.scale_by(self.config.inherit_velocity);
+
+		let offset = match self.config.shape {
+			EmitterShape::Point => Vector2::zero(),
+			EmitterShape::Circle(r) => {
+				let ang = self.rand_in((0.0, PI as f32 * 2.0));
+				get_components(r * self.rand_in((0.0, 1.0)).sqrt(), ang)
+			},
+			EmitterShape::Line(l) => get_components(self.rand_in((-l/2.0, l/2.0)), self.spawn_angle + HALF_PI),
+		};
+
+		let rad = self.rand_in((self.config.size.0, self.max_rad));
+		let lifespan_range = self.config.lifespan;
+		let mut lifespan = self.rand_in((lifespan_range.0 as f32, lifespan_range.1 as f32)) as f64 * self.lifespan_mult;
+		if self.config.lifespan_per_radius {
+			lifespan *= rad as f64;
 		}
 
-		self.particles.push(Particle::new(
-			self.spawn_pos,
-			vel,
-			time,
-			self.rand_thread.gen_range(DUST_PARTICLE_MIN_LIFESPAN..DUST_PARTICLE_MAX_LIFESPAN),
-			rad)
-		);
-	}
-
-	#[inline]
-	fn kill_particles(&mut self, time: f64) {
-		self.particles.retain(|i|time - i.time_created <= i.lifespan);
+		self.particles.spawn(self.spawn_pos + offset, vel, rad, time, lifespan, self.tint);
 	}
 }
 
-pub struct CarDustSystems {
-	pub left: ParticleSystem,
-	pub right: ParticleSystem
+pub struct CarDustSystems {    // One particle system per wheel
+	systems: Vec<ParticleSystem>,
 }
 
 impl CarDustSystems {
-	#[inline]
-	pub fn update(&mut self, dt: f32, time: f64) {
-		self.left.update(dt, time);
-		self.right.update(dt, time);
+	pub fn new(config: &EmitterConfig, wheels: usize, budget_per_wheel: usize, seed: u64) -> CarDustSystems {
+		CarDustSystems {
+			systems: (0..wheels).map(|i| ParticleSystem::new(config.clone(), budget_per_wheel, seed.wrapping_add(i as u64))).collect(),
+		}
 	}
 
 	#[inline]
-	pub fn draw(&self, d: &mut RaylibDrawHandle) {
-		self.left.draw(d);
-		self.right.draw(d);
+	pub fn update(&mut self, dt: f32, time: f64, wind: Vector2) {
+		for s in self.systems.iter_mut() {
+			s.update(dt, time, wind);
+		}
 	}
 
-	pub fn emit(&mut self, dt: f32, time: f64, player_ang: f32, rate_multiplier: f32, back_left_pos: Vector2, back_right_pos: Vector2) {
-		self.left.em_rate = DUST_PARTICLES_EMM_RATE * rate_multiplier;
-		self.right.em_rate = self.left.em_rate;
-
-		self.left.max_rad = (DUST_PARTICLE_MAX_RAD * rate_multiplier).max(0.5);
-		self.right.max_rad = self.left.max_rad;
-
-		self.left.spawn_pos = back_left_pos;
-		self.right.spawn_pos = back_right_pos;
-
-		self.left.spawn_angle = player_ang + PI as f32;
-		self.right.spawn_angle = self.left.spawn_angle;
-
-		self.left.spawn_particles(dt, time);
-		self.right.spawn_particles(dt, time);
+	#[inline]
+	pub fn draw<D: RaylibDraw>(&self, d: &mut D) {
+		for s in self.systems.iter() {
+			s.draw(d);
+		}
 	}
 
 	#[inline]
-	pub fn get_particle_count(&self) -> usize {
-		self.left.get_particle_count() + self.right.get_particle_count()
+	pub fn set_surfaces(&mut self, surfaces: &[SurfaceType]) {
+		for (s, surface) in self.systems.iter_mut().zip(surfaces.iter()) {
+			s.set_surface(*surface);
+		}
 	}
-}
 
-impl Default for CarDustSystems {
-	fn default() -> CarDustSystems {
-		CarDustSystems {
-			left: ParticleSystem::new(Vector2::zero(), 0.0),
-			right: ParticleSystem::new(Vector2::zero(), 0.0)
+	// Amounts, positions and velocities are given per wheel
+	pub fn emit(&mut self, dt: f32, time: f64, angle: f32, amounts: &[f32], wheel_positions: &[Vector2], wheel_vels: &[Vector2]) {
+		for (((s, amount), pos), vel) in self.systems.iter_mut().zip(amounts.iter()).zip(wheel_positions.iter()).zip(wheel_vels.iter()) {
+			if *amount > 0.0 {
+				s.emit(dt, time, *pos, *vel, angle, *amount);
+			}
 		}
 	}
-}
 
+	#[inline]
+	pub fn get_particle_count(&self) -> usize {
+		self.systems.iter().map(|s| s.get_particle_count()).sum()
+	}
+}
@@ -0,0 +1,193 @@
+use std::fs;
+
+use raylib::{color::Color, consts::BlendMode};
+
+use crate::keyvalue;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EmitterShape {
+	Point,
+	Circle(f32),  // Spawn anywhere inside a circle of this radius
+	Line(f32),    // Spawn along a line of this length, perpendicular to the emission angle
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ParticleBlend {
+	Alpha,
+	Additive,
+}
+
+impl ParticleBlend {
+	#[inline]
+	pub fn to_raylib(self) -> BlendMode {
+		match self {
+			ParticleBlend::Alpha => BlendMode::BLEND_ALPHA,
+			ParticleBlend::Additive => BlendMode::BLEND_ADDITIVE,
+		}
+	}
+}
+
+// Describes how a particle system looks and behaves, so one system can make dust, sparks, spray etc.
+// Loaded from simple `key = value` files, see the `emitters` folder.
+#[derive(Clone, Debug)]
+pub struct EmitterConfig {
+	pub shape: EmitterShape,
+	pub rate: f32,              // Particles per second, before any multiplier from the emitter's owner
+	pub speed: (f32, f32),      // Min and max speed
+	pub angle_spread: f32,      // Max angle (in radians) either side of the emission angle
+	pub lifespan: (f64, f64),   // Min and max, in seconds
+	pub lifespan_per_radius: bool,  // If true, lifespan is per pixel of starting radius
+	pub size: (f32, f32),       // Min and max starting radius
+	pub growth: f32,            // Pixels per second increase of radius
+	pub size_over_life: (f32, f32),  // Radius multiplier at the start and end of a particle's life
+	pub gradient: Vec<(f32, Color)>, // Colour keys over normalised life (0 -> 1), in order
+	pub blend: ParticleBlend,
+	pub drag: f32,              // Fraction of velocity (relative to the wind) lost per second
+	pub inherit_velocity: f32,  // Fraction of the emitter's own velocity given to new particles
+}
+
+impl Default for EmitterConfig {
+	fn default() -> EmitterConfig {
+		EmitterConfig {
+			shape: EmitterShape::Point,
+			rate: 100.0,
+			speed: (10.0, 100.0),
+			angle_spread: 0.0,
+			lifespan: (0.5, 1.0),
+			lifespan_per_radius: false,
+			size: (1.0, 5.0),
+			growth: 0.0,
+			size_over_life: (1.0, 1.0),
+			gradient: vec![(0.0, Color::WHITE), (1.0, Color::new(255, 255, 255, 0))],
+			blend: ParticleBlend::Alpha,
+			drag: 0.0,
+			inherit_velocity: 0.0,
+		}
+	}
+}
+
+impl EmitterConfig {
+	pub fn load(path: &str) -> Result<EmitterConfig, String> {
+		let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+		EmitterConfig::parse(&text).map_err(|e| format!("{}: {}", path, e))
+	}
+
+	pub fn parse(text: &str) -> Result<EmitterConfig, String> {
+		let mut config = EmitterConfig::default();
+		let mut gradient = vec![];
+
+		for entry in keyvalue::entries(text) {
+			let e = entry?;
+			match e.key {
+				"shape" => config.shape = match e.word_and_nums()? {
+					("point", nums) if nums.is_empty() => EmitterShape::Point,
+					("circle", nums) if nums.len() == 1 => EmitterShape::Circle(nums[0]),
+					("line", nums) if nums.len() == 1 => EmitterShape::Line(nums[0]),
+					_ => return Err(e.err("shape must be `point`, `circle <radius>` or `line <length>`")),
+				},
+				"rate" => config.rate = e.single()?,
+				"speed" => config.speed = e.pair()?,
+				"angle_spread" => config.angle_spread = e.single()?,
+				"lifespan" => {
+					let (min, max) = e.pair()?;
+					config.lifespan = (min as f64, max as f64);
+				},
+				"lifespan_per_radius" => config.lifespan_per_radius = e.bool()?,
+				"size" => config.size = e.pair()?,
+				"growth" => config.growth = e.single()?,
+				"size_over_life" => config.size_over_life = e.pair()?,
+				"colour" => match e.nums()?.as_slice() {
+					[t, r, g, b, a] => gradient.push((*t, Color::new(*r as u8, *g as u8, *b as u8, *a as u8))),
+					_ => return Err(e.err("colour must be `<life> <r> <g> <b> <a>`")),
+				},
+				"blend" => config.blend = match e.value {
+					"alpha" => ParticleBlend::Alpha,
+					"additive" => ParticleBlend::Additive,
+					_ => return Err(e.err("blend must be `alpha` or `additive`")),
+				},
+				"drag" => config.drag = e.single()?,
+				"inherit_velocity" => config.inherit_velocity = e.single()?,
+				key => return Err(e.err(&format!("unknown key `{}`", key))),
+			}
+		}
+
+		if !gradient.is_empty() {
+			if gradient.windows(2).any(|w| w[0].0 > w[1].0) {
+				return Err("colour keys must be in order of life".to_string());
+			}
+			config.gradient = gradient;
+		}
+		if config.speed.0 > config.speed.1 || config.lifespan.0 > config.lifespan.1 || config.size.0 > config.size.1 {
+			return Err("ranges must be written as `min max`".to_string());
+		}
+		if config.lifespan.0 <= 0.0 {
+			return Err("lifespan must be positive".to_string());
+		}
+		Ok(config)
+	}
+
+	pub fn sample_colour(&self, t: f32) -> Color {   // t is normalised life, 0 -> 1
+		let keys = &self.gradient;
+		if t <= keys[0].0 { return keys[0].1 }
+
+		for w in keys.windows(2) {
+			let ((t0, c0), (t1, c1)) = (w[0], w[1]);
+			if t <= t1 {
+				let f = if t1 > t0 { (t - t0)/(t1 - t0) } else { 1.0 };
+				let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * f) as u8;
+				return Color::new(lerp(c0.r, c1.r), lerp(c0.g, c1.g), lerp(c0.b, c1.b), lerp(c0.a, c1.a));
+			}
+		}
+		keys[keys.len() - 1].1
+	}
+
+	#[inline]
+	pub fn sample_size_mult(&self, t: f32) -> f32 {
+		self.size_over_life.0 + (self.size_over_life.1 - self.size_over_life.0) * t
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_an_emitter() {
+		let text = "# Comment\nshape = line 12\nrate = 50\nspeed = 5 15\nlifespan = 2\nlifespan_per_radius = true\n\
+			colour = 0 10 20 30 255\ncolour = 1 40 50 60 0\nblend = additive\ndrag = 0.5\n";
+		let config = EmitterConfig::parse(text).unwrap();
+		assert_eq!(config.shape, EmitterShape::Line(12.0));
+		assert_eq!(config.rate, 50.0);
+		assert_eq!(config.speed, (5.0, 15.0));
+		assert_eq!(config.lifespan, (2.0, 2.0));
+		assert!(config.lifespan_per_radius);
+		assert_eq!(config.gradient, vec![(0.0, Color::new(10, 20, 30, 255)), (1.0, Color::new(40, 50, 60, 0))]);
+		assert_eq!(config.blend, ParticleBlend::Additive);
+		assert_eq!(config.drag, 0.5);
+		assert_eq!(config.size, EmitterConfig::default().size);   // Not given, so left alone
+	}
+
+	#[test]
+	fn shipped_emitters_load() {
+		for path in crate::keyvalue::list_files("emitters", "emitter") {
+			if let Err(e) = EmitterConfig::load(path.to_str().unwrap()) {
+				panic!("{}", e);
+			}
+		}
+	}
+
+	#[test]
+	fn errors_name_the_line() {
+		let err = |text: &str| EmitterConfig::parse(text).err().unwrap();
+		assert_eq!(err("rate = 10\nspeed = 5 fast"), "line 2: `fast` is not a number");
+		assert_eq!(err("rate"), "line 1: expected `key = value`");
+		assert_eq!(err("rate ="), "line 1: expected a single number");
+		assert_eq!(err("sparkle = 3"), "line 1: unknown key `sparkle`");
+		assert_eq!(err("shape = square 4"), "line 1: shape must be `point`, `circle <radius>` or `line <length>`");
+		assert_eq!(err("shape = circle"), "line 1: shape must be `point`, `circle <radius>` or `line <length>`");
+		assert_eq!(err("shape = circle 4 5"), "line 1: shape must be `point`, `circle <radius>` or `line <length>`");
+		assert_eq!(err("shape = point 5"), "line 1: shape must be `point`, `circle <radius>` or `line <length>`");
+		assert_eq!(err("speed = 10 5"), "ranges must be written as `min max`");
+		assert_eq!(err("colour = 1 0 0 0 0\ncolour = 0 0 0 0 0"), "colour keys must be in order of life");
+	}
+}
@@ -0,0 +1,216 @@
+// Gym style environment for training drift agents: reset(seed) -> observation, then
+// step(action) -> (observation, reward, done) until the episode is over. Runs the same headless
+// simulation as replays, several fixed ticks per step, with particles and trails turned off. The
+// seed moves and turns the car a little off the level's start, so the same seed always gives the
+// same episode.
+//
+// Can be driven over stdin/stdout or a TCP socket with a line protocol, one reply per command:
+//   reset [seed]    ->  obs <values...>
+//   step <action>   ->  step <reward> <done 0/1> <values...>   (action is Input bits, 0 to 15)
+//   names           ->  names <observation names...>
+//   quit
+// Anything else gets `err <message>`. Lines not starting with one of those words can be ignored.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{input::Input, misc, Game, GameState, TICK_RATE, TWO_PI};
+
+pub const DEF_TICKS_PER_STEP: u32 = 4;   // 60 decisions a second
+const DEF_EPISODE_SECS: u32 = 60;
+const START_JITTER: f32 = 30.0;        // Pixels from the level's start
+const START_ANGLE_JITTER: f32 = 0.5;   // Radians either way
+
+pub const OBSERVATION_NAMES: [&str; 13] = [
+	"vel_forward", "vel_side", "speed", "angular_vel", "perp", "throttle",
+	"pillar_forward", "pillar_side", "pillar_dist",   // Closest pillar relative to the car
+	"orbit_progress", "scoring", "combo_multiplier", "combo_grace",
+];
+
+pub type Observation = [f32; 13];
+
+pub struct DriftEnv {
+	game: Game,
+	ticks_per_step: u32,
+	max_ticks: u32,    // Episodes also end when the mode ends the run
+	ticks: u32,
+	banked: f32,       // Score plus the combo in progress as of the last step
+}
+
+impl DriftEnv {
+	pub fn new(mut game: Game, ticks_per_step: u32, max_ticks: Option<u32>) -> DriftEnv {
+		// Nothing gets saved, nothing else writes to stdout while serving, and nothing is drawn
+		game.save_results = false;
+		game.settings.telemetry = false;
+		game.telemetry_path = None;
+		game.player.effects = false;
+		for p in game.others.iter_mut() {
+			p.car.effects = false;
+		}
+		DriftEnv {
+			game,
+			ticks_per_step: ticks_per_step.max(1),
+			max_ticks: max_ticks.unwrap_or(DEF_EPISODE_SECS * TICK_RATE),
+			ticks: 0,
+			banked: 0.0,
+		}
+	}
+
+	pub fn reset(&mut self, seed: u64) -> Observation {
+		self.game.start_run();   // Keeps the car, so files aren't read again every episode
+
+		let mut rng = StdRng::seed_from_u64(seed);
+		let level = &self.game.level;
+		let pos = level.start_pos + misc::get_components(rng.gen_range(0.0..START_JITTER), rng.gen_range(0.0..TWO_PI));
+		let angle = level.start_angle + rng.gen_range(-START_ANGLE_JITTER..START_ANGLE_JITTER);
+		self.game.player.reset(pos, angle);
+		self.ticks = 0;
+		self.banked = 0.0;
+		self.observe()
+	}
+
+	// Reward is the points the game gave for the step, counting a combo in progress at its multiplied value
+	pub fn step(&mut self, action: Input) -> (Observation, f32, bool) {
+		for _ in 0..self.ticks_per_step {
+			if self.is_done() { break }
+			self.game.tick(action);
+			self.ticks += 1;
+		}
+		let banked = self.get_banked();
+		let reward = banked - self.banked;
+		self.banked = banked;
+		(self.observe(), reward, self.is_done())
+	}
+
+	#[inline]
+	pub fn is_done(&self) -> bool {
+		self.game.state != GameState::Playing || self.ticks >= self.max_ticks
+	}
+
+	#[inline]
+	fn get_banked(&self) -> f32 {
+		let combo = &self.game.combo;
+		(self.game.score + combo.points * combo.multiplier) as f32
+	}
+
+	fn observe(&self) -> Observation {
+		let g = &self.game;
+		let car = &g.player;
+		let forward = misc::get_components(1.0, car.angle);
+		let side = misc::get_components(1.0, car.angle + TWO_PI/4.0);   // Same side as perp
+
+		let (pillar_pos, progress) = match g.level.pillars.get(g.closest_pillar_to_player.0 as usize) {
+			Some(p) => (p.pos - car.pos, if g.player_is_scoring_points { p.progress.abs() } else { 0.0 }),
+			None => (raylib::math::Vector2::zero(), 0.0),
+		};
+		[
+			car.vel.dot(forward), car.vel.dot(side), car.vel_mag, car.angular_vel, car.perp, car.throttle,
+			pillar_pos.dot(forward), pillar_pos.dot(side), pillar_pos.length(),
+			progress, g.player_is_scoring_points as u8 as f32, g.combo.multiplier as f32, g.combo.get_grace_left(),
+		]
+	}
+
+	// Answers commands until `quit` or the end of the input
+	pub fn serve<R: BufRead, W: Write>(&mut self, reader: R, mut writer: W) -> io::Result<()> {
+		let values = |obs: &Observation| obs.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" ");
+
+		for line in reader.lines() {
+			let line = line?;
+			let mut words = line.split_whitespace();
+			let reply = match (words.next(), words.next()) {
+				(Some("reset"), seed) => match seed.map(|s| s.parse::<u64>()) {
+					None => format!("obs {}", values(&self.reset(rand::random()))),
+					Some(Ok(seed)) => format!("obs {}", values(&self.reset(seed))),
+					Some(Err(_)) => "err seed must be a whole number".to_string(),
+				},
+				(Some("step"), Some(action)) => match action.parse::<u8>() {
+					Ok(bits) if bits < 16 && !self.is_done() => {
+						let (obs, reward, done) = self.step(Input::from_bits(bits));
+						format!("step {} {} {}", reward, done as u8, values(&obs))
+					},
+					Ok(bits) if bits < 16 => "err episode is over, reset first".to_string(),
+					_ => "err action must be 0 to 15".to_string(),
+				},
+				(Some("step"), None) => "err step needs an action".to_string(),
+				(Some("names"), _) => format!("names {}", OBSERVATION_NAMES.join(" ")),
+				(Some("quit"), _) => return Ok(()),
+				(None, _) => continue,
+				(Some(cmd), _) => format!("err unknown command `{}`", cmd),
+			};
+			writeln!(writer, "{}", reply)?;
+			writer.flush()?;
+		}
+		Ok(())
+	}
+
+	// One client at a time, each getting a fresh episode when it sends reset
+	pub fn listen(&mut self, addr: &str) -> Result<(), String> {
+		let listener = TcpListener::bind(addr).map_err(|e| format!("{}: {}", addr, e))?;
+		eprintln!("Listening on {}", addr);
+		for stream in listener.incoming() {
+			let result = stream.and_then(|s| {
+				let reader = BufReader::new(s.try_clone()?);
+				self.serve(reader, s)
+			});
+			if let Err(e) = result {
+				eprintln!("Client error: {}", e);
+			}
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use super::*;
+	use crate::settings::Settings;
+
+	fn get_env(max_ticks: Option<u32>) -> DriftEnv {
+		DriftEnv::new(Game::new(Settings::default(), 1).unwrap(), DEF_TICKS_PER_STEP, max_ticks)
+	}
+
+	fn serve(env: &mut DriftEnv, commands: &str) -> Vec<String> {
+		let mut out = vec![];
+		env.serve(Cursor::new(commands), &mut out).unwrap();
+		String::from_utf8(out).unwrap().lines().map(str::to_string).collect()
+	}
+
+	#[test]
+	fn answers_each_command() {
+		let mut env = get_env(None);
+		let replies = serve(&mut env, "names\nreset 3\n\nstep 1\nstep 16\nstep\nreset x\njump\nquit\nstep 1\n");
+		assert_eq!(replies.len(), 7);
+		assert_eq!(replies[0], format!("names {}", OBSERVATION_NAMES.join(" ")));
+		assert!(replies[1].starts_with("obs "));
+		assert_eq!(replies[1].split_whitespace().count(), OBSERVATION_NAMES.len() + 1);
+		assert!(replies[2].starts_with("step "));
+		assert_eq!(replies[2].split_whitespace().count(), OBSERVATION_NAMES.len() + 3);
+		assert_eq!(replies[3], "err action must be 0 to 15");
+		assert_eq!(replies[4], "err step needs an action");
+		assert_eq!(replies[5], "err seed must be a whole number");
+		assert_eq!(replies[6], "err unknown command `jump`");
+	}
+
+	#[test]
+	fn seeds_repeat_and_differ() {
+		let mut env = get_env(None);
+		let a = env.reset(1);
+		env.step(Input::from_bits(1));
+		assert_eq!(env.reset(1), a);
+		assert_ne!(env.reset(2), a);
+		assert!(!env.game.player.effects);
+	}
+
+	#[test]
+	fn episodes_end() {
+		let mut env = get_env(Some(DEF_TICKS_PER_STEP * 2));
+		let replies = serve(&mut env, "reset 1\nstep 1\nstep 1\nstep 1\nreset 1\nstep 1\n");
+		let done: Vec<_> = replies.iter().filter_map(|r| r.strip_prefix("step ")).map(|r| r.split_whitespace().nth(1).unwrap()).collect();
+		assert_eq!(done, vec!["0", "1", "0"]);
+		assert_eq!(replies[3], "err episode is over, reset first");
+	}
+}
@@ -0,0 +1,137 @@
+// Scrolling line graphs of the last few seconds of the car's state, drawn over the debug view for
+// seeing what the handling is doing while driving rather than digging through telemetry files.
+
+use std::collections::VecDeque;
+
+use raylib::{math::{Vector2, Rectangle}, drawing::{RaylibDraw, RaylibDrawHandle}, color::Color, text::measure_text};
+
+use crate::{car::Car, input::Input, CHARCOAL, RED_1};
+
+const HISTORY: f64 = 5.0;        // Seconds shown
+const WIDTH: f32 = 0.3;          // Fraction of the screen width
+const GRAPH_H: f32 = 70.0;       // At a UI unit of 1
+const GAP: f32 = 6.0;
+
+static PANEL_COLOR: Color = Color { r: 255, g: 255, b: 250, a: 170 };
+static AXIS_COLOR: Color = Color { r: 38, g: 38, b: 38, a: 60 };
+static BLUE: Color = Color { r: 40, g: 90, b: 170, a: 255 };
+
+#[derive(Clone, Copy)]
+struct Sample {
+	time: f64,
+	speed: f32,
+	perp: f32,
+	angular_vel: f32,
+	throttle: f32,
+	steer: f32,      // 1 is left
+	frame_ms: f32,
+}
+
+enum Scale {
+	Fixed(f32, f32),
+	Auto { min_range: f32, symmetric: bool },   // Fits the values shown, but never closer than min_range
+}
+
+type Line = (fn(&Sample) -> f32, Color);   // Value to plot and its colour
+
+struct Graph {
+	name: &'static str,
+	lines: &'static [Line],
+	scale: Scale,
+}
+
+const GRAPHS: [Graph; 5] = [
+	Graph { name: "Speed", lines: &[(|s| s.speed, RED_1)], scale: Scale::Auto { min_range: 200.0, symmetric: false } },
+	Graph { name: "Perp", lines: &[(|s| s.perp, RED_1)], scale: Scale::Fixed(-1.0, 1.0) },
+	Graph { name: "Angular vel", lines: &[(|s| s.angular_vel, RED_1)], scale: Scale::Auto { min_range: 2.0, symmetric: true } },
+	Graph { name: "Throttle / steer", lines: &[(|s| s.throttle, RED_1), (|s| s.steer, BLUE)], scale: Scale::Fixed(-1.0, 1.0) },
+	Graph { name: "Frame ms", lines: &[(|s| s.frame_ms, RED_1)], scale: Scale::Auto { min_range: 20.0, symmetric: false } },
+];
+
+pub struct Graphs {
+	pub visible: bool,
+	samples: VecDeque<Sample>,
+}
+
+impl Graphs {
+	pub fn new() -> Graphs {
+		Graphs { visible: true, samples: VecDeque::new() }
+	}
+
+	#[inline]
+	pub fn clear(&mut self) {
+		self.samples.clear();
+	}
+
+	// Once a frame while playing
+	pub fn push(&mut self, time: f64, frame_time: f32, car: &Car, input: Input) {
+		self.samples.push_back(Sample {
+			time,
+			speed: car.vel_mag,
+			perp: car.perp,
+			angular_vel: car.angular_vel,
+			throttle: car.throttle,
+			steer: input.steer_left as i32 as f32 - input.steer_right as i32 as f32,
+			frame_ms: frame_time * 1000.0,
+		});
+		while self.samples.front().is_some_and(|s| s.time < time - HISTORY) {
+			self.samples.pop_front();
+		}
+	}
+
+	pub fn draw(&self, d: &mut RaylibDrawHandle, ui_scale: f32) {
+		let unit = d.get_screen_height() as f32/800.0 * ui_scale;
+		let w = d.get_screen_width() as f32 * WIDTH;
+		let h = GRAPH_H * unit;
+		let x = d.get_screen_width() as f32 - w - GAP * unit;
+		let end = self.samples.back().map_or(0.0, |s| s.time);
+
+		for (i, graph) in GRAPHS.iter().enumerate() {
+			let rect = Rectangle::new(x, GAP * unit + (h + GAP * unit) * i as f32, w, h);
+			self.draw_graph(d, graph, rect, end, unit);
+		}
+	}
+
+	fn draw_graph(&self, d: &mut RaylibDrawHandle, graph: &Graph, rect: Rectangle, end: f64, unit: f32) {
+		d.draw_rectangle_rec(rect, PANEL_COLOR);
+
+		let (lo, hi) = match graph.scale {
+			Scale::Fixed(lo, hi) => (lo, hi),
+			Scale::Auto { min_range, symmetric } => {
+				let values = self.samples.iter().flat_map(|s| graph.lines.iter().map(move |(f, _)| f(s)));
+				if symmetric {
+					let max = values.fold(min_range/2.0, |m, v| m.max(v.abs()));
+					(-max, max)
+				} else {
+					(0.0, values.fold(min_range, f32::max))
+				}
+			},
+		};
+		let to_screen = |time: f64, value: f32| Vector2 {
+			x: rect.x + rect.width * (1.0 - ((end - time)/HISTORY) as f32),
+			y: rect.y + rect.height * (1.0 - (value.clamp(lo, hi) - lo)/(hi - lo)),
+		};
+
+		if lo < 0.0 && hi > 0.0 {
+			let zero = to_screen(end, 0.0).y;
+			d.draw_line_ex(Vector2::new(rect.x, zero), Vector2::new(rect.x + rect.width, zero), 1.0, AXIS_COLOR);
+		}
+		for (f, colour) in graph.lines.iter() {
+			let mut points = self.samples.iter().map(|s| to_screen(s.time, f(s)));
+			if let Some(mut prev) = points.next() {
+				for p in points {
+					d.draw_line_ex(prev, p, 1.5 * unit, colour);
+					prev = p;
+				}
+			}
+		}
+
+		let font = (14.0 * unit) as i32;
+		let current = self.samples.back().map_or(String::new(), |s| {
+			graph.lines.iter().map(|(f, _)| format!("{:.2}", f(s))).collect::<Vec<_>>().join(" / ")
+		});
+		d.draw_text(&format!("{}: {}", graph.name, current), (rect.x + 4.0 * unit) as i32, (rect.y + 3.0 * unit) as i32, font, CHARCOAL);
+		let top = format!("{:.0}", hi);   // Top of the scale, which moves for the auto scaled graphs
+		d.draw_text(&top, (rect.x + rect.width - 4.0 * unit) as i32 - measure_text(&top, font), (rect.y + 3.0 * unit) as i32, font, AXIS_COLOR);
+	}
+}
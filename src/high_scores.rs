@@ -0,0 +1,214 @@
+// Local leaderboards, one table per mode and level, saved between sessions.
+// The file starts with a `version <n>` line so older files can still be read after the format changes,
+// then has one score per line as `table <tab> level <tab> score <tab> name <tab> date <tab> car <tab> replay`.
+
+use std::{fs, collections::HashMap, time::{SystemTime, UNIX_EPOCH}};
+
+use crate::misc;
+
+pub const HIGH_SCORES_PATH: &str = "high_scores.txt";
+pub const TABLE_SIZE: usize = 10;
+
+const FORMAT_VERSION: u32 = 1;
+const NO_REPLAY: &str = "-";
+
+#[derive(Clone, Debug)]
+pub struct HighScore {
+	pub score: u32,   // Points, or milliseconds for timed modes
+	pub name: String,
+	pub date: String,   // YYYY-MM-DD
+	pub car: String,
+	pub replay: Option<String>,   // Replay file the run was saved to, if any
+}
+
+#[derive(Default)]
+pub struct HighScores {
+	tables: HashMap<(String, String), Vec<HighScore>>,   // By table and level, best first
+	read_only: bool,   // Set if the file is from a newer version, so saving doesn't throw away what we couldn't read
+}
+
+impl HighScores {
+	pub fn load(path: &str) -> HighScores {
+		match fs::read_to_string(path) {
+			Ok(text) => HighScores::parse(&text, path),
+			Err(_) => HighScores::default(),   // Nothing saved yet
+		}
+	}
+
+	fn parse(text: &str, path: &str) -> HighScores {
+		let mut scores = HighScores::default();
+		let mut lines = text.lines().enumerate();
+		let version = match lines.next().and_then(|(_, l)| l.strip_prefix("version ")).map(|v| v.trim().parse::<u32>()) {
+			Some(Ok(v)) => v,
+			_ => {
+				println!("{}: missing version line, not loading or saving high scores", path);
+				scores.read_only = true;
+				return scores;
+			},
+		};
+		if version > FORMAT_VERSION {
+			println!("{}: version {} is newer than this game understands, high scores won't be saved", path, version);
+			scores.read_only = true;
+			return scores;
+		}
+
+		// Every field so far has been there since version 1. Later versions should fill in defaults for old files here.
+		for (line_num, line) in lines {
+			if line.trim().is_empty() || line.starts_with('#') { continue }
+
+			let parts: Vec<&str> = line.split('\t').collect();
+			match parts.as_slice() {
+				[table, level, score, name, date, car, replay] => match score.parse() {
+					Ok(score) => scores.tables.entry((table.to_string(), level.to_string())).or_default().push(HighScore {
+						score,
+						name: name.to_string(),
+						date: date.to_string(),
+						car: car.to_string(),
+						replay: if *replay == NO_REPLAY { None } else { Some(replay.to_string()) },
+					}),
+					Err(_) => println!("{}: line {}: bad score", path, line_num + 1),
+				},
+				_ => println!("{}: line {}: expected 7 tab separated fields", path, line_num + 1),
+			}
+		}
+		scores
+	}
+
+	pub fn save(&self, path: &str) -> Result<(), String> {
+		if self.read_only {
+			return Err(format!("{}: not overwriting a file this version couldn't read", path));
+		}
+
+		misc::write_atomic(path, &self.to_text())
+	}
+
+	fn to_text(&self) -> String {
+		let mut keys: Vec<_> = self.tables.keys().collect();
+		keys.sort();
+
+		let mut text = format!("version {}\n# table\tlevel\tscore\tname\tdate\tcar\treplay\n", FORMAT_VERSION);
+		for key in keys {
+			for s in self.tables[key].iter() {
+				text += &format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+					clean(&key.0), clean(&key.1), s.score, clean(&s.name), s.date, clean(&s.car), s.replay.as_deref().map_or(NO_REPLAY.to_string(), clean));
+			}
+		}
+		text
+	}
+
+	#[inline]
+	pub fn get(&self, table: &str, level: &str) -> &[HighScore] {
+		self.tables.get(&(table.to_string(), level.to_string())).map_or(&[], |t| t.as_slice())
+	}
+
+	// Adds the score if it makes the table, returning its position
+	pub fn submit(&mut self, table: &str, level: &str, entry: HighScore, lower_is_better: bool) -> Option<usize> {
+		let scores = self.tables.entry((table.to_string(), level.to_string())).or_default();
+		let beats = |s: &HighScore| if lower_is_better { entry.score < s.score } else { entry.score > s.score };
+		let pos = scores.iter().position(beats).unwrap_or(scores.len());
+		if pos >= TABLE_SIZE { return None }
+
+		scores.insert(pos, entry);
+		scores.truncate(TABLE_SIZE);
+		Some(pos)
+	}
+}
+
+#[inline]
+fn clean(text: &str) -> String {   // Tabs and newlines would break the file
+	text.replace(['\t', '\n', '\r'], " ")
+}
+
+// Name for new entries until the player sets one, taken from the OS user
+pub fn get_default_name() -> String {
+	std::env::var("USER")
+		.or_else(|_| std::env::var("USERNAME"))
+		.ok()
+		.filter(|n| !n.trim().is_empty())
+		.unwrap_or_else(|| "Player".to_string())
+}
+
+#[inline]
+pub fn get_timestamp() -> u64 {   // Seconds since 1970
+	SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+pub fn get_today() -> String {
+	let (y, m, d) = civil_from_days((get_timestamp() / 86400) as i64);
+	format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+// Days since 1970-01-01 to a (year, month, day) date, from Howard Hinnant's date algorithms
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+	let z = days + 719468;
+	let era = z.div_euclid(146097);
+	let doe = z.rem_euclid(146097);
+	let yoe = (doe - doe/1460 + doe/36524 - doe/146096) / 365;
+	let doy = doe - (365*yoe + yoe/4 - yoe/100);
+	let mp = (5*doy + 2)/153;
+	let d = (doy - (153*mp + 2)/5 + 1) as u32;
+	let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+	(yoe + era * 400 + if m <= 2 { 1 } else { 0 }, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entry(score: u32, name: &str) -> HighScore {
+		HighScore { score, name: name.to_string(), date: "2024-01-02".to_string(), car: "Hatch".to_string(), replay: None }
+	}
+
+	#[test]
+	fn round_trips() {
+		let mut scores = HighScores::default();
+		scores.submit("free", "Proving Ground", entry(500, "Ann"), false);
+		scores.submit("free", "Proving Ground", HighScore { replay: Some("replays/1.replay".to_string()), ..entry(900, "Bo") }, false);
+		scores.submit("gymkhana", "Three Pillars", entry(41250, "Cy"), true);
+
+		let loaded = HighScores::parse(&scores.to_text(), "test");
+		let free = loaded.get("free", "Proving Ground");
+		assert_eq!(free.iter().map(|s| (s.score, s.name.as_str())).collect::<Vec<_>>(), vec![(900, "Bo"), (500, "Ann")]);
+		assert_eq!(free[0].replay.as_deref(), Some("replays/1.replay"));
+		assert_eq!(free[1].replay, None);
+		assert_eq!(loaded.get("gymkhana", "Three Pillars")[0].score, 41250);
+		assert!(!loaded.read_only);
+	}
+
+	#[test]
+	fn tabs_and_newlines_are_cleaned() {
+		let mut scores = HighScores::default();
+		scores.submit("free", "Bad\tlevel", entry(10, "Two\nlines"), false);
+
+		let text = scores.to_text();
+		assert_eq!(text.lines().count(), 3);
+		let loaded = HighScores::parse(&text, "test");
+		assert_eq!(loaded.get("free", "Bad level")[0].name, "Two lines");
+	}
+
+	#[test]
+	fn newer_or_unversioned_files_are_read_only() {
+		assert!(HighScores::parse(&format!("version {}\n", FORMAT_VERSION + 1), "test").read_only);
+		assert!(HighScores::parse("free\tLevel\t1\tAnn\t2024-01-02\tHatch\t-\n", "test").read_only);
+		assert!(HighScores::parse("version 1\nfree\tLevel\tnope\tAnn\t2024-01-02\tHatch\t-\n", "test").tables.is_empty());
+	}
+
+	#[test]
+	fn tables_keep_the_best() {
+		let mut scores = HighScores::default();
+		for i in 0..TABLE_SIZE as u32 {
+			scores.submit("time_trial", "Circuit", entry(1000 + i, "Ann"), true);
+		}
+		assert_eq!(scores.submit("time_trial", "Circuit", entry(2000, "Bo"), true), None);
+		assert_eq!(scores.submit("time_trial", "Circuit", entry(1003, "Bo"), true), Some(4));
+		assert_eq!(scores.get("time_trial", "Circuit").len(), TABLE_SIZE);
+		assert_eq!(scores.get("time_trial", "Circuit").last().unwrap().score, 1008);
+	}
+
+	#[test]
+	fn dates_from_days() {
+		assert_eq!(civil_from_days(0), (1970, 1, 1));
+		assert_eq!(civil_from_days(19723), (2024, 1, 1));
+		assert_eq!(civil_from_days(19782), (2024, 2, 29));
+	}
+}
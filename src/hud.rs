@@ -0,0 +1,264 @@
+use raylib::{math::{Vector2, Rectangle}, drawing::{RaylibDraw, RaylibDrawHandle}, color::Color, text::measure_text};
+
+use crate::{
+	misc::get_components,
+	tyre::{self, Tyre},
+	CHARCOAL, RED_1, RED_2,
+};
+
+const PIXELS_PER_METRE: f32 = 12.0;   // Car is 56 pixels long, so roughly 4.5m
+const KMH_PER_MPH: f32 = 1.609344;
+const SPEEDO_MAX_KMH: f32 = 200.0;
+const SPEEDO_START_ANGLE: f32 = 315.0;  // Degrees, in raylib's convention of 0 pointing down
+const SPEEDO_SWEEP: f32 = 270.0;
+const DRIFT_GAUGE_MAX_ANGLE: f32 = 90.0;
+
+const MARGIN: f32 = 0.02;        // Fraction of the screen height kept clear around the edges
+const POPUP_LIFESPAN: f32 = 1.2; // In seconds
+const POPUP_RISE: f32 = 60.0;    // Pixels a popup floats up over its life
+const TIME_WARNING: f32 = 10.0;  // Seconds left when a countdown turns red
+
+static PANEL_COLOR: Color = Color { r: 255, g: 255, b: 250, a: 170 };
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SpeedUnit {
+	Kmh,
+	Mph,
+}
+
+impl SpeedUnit {
+	pub fn from_name(name: &str) -> Option<SpeedUnit> {   // As written in the settings file
+		match name {
+			"kmh" => Some(SpeedUnit::Kmh),
+			"mph" => Some(SpeedUnit::Mph),
+			_ => None,
+		}
+	}
+
+	#[inline]
+	pub fn file_name(&self) -> &'static str {
+		match self {
+			SpeedUnit::Kmh => "kmh",
+			SpeedUnit::Mph => "mph",
+		}
+	}
+
+	#[inline]
+	pub fn label(&self) -> &'static str {
+		match self {
+			SpeedUnit::Kmh => "km/h",
+			SpeedUnit::Mph => "mph",
+		}
+	}
+
+	#[inline]
+	pub fn toggled(&self) -> SpeedUnit {
+		match self {
+			SpeedUnit::Kmh => SpeedUnit::Mph,
+			SpeedUnit::Mph => SpeedUnit::Kmh,
+		}
+	}
+
+	#[inline]
+	pub fn convert(&self, speed: f32) -> f32 {   // From pixels per second
+		match self {
+			SpeedUnit::Kmh => to_kmh(speed),
+			SpeedUnit::Mph => to_kmh(speed)/KMH_PER_MPH,
+		}
+	}
+}
+
+pub struct HudInfo<'a> {    // Everything the HUD shows, gathered from the game each frame
+	pub speed: f32,         // Pixels per second
+	pub units: SpeedUnit,
+	pub perp: f32,
+	pub score: u32,
+	pub best_score: u32,
+	pub combo_points: u32,
+	pub combo_multiplier: u32,
+	pub combo_grace: f32,   // 0 -> 1
+	pub time: f32,          // Seconds since the run started
+	pub time_limit: Option<f32>,   // Shows a countdown instead if set
+	pub objective: Option<String>, // What the mode wants the player to do next, under the timer
+	pub tyres: &'a [Tyre; 4],
+}
+
+pub struct PlayerPanel {   // One split screen view's player
+	pub rect: Rectangle,   // Their view, on screen
+	pub label: String,
+	pub colour: Color,
+	pub score: u32,
+	pub combo_points: u32,
+	pub combo_multiplier: u32,
+	pub speed: f32,
+}
+
+#[derive(Clone)]
+struct Popup {
+	text: String,
+	colour: Color,
+	age: f32,
+}
+
+#[derive(Default, Clone)]
+pub struct Hud {
+	popups: Vec<Popup>,
+	combo_pulse: f32,   // Makes the combo text swell when the multiplier goes up, decays to 0
+}
+
+impl Hud {
+	pub fn push_popup(&mut self, text: String, colour: Color) {
+		self.popups.push(Popup { text, colour, age: 0.0 });
+	}
+
+	#[inline]
+	pub fn pulse_combo(&mut self) {
+		self.combo_pulse = 1.0;
+	}
+
+	pub fn update(&mut self, dt: f32) {
+		for p in self.popups.iter_mut() {
+			p.age += dt;
+		}
+		self.popups.retain(|p| p.age < POPUP_LIFESPAN);
+		self.combo_pulse = (self.combo_pulse - dt * 3.0).max(0.0);
+	}
+
+	pub fn draw(&self, d: &mut RaylibDrawHandle, info: &HudInfo, ui_scale: f32) {
+		let w = d.get_screen_width() as f32;
+		let h = d.get_screen_height() as f32;
+		let unit = h/800.0 * ui_scale;   // Everything is sized relative to an 800 pixel tall window
+		let margin = h * MARGIN;
+		let font = |size: f32| (size * unit) as i32;
+
+		// Score and best, top centre
+		let score_text = format!("{}", info.score);
+		draw_text_centred(d, &score_text, w/2.0, margin, font(36.0), RED_1);
+		draw_text_centred(d, &format!("Best {}", info.best_score), w/2.0, margin + 40.0 * unit, font(18.0), CHARCOAL);
+
+		self.draw_timer(d, info, w, margin, unit);
+
+		// Combo, under the score
+		if info.combo_points > 0 {
+			let size = 28.0 * (1.0 + self.combo_pulse * 0.4);
+			let y = margin + 70.0 * unit;
+			let combo_text = format!("+{} x{}", info.combo_points, info.combo_multiplier);
+			draw_text_centred(d, &combo_text, w/2.0, y, font(size), RED_2);
+
+			let bar_w = 120.0 * unit;
+			let bar = Rectangle::new(w/2.0 - bar_w/2.0, y + size * unit + 6.0 * unit, bar_w * info.combo_grace, 4.0 * unit);
+			d.draw_rectangle_rec(bar, RED_2);
+		}
+
+		for p in self.popups.iter() {
+			let t = p.age/POPUP_LIFESPAN;
+			let mut col = p.colour;
+			col.a = ((1.0 - t) * 255.0) as u8;
+			draw_text_centred(d, &p.text, w/2.0, h * 0.3 - t * POPUP_RISE * unit, font(32.0), col);
+		}
+
+		let dial_r = 70.0 * unit;
+		self.draw_speedometer(d, Vector2::new(w - margin - dial_r, h - margin - dial_r), dial_r, info.speed, info.units, unit);
+		self.draw_drift_gauge(d, Vector2::new(w/2.0, h - margin), dial_r, info.perp, unit);
+
+		tyre::draw_tyre_state(d, info.tyres, Vector2::new(margin, h - margin - 64.0 * unit), unit);
+	}
+
+	// Split screen: each player's score and combo in the corner of their view, with the timer and
+	// player one's popups over the whole screen
+	pub fn draw_split(&self, d: &mut RaylibDrawHandle, info: &HudInfo, panels: &[PlayerPanel], ui_scale: f32) {
+		let w = d.get_screen_width() as f32;
+		let h = d.get_screen_height() as f32;
+		let unit = h/800.0 * ui_scale;
+		let margin = h * MARGIN;
+		let font = |size: f32| (size * unit) as i32;
+
+		for p in panels.iter() {
+			let (x, y) = (p.rect.x + margin, p.rect.y + margin);
+			let panel = Rectangle::new(x, y, 180.0 * unit, if p.combo_points > 0 { 74.0 } else { 44.0 } * unit);
+			d.draw_rectangle_rec(panel, PANEL_COLOR);
+			d.draw_text(&p.label, (x + 6.0 * unit) as i32, (y + 6.0 * unit) as i32, font(16.0), p.colour);
+			let score = format!("{}", p.score);
+			let sw = measure_text(&score, font(28.0)) as f32;
+			d.draw_text(&score, (x + panel.width - 6.0 * unit - sw) as i32, (y + 8.0 * unit) as i32, font(28.0), p.colour);
+			if p.combo_points > 0 {
+				d.draw_text(&format!("+{} x{}", p.combo_points, p.combo_multiplier), (x + 6.0 * unit) as i32, (y + 44.0 * unit) as i32, font(22.0), RED_2);
+			}
+			let speed = format!("{:.0} {}", info.units.convert(p.speed), info.units.label());
+			let spw = measure_text(&speed, font(18.0)) as f32;
+			d.draw_text(&speed, (p.rect.x + p.rect.width - margin - spw) as i32, (p.rect.y + p.rect.height - margin - 18.0 * unit) as i32, font(18.0), CHARCOAL);
+		}
+
+		self.draw_timer(d, info, w, margin, unit);
+		for p in self.popups.iter() {
+			let t = p.age/POPUP_LIFESPAN;
+			let mut col = p.colour;
+			col.a = ((1.0 - t) * 255.0) as u8;
+			if let Some(first) = panels.first() {
+				draw_text_centred(d, &p.text, first.rect.x + first.rect.width/2.0, first.rect.y + first.rect.height * 0.3 - t * POPUP_RISE * unit, font(32.0), col);
+			}
+		}
+	}
+
+	// Top right, with the objective under it
+	fn draw_timer(&self, d: &mut RaylibDrawHandle, info: &HudInfo, w: f32, margin: f32, unit: f32) {
+		let font = |size: f32| (size * unit) as i32;
+		let (time, time_col) = match info.time_limit {
+			Some(limit) => {
+				let left = (limit - info.time).max(0.0);
+				(left, if left < TIME_WARNING { RED_1 } else { CHARCOAL })
+			},
+			None => (info.time, CHARCOAL),
+		};
+		let mins = (time/60.0) as u32;
+		let time_text = format!("{}:{:05.2}", mins, time - mins as f32 * 60.0);
+		let tw = measure_text(&time_text, font(28.0)) as f32;
+		d.draw_text(&time_text, (w - margin - tw) as i32, margin as i32, font(28.0), time_col);
+		if let Some(objective) = &info.objective {
+			let ow = measure_text(objective, font(20.0)) as f32;
+			d.draw_text(objective, (w - margin - ow) as i32, (margin + 34.0 * unit) as i32, font(20.0), CHARCOAL);
+		}
+	}
+
+	fn draw_speedometer(&self, d: &mut RaylibDrawHandle, centre: Vector2, r: f32, speed: f32, units: SpeedUnit, unit: f32) {
+		let frac = (to_kmh(speed)/SPEEDO_MAX_KMH).min(1.0);
+
+		d.draw_circle_v(centre, r, PANEL_COLOR);
+		d.draw_ring(centre, r * 0.85, r * 0.95, SPEEDO_START_ANGLE - SPEEDO_SWEEP, SPEEDO_START_ANGLE, 40, Color::new(38, 38, 38, 60));
+		d.draw_ring(centre, r * 0.85, r * 0.95, SPEEDO_START_ANGLE - SPEEDO_SWEEP * frac, SPEEDO_START_ANGLE, 40, RED_2);
+
+		let needle_ang = (SPEEDO_START_ANGLE - SPEEDO_SWEEP * frac).to_radians();
+		d.draw_line_ex(centre, centre + get_components(r * 0.8, needle_ang), 3.0 * unit, RED_1);
+		d.draw_circle_v(centre, 5.0 * unit, CHARCOAL);
+
+		draw_text_centred(d, &format!("{:.0}", units.convert(speed)), centre.x, centre.y + r * 0.25, (26.0 * unit) as i32, CHARCOAL);
+		draw_text_centred(d, units.label(), centre.x, centre.y + r * 0.55, (14.0 * unit) as i32, CHARCOAL);
+	}
+
+	fn draw_drift_gauge(&self, d: &mut RaylibDrawHandle, bottom_centre: Vector2, r: f32, perp: f32, unit: f32) {
+		// Half dial over the top, straight up is no drift angle
+		let drift_angle = perp.clamp(-1.0, 1.0).asin().to_degrees();
+		let frac = drift_angle/DRIFT_GAUGE_MAX_ANGLE;
+		let col = if drift_angle.abs() > 20.0 { RED_2 } else { CHARCOAL };
+
+		d.draw_circle_sector(bottom_centre, r, 90.0, 270.0, 30, PANEL_COLOR);
+		d.draw_ring(bottom_centre, r * 0.85, r * 0.95, 90.0, 270.0, 30, Color::new(38, 38, 38, 60));
+		let (from, to) = if frac > 0.0 { (180.0 - 90.0 * frac, 180.0) } else { (180.0, 180.0 - 90.0 * frac) };
+		d.draw_ring(bottom_centre, r * 0.85, r * 0.95, from, to, 30, col);
+
+		let needle_ang = (180.0 - 90.0 * frac).to_radians();
+		d.draw_line_ex(bottom_centre, bottom_centre + get_components(r * 0.8, needle_ang), 3.0 * unit, RED_1);
+
+		draw_text_centred(d, &format!("{:.0}", drift_angle.abs()), bottom_centre.x, bottom_centre.y - r * 0.45, (22.0 * unit) as i32, col);
+	}
+}
+
+#[inline]
+pub fn to_kmh(speed: f32) -> f32 {   // From pixels per second
+	speed/PIXELS_PER_METRE * 3.6
+}
+
+pub fn draw_text_centred(d: &mut RaylibDrawHandle, text: &str, x: f32, y: f32, size: i32, col: Color) {
+	let w = measure_text(text, size);
+	d.draw_text(text, x as i32 - w/2, y as i32, size, col);
+}
@@ -0,0 +1,40 @@
+// What the driver wants the car to do on one tick. Comes from the keyboard when playing and from
+// the file when watching a replay, so the simulation never reads keys itself.
+
+use raylib::RaylibHandle;
+
+use crate::settings::Controls;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Input {
+	pub accelerate: bool,
+	pub brake: bool,
+	pub steer_left: bool,
+	pub steer_right: bool,
+}
+
+impl Input {
+	pub fn from_keys(rl: &RaylibHandle, controls: &Controls) -> Input {
+		Input {
+			accelerate: rl.is_key_down(controls.accelerate),
+			brake: rl.is_key_down(controls.brake),
+			steer_left: rl.is_key_down(controls.steer_left),
+			steer_right: rl.is_key_down(controls.steer_right),
+		}
+	}
+
+	#[inline]
+	pub fn to_bits(self) -> u8 {   // For saving in replays
+		self.accelerate as u8 | (self.brake as u8) << 1 | (self.steer_left as u8) << 2 | (self.steer_right as u8) << 3
+	}
+
+	#[inline]
+	pub fn from_bits(bits: u8) -> Input {
+		Input {
+			accelerate: bits & 1 != 0,
+			brake: bits & 2 != 0,
+			steer_left: bits & 4 != 0,
+			steer_right: bits & 8 != 0,
+		}
+	}
+}
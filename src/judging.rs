@@ -0,0 +1,287 @@
+// Competition style judging. Levels mark clipping zones, and each pass through one is scored on
+// line (how close the car got to the clip), angle (how far sideways it was) and style (entry speed
+// and how committed the drift was). The run's score out of 100 is the average of each zone's best pass.
+
+use raylib::{math::Vector2, drawing::RaylibDraw, color::Color};
+
+use crate::car::Car;
+
+const IDEAL_ANGLE: f32 = 45.0;       // Drift angle in degrees that gets full angle marks
+const STYLE_FULL_SPEED: f32 = 350.0; // Entry speed (pixels per second) that gets full speed marks
+const LINE_WEIGHT: f32 = 0.4;
+const ANGLE_WEIGHT: f32 = 0.3;
+const STYLE_WEIGHT: f32 = 0.3;
+
+static ZONE_COLOR: Color = Color { r: 70, g: 110, b: 200, a: 45 };
+static CLIP_COLOR: Color = Color { r: 70, g: 110, b: 200, a: 200 };
+
+#[derive(Clone, Debug)]
+pub enum ClipShape {
+	Point { pos: Vector2, radius: f32 },   // Inner clip, judged on how close the car's centre gets to it
+	Area(Vec<Vector2>),                    // Outer zone, judged on how close the rear gets to the first edge (e.g. a wall). Must be convex.
+}
+
+#[derive(Clone, Debug)]
+pub struct ClipZone {
+	pub shape: ClipShape,
+}
+
+impl ClipZone {
+	pub fn point(pos: Vector2, radius: f32) -> ClipZone {
+		ClipZone { shape: ClipShape::Point { pos, radius } }
+	}
+
+	pub fn area(mut points: Vec<Vector2>) -> ClipZone {
+		// Raylib wants triangle fans anticlockwise on screen, which is a negative signed area with y pointing down
+		let signed_area: f32 = points.iter().zip(points.iter().cycle().skip(1))
+			.map(|(a, b)| a.x * b.y - b.x * a.y)
+			.sum();
+		if signed_area > 0.0 {
+			// Reverse, then rotate so the first edge is still the clipping edge
+			let n = points.len();
+			points.reverse();
+			points.rotate_left(n - 2);
+		}
+		ClipZone { shape: ClipShape::Area(points) }
+	}
+
+	#[inline]
+	fn get_judged_point(&self, car: &Car) -> Vector2 {
+		match self.shape {
+			ClipShape::Point { .. } => car.pos,
+			ClipShape::Area(_) => car.get_rear_pos(),
+		}
+	}
+
+	fn contains(&self, point: Vector2) -> bool {
+		match &self.shape {
+			ClipShape::Point { pos, radius } => point.distance_to(*pos) <= *radius,
+			ClipShape::Area(points) => {
+				// Even-odd ray cast
+				let mut inside = false;
+				for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+					if (a.y > point.y) != (b.y > point.y) && point.x < a.x + (point.y - a.y)/(b.y - a.y) * (b.x - a.x) {
+						inside = !inside;
+					}
+				}
+				inside
+			},
+		}
+	}
+
+	fn get_line_score(&self, point: Vector2) -> f32 {   // 1 on the clip, falling to 0 at the far side of the zone
+		match &self.shape {
+			ClipShape::Point { pos, radius } => 1.0 - (point.distance_to(*pos)/radius).min(1.0),
+			ClipShape::Area(points) => {
+				let (a, b) = (points[0], points[1]);
+				let depth = points.iter().map(|p| dist_to_line(*p, a, b)).fold(0.0, f32::max);
+				if depth <= 0.0 { return 0.0 }
+				1.0 - (dist_to_line(point, a, b)/depth).min(1.0)
+			},
+		}
+	}
+
+	pub fn draw<D: RaylibDraw>(&self, d: &mut D) {
+		match &self.shape {
+			ClipShape::Point { pos, radius } => {
+				d.draw_circle_v(*pos, *radius, ZONE_COLOR);
+				d.draw_circle_v(*pos, 4.0, CLIP_COLOR);
+			},
+			ClipShape::Area(points) => {
+				d.draw_triangle_fan(points, ZONE_COLOR);
+				d.draw_line_ex(points[0], points[1], 4.0, CLIP_COLOR);
+			},
+		}
+	}
+}
+
+#[inline]
+fn dist_to_line(p: Vector2, a: Vector2, b: Vector2) -> f32 {   // To the infinite line through a and b
+	let ab = b - a;
+	let len = ab.length();
+	if len <= 0.0 { return p.distance_to(a) }
+	(ab.x * (p.y - a.y) - ab.y * (p.x - a.x)).abs()/len
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ZoneScore {   // Each part 0 -> 1
+	pub line: f32,
+	pub angle: f32,
+	pub style: f32,
+}
+
+impl ZoneScore {
+	#[inline]
+	pub fn get_total(&self) -> f32 {   // Out of 100
+		(self.line * LINE_WEIGHT + self.angle * ANGLE_WEIGHT + self.style * STYLE_WEIGHT) * 100.0
+	}
+}
+
+#[derive(Default, Clone)]
+struct ZonePass {    // Built up while the car is inside a zone
+	time: f32,
+	drift_time: f32,
+	angle_time: f32,   // Drift angle integrated over time, for the average
+	entry_speed: f32,
+	best_line: f32,
+}
+
+impl ZonePass {
+	fn get_score(&self) -> ZoneScore {
+		let avg_angle = if self.time > 0.0 { self.angle_time/self.time } else { 0.0 };
+		let speed = (self.entry_speed/STYLE_FULL_SPEED).min(1.0);
+		let commitment = if self.time > 0.0 { self.drift_time/self.time } else { 0.0 };
+		ZoneScore {
+			line: self.best_line,
+			angle: (1.0 - (avg_angle - IDEAL_ANGLE).abs()/IDEAL_ANGLE).max(0.0),
+			style: 0.5 * speed + 0.5 * commitment,
+		}
+	}
+}
+
+#[derive(Clone)]
+pub struct Judge {
+	passes: Vec<Option<ZonePass>>,     // Per zone, while the car is in it
+	pub best: Vec<Option<ZoneScore>>,  // Per zone, None if never driven through
+}
+
+impl Judge {
+	pub fn new(zone_count: usize) -> Judge {
+		Judge {
+			passes: (0..zone_count).map(|_| None).collect(),
+			best: vec![None; zone_count],
+		}
+	}
+
+	// Returns each zone the car left this tick, with its score
+	pub fn update(&mut self, dt: f32, car: &Car, zones: &[ClipZone]) -> Vec<(usize, ZoneScore)> {
+		let drift_angle = car.perp.abs().min(1.0).asin().to_degrees();
+		let mut finished = vec![];
+
+		for (i, zone) in zones.iter().enumerate().take(self.passes.len()) {
+			let point = zone.get_judged_point(car);
+			let pass = &mut self.passes[i];
+			if zone.contains(point) {
+				let p = pass.get_or_insert_with(|| ZonePass { entry_speed: car.vel_mag, ..Default::default() });
+				p.time += dt;
+				p.angle_time += drift_angle * dt;
+				if car.drifting {
+					p.drift_time += dt;
+				}
+				p.best_line = p.best_line.max(zone.get_line_score(point));
+			} else if let Some(p) = pass.take() {
+				let score = p.get_score();
+				self.record(i, score);
+				finished.push((i, score));
+			}
+		}
+		finished
+	}
+
+	// Judges any zone the car is still inside when the run ends
+	pub fn finish(&mut self) {
+		for i in 0..self.passes.len() {
+			if let Some(p) = self.passes[i].take() {
+				self.record(i, p.get_score());
+			}
+		}
+	}
+
+	#[inline]
+	fn record(&mut self, zone: usize, score: ZoneScore) {
+		if self.best[zone].map_or(true, |b| score.get_total() > b.get_total()) {
+			self.best[zone] = Some(score);
+		}
+	}
+
+	pub fn get_total(&self) -> f32 {   // Out of 100, zones never driven through count as 0
+		if self.best.is_empty() { return 0.0 }
+		self.best.iter().map(|s| s.map_or(0.0, |s| s.get_total())).sum::<f32>()/self.best.len() as f32
+	}
+
+	pub fn get_breakdown(&self) -> Vec<String> {
+		let mut lines = vec![format!("Judges' score: {:.0}/100", self.get_total())];
+		for (i, s) in self.best.iter().enumerate() {
+			lines.push(match s {
+				Some(s) => format!("Zone {}: {:.0}  (line {:.0}%, angle {:.0}%, style {:.0}%)", i + 1, s.get_total(), s.line * 100.0, s.angle * 100.0, s.style * 100.0),
+				None => format!("Zone {}: missed", i + 1),
+			});
+		}
+		lines
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{car_spec::CarSpec, particle_pool::DEF_PARTICLE_BUDGET};
+
+	const DT: f32 = 0.1;
+
+	fn get_car(pos: Vector2) -> Car {
+		let mut car = Car::new(&CarSpec::default(), pos, 0.0, 1, DEF_PARTICLE_BUDGET);
+		car.vel_mag = STYLE_FULL_SPEED;
+		car.perp = IDEAL_ANGLE.to_radians().sin();
+		car.drifting = true;
+		car
+	}
+
+	#[test]
+	fn perfect_pass_scores_full_marks() {
+		let zones = [ClipZone::point(Vector2::zero(), 50.0)];
+		let mut judge = Judge::new(1);
+		assert!(judge.update(DT, &get_car(Vector2::zero()), &zones).is_empty());
+		let left = judge.update(DT, &get_car(Vector2::new(100.0, 0.0)), &zones);
+		assert_eq!(left.len(), 1);
+		assert!((left[0].1.get_total() - 100.0).abs() < 0.01);
+		assert!((judge.get_total() - 100.0).abs() < 0.01);
+	}
+
+	#[test]
+	fn leaving_two_zones_at_once_scores_both() {
+		let zones = [ClipZone::point(Vector2::zero(), 50.0), ClipZone::point(Vector2::new(40.0, 0.0), 50.0)];
+		let mut judge = Judge::new(2);
+		judge.update(DT, &get_car(Vector2::new(20.0, 0.0)), &zones);
+		let left = judge.update(DT, &get_car(Vector2::new(500.0, 0.0)), &zones);
+		assert_eq!(left.iter().map(|(i, _)| *i).collect::<Vec<_>>(), vec![0, 1]);
+		assert!(judge.best.iter().all(|b| b.is_some()));
+	}
+
+	#[test]
+	fn keeps_the_best_pass_and_counts_missed_zones_as_nothing() {
+		let zones = [ClipZone::point(Vector2::zero(), 50.0), ClipZone::point(Vector2::new(1000.0, 0.0), 50.0)];
+		let mut judge = Judge::new(2);
+		let outside = get_car(Vector2::new(500.0, 0.0));
+		for pos in [Vector2::zero(), Vector2::new(25.0, 0.0)] {
+			judge.update(DT, &get_car(pos), &zones);
+			judge.update(DT, &outside, &zones);
+		}
+		let best = judge.best[0].unwrap();
+		assert!((best.line - 1.0).abs() < 0.01);
+		assert!(judge.best[1].is_none());
+		assert!((judge.get_total() - best.get_total()/2.0).abs() < 0.01);
+	}
+
+	#[test]
+	fn finish_judges_zones_still_being_driven() {
+		let zones = [ClipZone::point(Vector2::zero(), 50.0)];
+		let mut judge = Judge::new(1);
+		judge.update(DT, &get_car(Vector2::zero()), &zones);
+		judge.finish();
+		assert!(judge.best[0].is_some());
+	}
+
+	#[test]
+	fn areas_work_either_way_round() {
+		let square = vec![Vector2::new(0.0, 0.0), Vector2::new(100.0, 0.0), Vector2::new(100.0, 100.0), Vector2::new(0.0, 100.0)];
+		let mut reversed = square.clone();
+		reversed.reverse();
+		reversed.rotate_left(2);   // Still starting on the top edge
+		for zone in [ClipZone::area(square), ClipZone::area(reversed)] {
+			assert!(zone.contains(Vector2::new(50.0, 50.0)));
+			assert!(!zone.contains(Vector2::new(150.0, 50.0)));
+			assert!((zone.get_line_score(Vector2::new(50.0, 0.0)) - 1.0).abs() < 0.01);   // On the clipping edge
+			assert!(zone.get_line_score(Vector2::new(50.0, 100.0)).abs() < 0.01);
+		}
+	}
+}
@@ -0,0 +1,131 @@
+// Reading of the simple `key = value` text files used for emitters, levels and cars.
+// Anything after a # is a comment, and keys can be repeated where it makes sense.
+
+use std::{fs, path::PathBuf};
+
+use raylib::color::Color;
+
+pub struct Entry<'a> {
+	pub line_num: usize,
+	pub key: &'a str,
+	pub value: &'a str,
+}
+
+pub fn entries(text: &str) -> impl Iterator<Item = Result<Entry<'_>, String>> {
+	text.lines().enumerate().filter_map(|(i, line)| {
+		let line = line.split('#').next().unwrap().trim();   // Strip comments
+		if line.is_empty() { return None }
+
+		let mut parts = line.splitn(2, '=');
+		let key = parts.next().unwrap().trim();
+		Some(match parts.next() {
+			Some(value) => Ok(Entry { line_num: i + 1, key, value: value.trim() }),
+			None => Err(format!("line {}: expected `key = value`", i + 1)),
+		})
+	})
+}
+
+impl<'a> Entry<'a> {
+	#[inline]
+	pub fn err(&self, msg: &str) -> String {
+		format!("line {}: {}", self.line_num, msg)
+	}
+
+	pub fn nums(&self) -> Result<Vec<f32>, String> {
+		self.value.split_whitespace()
+			.map(|v| v.parse::<f32>().map_err(|_| self.err(&format!("`{}` is not a number", v))))
+			.collect()
+	}
+
+	pub fn single(&self) -> Result<f32, String> {
+		match self.nums()?.as_slice() {
+			[a] => Ok(*a),
+			_ => Err(self.err("expected a single number")),
+		}
+	}
+
+	pub fn pair(&self) -> Result<(f32, f32), String> {   // One number is used for both
+		match self.nums()?.as_slice() {
+			[a] => Ok((*a, *a)),
+			[a, b] => Ok((*a, *b)),
+			_ => Err(self.err("expected one or two numbers")),
+		}
+	}
+
+	pub fn bool(&self) -> Result<bool, String> {
+		match self.value {
+			"true" => Ok(true),
+			"false" => Ok(false),
+			_ => Err(self.err("expected `true` or `false`")),
+		}
+	}
+
+	pub fn colour(&self) -> Result<Color, String> {
+		match self.nums()?.as_slice() {
+			[r, g, b] => Ok(Color::new(*r as u8, *g as u8, *b as u8, 255)),
+			[r, g, b, a] => Ok(Color::new(*r as u8, *g as u8, *b as u8, *a as u8)),
+			_ => Err(self.err("colour must be `<r> <g> <b>` or `<r> <g> <b> <a>`")),
+		}
+	}
+
+	// First word of the value, and the rest parsed as numbers. For things like `surface = ice 0 0 100 100`
+	pub fn word_and_nums(&self) -> Result<(&'a str, Vec<f32>), String> {
+		let mut words = self.value.splitn(2, char::is_whitespace);
+		let word = words.next().unwrap();
+		let nums = words.next().unwrap_or("").split_whitespace()
+			.map(|v| v.parse::<f32>().map_err(|_| self.err(&format!("`{}` is not a number", v))))
+			.collect::<Result<Vec<f32>, String>>()?;
+		Ok((word, nums))
+	}
+}
+
+// Every file in the folder with the extension, sorted by name so files can be ordered with a number prefix
+pub fn list_files(dir: &str, extension: &str) -> Vec<PathBuf> {
+	let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+		Ok(entries) => entries.filter_map(|e| e.ok())
+			.map(|e| e.path())
+			.filter(|p| p.extension().is_some_and(|ext| ext == extension))
+			.collect(),
+		Err(e) => {
+			println!("Couldn't read {}: {}", dir, e);
+			vec![]
+		},
+	};
+	paths.sort();
+	paths
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn skips_comments_and_blank_lines() {
+		let text = "# header\n\nname = Test level # trailing\n  size=1000 800  \n";
+		let entries: Vec<Entry> = entries(text).collect::<Result<_, _>>().unwrap();
+		assert_eq!(entries.len(), 2);
+		assert_eq!((entries[0].line_num, entries[0].key, entries[0].value), (3, "name", "Test level"));
+		assert_eq!((entries[1].line_num, entries[1].key, entries[1].value), (4, "size", "1000 800"));
+	}
+
+	#[test]
+	fn missing_equals_is_an_error() {
+		let results: Vec<_> = entries("a = 1\nbroken\n").collect();
+		assert!(results[0].is_ok());
+		assert_eq!(results[1].as_ref().err().unwrap(), "line 2: expected `key = value`");
+	}
+
+	#[test]
+	fn values() {
+		let entry = |value| Entry { line_num: 7, key: "k", value };
+		assert_eq!(entry("1 2.5").nums(), Ok(vec![1.0, 2.5]));
+		assert_eq!(entry("4").pair(), Ok((4.0, 4.0)));
+		assert_eq!(entry("1 2 3").pair(), Err("line 7: expected one or two numbers".to_string()));
+		assert_eq!(entry("x").single(), Err("line 7: `x` is not a number".to_string()));
+		assert_eq!(entry("true").bool(), Ok(true));
+		assert!(entry("yes").bool().is_err());
+		assert_eq!(entry("10 20 30").colour(), Ok(Color::new(10, 20, 30, 255)));
+		assert_eq!(entry("ice 0 0 100 50").word_and_nums(), Ok(("ice", vec![0.0, 0.0, 100.0, 50.0])));
+		assert_eq!(entry("point").word_and_nums(), Ok(("point", vec![])));
+	}
+}
@@ -0,0 +1,243 @@
+// Lap timing for closed circuits. A lap starts and ends at the start line, and must cross every
+// checkpoint in order on the way round or it doesn't count. The finish line only counts once the
+// last checkpoint is behind the car.
+
+use raylib::{math::Vector2, drawing::RaylibDraw, color::Color};
+
+use crate::{CHARCOAL, RED_2};
+
+pub const DEF_LAP_COUNT: u32 = 3;
+
+static GATE_COLOR: Color = Color { r: 38, g: 38, b: 38, a: 90 };
+
+#[derive(Clone, Copy, Debug)]
+pub struct Gate {    // Line the car has to drive across, with `from` on its left
+	pub from: Vector2,
+	pub to: Vector2,
+}
+
+impl Gate {
+	pub fn new(from: Vector2, to: Vector2) -> Gate {
+		Gate { from, to }
+	}
+
+	// Only counts going forwards. Ending a tick exactly on the line counts as across, so a car stopped
+	// there has to back off over it again before it can cross a second time.
+	fn is_crossed(&self, prev_pos: Vector2, pos: Vector2) -> bool {
+		let dir = self.to - self.from;
+		let side = |p: Vector2| cross(dir, p - self.from);   // Positive before the line
+		let (before, after) = (side(prev_pos), side(pos));
+		if before <= 0.0 || after > 0.0 { return false }
+
+		// Where the car's path meets the line, as a fraction of the way along the gate
+		let hit = prev_pos + (pos - prev_pos).scale_by(before/(before - after));
+		let along = (hit - self.from).dot(dir)/dir.dot(dir);
+		(0.0..=1.0).contains(&along)
+	}
+}
+
+#[inline]
+fn cross(a: Vector2, b: Vector2) -> f32 {
+	a.x * b.y - a.y * b.x
+}
+
+#[derive(Clone, Debug)]
+pub struct Lap {
+	pub time: f32,
+	pub splits: Vec<f32>,   // Time into the lap at each checkpoint
+	pub valid: bool,
+}
+
+pub enum LapEvent {
+	Started,
+	Sector(Option<f32>),      // Delta to the best lap at this checkpoint, if there is one
+	Finished(Lap, bool),      // The lap, and whether it's a new best
+	Invalidated,
+}
+
+pub struct LapTimer {
+	pub laps: Vec<Lap>,       // Finished laps this run
+	pub best: Option<Lap>,
+	pub lap_time: f32,
+	pub delta: Option<f32>,   // To the best lap, as of the last checkpoint
+	started: bool,
+	splits: Vec<f32>,
+	valid: bool,
+}
+
+impl LapTimer {
+	pub fn new(best: Option<Lap>) -> LapTimer {
+		LapTimer {
+			laps: vec![],
+			best,
+			lap_time: 0.0,
+			delta: None,
+			started: false,
+			splits: vec![],
+			valid: true,
+		}
+	}
+
+	#[inline]
+	pub fn get_next_checkpoint(&self) -> usize {
+		self.splits.len()
+	}
+
+	pub fn update(&mut self, dt: f32, prev_pos: Vector2, pos: Vector2, start: &Gate, checkpoints: &[Gate]) -> Option<LapEvent> {
+		if self.started {
+			self.lap_time += dt;
+		}
+
+		if start.is_crossed(prev_pos, pos) {
+			if !self.started {
+				self.started = true;
+				return Some(LapEvent::Started);
+			}
+
+			if self.splits.len() == checkpoints.len() {
+				let lap = Lap { time: self.lap_time, splits: self.splits.clone(), valid: self.valid };
+				let is_best = lap.valid && self.best.as_ref().map_or(true, |b| lap.time < b.time);
+				if is_best {
+					self.best = Some(lap.clone());
+				}
+				self.laps.push(lap.clone());
+
+				self.lap_time = 0.0;
+				self.splits.clear();
+				self.valid = true;
+				self.delta = None;
+				return Some(LapEvent::Finished(lap, is_best));
+			}
+		}
+
+		if !self.started { return None }
+
+		for (i, gate) in checkpoints.iter().enumerate() {
+			if !gate.is_crossed(prev_pos, pos) { continue }
+
+			let next = self.get_next_checkpoint();
+			if i == next {
+				self.splits.push(self.lap_time);
+				self.delta = self.best.as_ref().filter(|b| b.splits.len() > i).map(|b| self.lap_time - b.splits[i]);
+				return Some(LapEvent::Sector(self.delta));
+			} else if i > next {
+				// Missed some out, so carry on from here but the lap won't count
+				self.splits.resize(i + 1, self.lap_time);
+				self.delta = None;
+				if self.valid {
+					self.valid = false;
+					return Some(LapEvent::Invalidated);
+				}
+			}
+		}
+		None
+	}
+
+	pub fn get_objective(&self, lap_count: u32) -> String {
+		if !self.started { return "Cross the start line".to_string() }
+
+		let mut text = format!("Lap {}/{}  {:.2}", self.laps.len() + 1, lap_count, self.lap_time);
+		if !self.valid {
+			text += "  invalid";
+		} else if let Some(delta) = self.delta {
+			text += &format!("  {:+.2}", delta);
+		}
+		text
+	}
+
+	pub fn get_breakdown(&self) -> Vec<String> {
+		let mut lines: Vec<String> = self.laps.iter().enumerate().map(|(i, lap)| {
+			let sectors: Vec<String> = lap.splits.iter()
+				.chain(std::iter::once(&lap.time))
+				.scan(0.0, |last, t| {
+					let s = format!("{:.2}", t - *last);
+					*last = *t;
+					Some(s)
+				})
+				.collect();
+			format!("Lap {}: {:.2}{}  ({})", i + 1, lap.time, if lap.valid { "" } else { " invalid" }, sectors.join(" / "))
+		}).collect();
+		lines.push(match &self.best {
+			Some(b) => format!("Best lap: {:.2}", b.time),
+			None => "No valid laps".to_string(),
+		});
+		lines
+	}
+
+	pub fn draw<D: RaylibDraw>(&self, d: &mut D, start: &Gate, checkpoints: &[Gate]) {
+		d.draw_line_ex(start.from, start.to, 6.0, CHARCOAL);
+		for (i, gate) in checkpoints.iter().enumerate() {
+			let col = if self.started && i == self.get_next_checkpoint() { RED_2 } else { GATE_COLOR };
+			d.draw_line_ex(gate.from, gate.to, 3.0, col);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn v(x: f32, y: f32) -> Vector2 {
+		Vector2::new(x, y)
+	}
+
+	// A square lap driven anticlockwise on screen: east along the bottom, then up, west and down
+	fn course() -> (Gate, Vec<Gate>) {
+		let start = Gate::new(v(50.0, 50.0), v(50.0, 100.0));
+		let checkpoints = vec![Gate::new(v(100.0, 0.0), v(150.0, 0.0)), Gate::new(v(0.0, -50.0), v(0.0, -100.0))];
+		(start, checkpoints)
+	}
+
+	fn drive(timer: &mut LapTimer, path: &[Vector2]) -> Vec<LapEvent> {
+		let (start, checkpoints) = course();
+		path.windows(2).filter_map(|w| timer.update(1.0, w[0], w[1], &start, &checkpoints)).collect()
+	}
+
+	const LAP: [Vector2; 7] = [
+		Vector2 { x: 0.0, y: 75.0 }, Vector2 { x: 100.0, y: 75.0 }, Vector2 { x: 125.0, y: -75.0 },
+		Vector2 { x: 50.0, y: -75.0 }, Vector2 { x: -25.0, y: -75.0 }, Vector2 { x: -25.0, y: 75.0 }, Vector2 { x: 100.0, y: 75.0 },
+	];
+
+	#[test]
+	fn gate_only_counts_forwards() {
+		let gate = Gate::new(v(0.0, -10.0), v(0.0, 10.0));
+		assert!(gate.is_crossed(v(-5.0, 0.0), v(5.0, 0.0)));
+		assert!(!gate.is_crossed(v(5.0, 0.0), v(-5.0, 0.0)));
+		assert!(!gate.is_crossed(v(-5.0, 20.0), v(5.0, 20.0)));   // Past the end
+	}
+
+	#[test]
+	fn stopping_on_the_line_crosses_once() {
+		let gate = Gate::new(v(0.0, -10.0), v(0.0, 10.0));
+		assert!(gate.is_crossed(v(-5.0, 0.0), v(0.0, 0.0)));
+		assert!(!gate.is_crossed(v(0.0, 0.0), v(0.0, 0.0)));
+		assert!(!gate.is_crossed(v(0.0, 0.0), v(5.0, 0.0)));
+	}
+
+	#[test]
+	fn full_lap_finishes() {
+		let mut timer = LapTimer::new(None);
+		let events = drive(&mut timer, &LAP);
+		assert!(matches!(events.as_slice(), [LapEvent::Started, LapEvent::Sector(None), LapEvent::Sector(None), LapEvent::Finished(_, true)]));
+		assert_eq!(timer.laps[0].splits, vec![1.0, 3.0]);
+		assert!(timer.laps[0].valid);
+	}
+
+	#[test]
+	fn finish_needs_every_checkpoint() {
+		let mut timer = LapTimer::new(None);
+		// Over the line, straight back behind it and over again
+		let events = drive(&mut timer, &[v(0.0, 75.0), v(100.0, 75.0), v(100.0, 150.0), v(0.0, 150.0), v(0.0, 75.0), v(100.0, 75.0)]);
+		assert!(matches!(events.as_slice(), [LapEvent::Started]));
+		assert!(timer.laps.is_empty());
+	}
+
+	#[test]
+	fn skipped_checkpoint_invalidates() {
+		let mut timer = LapTimer::new(None);
+		// Cuts across the top corner, missing the first checkpoint
+		let events = drive(&mut timer, &[v(0.0, 75.0), v(75.0, 75.0), v(75.0, -75.0), v(-25.0, -75.0), v(-25.0, 75.0), v(100.0, 75.0)]);
+		assert!(matches!(events.as_slice(), [LapEvent::Started, LapEvent::Invalidated, LapEvent::Finished(_, false)]));
+		assert!(timer.best.is_none());
+	}
+}
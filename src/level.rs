@@ -0,0 +1,250 @@
+use std::fs;
+
+use raylib::{math::{Vector2, Rectangle}, drawing::RaylibDraw, consts};
+
+use crate::{
+	keyvalue,
+	course::{CourseStep, Direction},
+	judging::ClipZone,
+	lap_timer::{Gate, DEF_LAP_COUNT},
+	pillar::Pillar,
+	surface::{SurfaceType, SurfaceRegion},
+	weather::{Weather, Puddle, AQUAPLANE_SPEED},
+};
+
+pub const LEVEL_DIR: &str = "levels";
+const LEVEL_EXTENSION: &str = "level";
+const DEF_LEVEL_W: f32 = 1000.0;
+const DEF_LEVEL_H: f32 = 800.0;
+
+// Loaded from `key = value` files, see the `levels` folder
+#[derive(Clone)]
+pub struct Level {
+	pub name: String,
+	pub size: Vector2,   // World area the camera fits to the window
+	pub start_pos: Vector2,
+	pub start_angle: f32,   // Radians, 0 faces down the screen
+	pub pillars: Vec<Pillar>,
+	pub course: Vec<CourseStep>,   // Gymkhana order, empty if the level has no course
+	pub zones: Vec<ClipZone>,      // Judged clipping zones, in the order they should be driven
+	pub start_line: Option<Gate>,  // Start and finish for lap timing, None if the level isn't a circuit
+	pub checkpoints: Vec<Gate>,    // In the order they must be crossed each lap
+	pub laps: u32,
+	pub base_surface: SurfaceType,   // Surface used anywhere not covered by a region
+	pub surfaces: Vec<SurfaceRegion>,
+	pub weather: Weather,
+	pub puddles: Vec<Puddle>,
+	pub wind: Vector2,   // Pixels per second, carries particles along
+}
+
+impl Default for Level {
+	fn default() -> Level {
+		Level {
+			name: "Untitled".to_string(),
+			size: Vector2::new(DEF_LEVEL_W, DEF_LEVEL_H),
+			start_pos: Vector2::new(DEF_LEVEL_W/2.0, DEF_LEVEL_H/2.0),
+			start_angle: consts::PI as f32,
+			pillars: vec![],
+			course: vec![],
+			zones: vec![],
+			start_line: None,
+			checkpoints: vec![],
+			laps: DEF_LAP_COUNT,
+			base_surface: SurfaceType::Asphalt,
+			surfaces: vec![],
+			weather: Weather::Dry,
+			puddles: vec![],
+			wind: Vector2::zero(),
+		}
+	}
+}
+
+impl Level {
+	pub fn load(path: &str) -> Result<Level, String> {
+		let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+		Level::parse(&text).map_err(|e| format!("{}: {}", path, e))
+	}
+
+	pub fn parse(text: &str) -> Result<Level, String> {
+		let mut level = Level::default();
+
+		for entry in keyvalue::entries(text) {
+			let e = entry?;
+			let surface = |name: &str| SurfaceType::from_name(name).ok_or_else(|| e.err(&format!("unknown surface `{}`", name)));
+
+			match e.key {
+				"name" => level.name = e.value.to_string(),
+				"size" => {
+					let (w, h) = e.pair()?;
+					level.size = Vector2::new(w, h);
+				},
+				"start" => match e.nums()?.as_slice() {
+					[x, y] => level.start_pos = Vector2::new(*x, *y),
+					[x, y, a] => {
+						level.start_pos = Vector2::new(*x, *y);
+						level.start_angle = a.to_radians();
+					},
+					_ => return Err(e.err("start must be `<x> <y>` or `<x> <y> <angle in degrees>`")),
+				},
+				"base" => level.base_surface = surface(e.value)?,
+				"weather" => level.weather = match e.value {
+					"dry" => Weather::Dry,
+					"rain" => Weather::Rain,
+					_ => return Err(e.err("weather must be `dry` or `rain`")),
+				},
+				"wind" => {
+					let (x, y) = e.pair()?;
+					level.wind = Vector2::new(x, y);
+				},
+				"surface" => match e.word_and_nums()? {
+					(name, n) if n.len() == 4 => level.add_surface(Rectangle::new(n[0], n[1], n[2], n[3]), surface(name)?),
+					_ => return Err(e.err("surface must be `<type> <x> <y> <width> <height>`")),
+				},
+				"puddle" => match e.nums()?.as_slice() {
+					[x, y, r] => level.add_puddle(Vector2::new(*x, *y), *r),
+					_ => return Err(e.err("puddle must be `<x> <y> <radius>`")),
+				},
+				"pillar" => match e.nums()?.as_slice() {
+					[x, y, r] => level.add_pillar(Vector2::new(*x, *y), *r),
+					_ => return Err(e.err("pillar must be `<x> <y> <radius>`")),
+				},
+				"course" => match e.word_and_nums()? {
+					(dir, n) if n.len() == 2 && Direction::from_name(dir).is_some() => level.course.push(CourseStep {
+						pillar: n[0] as usize,
+						direction: Direction::from_name(dir).unwrap(),
+						rotations: n[1],
+					}),
+					_ => return Err(e.err("course must be `<clockwise|anticlockwise> <pillar number> <rotations>`")),
+				},
+				"clip_point" => match e.nums()?.as_slice() {
+					[x, y, r] => level.zones.push(ClipZone::point(Vector2::new(*x, *y), *r)),
+					_ => return Err(e.err("clip_point must be `<x> <y> <radius>`")),
+				},
+				"clip_area" => {
+					let n = e.nums()?;
+					if n.len() < 6 || n.len() % 2 != 0 {
+						return Err(e.err("clip_area must be at least three `<x> <y>` corners, clipping edge first"));
+					}
+					level.zones.push(ClipZone::area(n.chunks(2).map(|c| Vector2::new(c[0], c[1])).collect()));
+				},
+				"start_line" => match e.nums()?.as_slice() {
+					[x1, y1, x2, y2] => level.start_line = Some(Gate::new(Vector2::new(*x1, *y1), Vector2::new(*x2, *y2))),
+					_ => return Err(e.err("start_line must be `<x1> <y1> <x2> <y2>`")),
+				},
+				"checkpoint" => match e.nums()?.as_slice() {
+					[x1, y1, x2, y2] => level.checkpoints.push(Gate::new(Vector2::new(*x1, *y1), Vector2::new(*x2, *y2))),
+					_ => return Err(e.err("checkpoint must be `<x1> <y1> <x2> <y2>`")),
+				},
+				"laps" => match e.single()? {
+					n if n >= 1.0 => level.laps = n as u32,
+					_ => return Err(e.err("laps must be at least 1")),
+				},
+				_ => return Err(e.err(&format!("unknown key `{}`", e.key))),
+			}
+		}
+
+		if level.size.x <= 0.0 || level.size.y <= 0.0 {
+			return Err("size must be positive".to_string());
+		}
+		if level.pillars.is_empty() {
+			return Err("a level needs at least one pillar".to_string());
+		}
+		if !level.checkpoints.is_empty() && level.start_line.is_none() {
+			return Err("checkpoints need a start_line".to_string());
+		}
+		if level.course.iter().any(|s| s.pillar >= level.pillars.len() || s.rotations <= 0.0) {
+			return Err("course steps must use an existing pillar (numbered from 0) and a positive number of rotations".to_string());
+		}
+		Ok(level)
+	}
+
+	#[inline]
+	pub fn add_pillar(&mut self, pos: Vector2, radius: f32) {
+		self.pillars.push( Pillar::new(pos, radius) );
+	}
+
+	#[inline]
+	pub fn add_surface(&mut self, rect: Rectangle, surface: SurfaceType) {
+		self.surfaces.push( SurfaceRegion::new(rect, surface) );
+	}
+
+	#[inline]
+	pub fn add_puddle(&mut self, pos: Vector2, radius: f32) {
+		self.puddles.push( Puddle::new(pos, radius) );
+	}
+
+	pub fn is_aquaplaning(&self, point: Vector2, speed: f32) -> bool {
+		self.weather == Weather::Rain
+			&& speed > AQUAPLANE_SPEED
+			&& self.puddles.iter().any(|p| p.contains(point))
+	}
+
+	pub fn surface_at(&self, point: Vector2) -> SurfaceType {
+		// Later regions are drawn on top, so they take priority
+		self.surfaces.iter()
+			.rev()
+			.find(|s| s.contains(point))
+			.map_or(self.base_surface, |s| s.surface)
+	}
+
+	pub fn draw<D: RaylibDraw>(&self, d: &mut D) {
+		for s in self.surfaces.iter() {
+			s.draw(d);
+		}
+
+		if self.weather == Weather::Rain {
+			for p in self.puddles.iter() {
+				p.draw(d);
+			}
+		}
+
+		for z in self.zones.iter() {
+			z.draw(d);
+		}
+	}
+}
+
+// Loads every level in the folder. Broken files are reported and skipped.
+pub fn load_all(dir: &str) -> Vec<Level> {
+	keyvalue::list_files(dir, LEVEL_EXTENSION).iter()
+		.filter_map(|p| Level::load(&p.to_string_lossy()).map_err(|e| println!("{}", e)).ok())
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_a_level() {
+		let level = Level::parse("name = Test\nsize = 500 400\nstart = 100 200 90\nweather = rain\npillar = 250 200 7\npillar = 300 200 5\ncourse = cw 1 2\n").unwrap();
+		assert_eq!(level.name, "Test");
+		assert_eq!(level.size, Vector2::new(500.0, 400.0));
+		assert_eq!(level.start_pos, Vector2::new(100.0, 200.0));
+		assert!((level.start_angle - consts::PI as f32/2.0).abs() < 1e-6);
+		assert_eq!(level.weather, Weather::Rain);
+		assert_eq!(level.pillars.len(), 2);
+		assert_eq!((level.course[0].pillar, level.course[0].direction, level.course[0].rotations), (1, Direction::Clockwise, 2.0));
+	}
+
+	#[test]
+	fn rejects_bad_levels() {
+		let err = |text| Level::parse(text).err().unwrap();
+		assert_eq!(err("pillar = 1 2 3\ncolour = red\n"), "line 2: unknown key `colour`");
+		assert_eq!(err("name = Empty\n"), "a level needs at least one pillar");
+		assert_eq!(err("pillar = 1 2 3\ncheckpoint = 0 0 10 0\n"), "checkpoints need a start_line");
+		assert!(err("pillar = 1 2 3\ncourse = cw 1 1\n").starts_with("course steps"));
+		assert!(err("pillar = 1 2 3\nsurface = lava 0 0 10 10\n").contains("unknown surface `lava`"));
+	}
+
+	#[test]
+	fn shipped_levels_load() {
+		let paths = keyvalue::list_files(LEVEL_DIR, LEVEL_EXTENSION);
+		assert!(!paths.is_empty());
+		for path in paths {
+			if let Err(e) = Level::load(path.to_str().unwrap()) {
+				panic!("{}", e);
+			}
+		}
+	}
+}
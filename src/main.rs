@@ -8,10 +8,49 @@ mod drift_trail;
 mod dust_system;
 mod pillar;
 mod misc;
+mod surface;
+mod level;
+mod weather;
+mod particle_pool;
+mod emitter;
+mod tyre;
+mod synth;
+mod audio;
+mod combo;
+mod hud;
+mod view;
+mod menu;
+mod keyvalue;
+mod car_spec;
+mod run_stats;
+mod personal_best;
+mod course;
+mod judging;
+mod lap_timer;
+mod high_scores;
+mod settings;
+mod input;
+mod replay;
+mod trail_export;
+mod cli;
+mod telemetry;
+mod graphs;
+mod debug_draw;
+mod ai;
+mod env;
+mod players;
+mod tandem;
+mod net;
+mod rollback;
+
+use raylib::{color::Color, drawing::{RaylibDraw, RaylibDrawHandle, RaylibTextureModeExt, RaylibMode2DExt, RaylibScissorModeExt}, RaylibHandle, RaylibThread, consts};
+use std::collections::HashMap;
 
-use raylib::{color::Color, math::Vector2, drawing::{RaylibDraw, RaylibDrawHandle}, RaylibHandle, RaylibThread, consts};
 use crate::{
 	traits::*,
+	synth::OneShot,
+	combo::ComboEvent,
+	menu::MenuAction,
 };
 
 static BG_COLOR: Color = Color { r: 230, g: 230, b: 220, a: 255 };
@@ -22,112 +61,1527 @@ static CHARCOAL: Color = Color { r: 38, g: 38, b: 38, a: 255 };
 const POINT_DIST_THRESHOLD: f32 = 200.0; //100.0;
 const MAX_POINTS_PER_FRAME: u32 = 5;
 const TWO_PI: f32 = consts::PI as f32 * 2.0;
+const DEF_SCORE_ATTACK_TIME: u32 = 90;   // In seconds
+const MIN_SCORE_ATTACK_TIME: u32 = 30;
+const MAX_SCORE_ATTACK_TIME: u32 = 300;
+const SCORE_ATTACK_TIME_STEP: u32 = 15;
+const RESULTS_HIGH_SCORES: usize = 5;   // Entries of the table shown after a run
+const CONTROLS_RESET_ROW: usize = settings::Controls::NAMES.len();   // After one row per control
+const TICK_RATE: u32 = 240;   // Simulation steps per second, fixed so replays play back exactly
+const TICK_DT: f32 = 1.0/TICK_RATE as f32;
+const MAX_TICKS_PER_FRAME: u32 = 20;   // Slow frames drop time rather than trying to catch up forever
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum GameMode {
+	FreeDrift,     // No time limit, just drift
+	ScoreAttack,   // Score as much as possible before time runs out
+	Gymkhana,      // Orbit the level's pillars in order against the clock
+	TimeTrial,     // Laps of a circuit, through every checkpoint
+	Tandem,        // Lead then chase another car, against the AI or a second player
+}
+
+impl GameMode {
+	const ALL: [GameMode; 5] = [GameMode::FreeDrift, GameMode::ScoreAttack, GameMode::Gymkhana, GameMode::TimeTrial, GameMode::Tandem];
+
+	fn name(&self) -> &'static str {
+		match self {
+			GameMode::FreeDrift => "Free drift",
+			GameMode::ScoreAttack => "Score attack",
+			GameMode::Gymkhana => "Gymkhana",
+			GameMode::TimeTrial => "Time trial",
+			GameMode::Tandem => "Tandem",
+		}
+	}
+
+	#[inline]
+	fn can_play(&self, level: &level::Level) -> bool {
+		match self {
+			GameMode::Gymkhana => !level.course.is_empty(),
+			GameMode::TimeTrial => level.start_line.is_some(),
+			GameMode::Tandem => !level.pillars.is_empty(),
+			_ => true,
+		}
+	}
+
+	fn key(&self) -> &'static str {   // For files and the command line
+		match self {
+			GameMode::FreeDrift => "free_drift",
+			GameMode::ScoreAttack => "score_attack",
+			GameMode::Gymkhana => "gymkhana",
+			GameMode::TimeTrial => "time_trial",
+			GameMode::Tandem => "tandem",
+		}
+	}
+
+	#[inline]
+	fn from_key(key: &str) -> Option<GameMode> {
+		GameMode::ALL.iter().copied().find(|m| m.key() == key)
+	}
+
+	#[inline]
+	fn allows_split_screen(&self) -> bool {   // Modes scored off the pillars, which every player can do at once
+		matches!(self, GameMode::FreeDrift | GameMode::ScoreAttack)
+	}
+
+	#[inline]
+	fn is_timed(&self) -> bool {   // High scores are times, so lower is better
+		matches!(self, GameMode::Gymkhana | GameMode::TimeTrial)
+	}
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum GameState {
+	Title,
+	PlayerSelect,   // Number of players for split screen
+	Lobby,          // Online, waiting for the host to start
+	ModeSelect,
+	LevelSelect,
+	CarSelect,
+	Settings { paused: bool },   // Reached from the title or the pause menu, and goes back there
+	Controls { paused: bool },   // Key bindings, under settings
+	Playing,
+	Paused,
+	Results,
+}
+
+// Rows of the settings menu, top to bottom
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum SettingsRow {
+	FrameRate,
+	WindowSize,
+	Fullscreen,
+	Msaa,
+	UiScale,
+	Debug,
+	Volume,
+	TractionControl,
+	Units,
+	ScoreAttackTime,
+	Weather,
+	Telemetry,
+	Controls,
+	Back,
+}
+
+impl SettingsRow {
+	const ALL: [SettingsRow; 14] = [
+		SettingsRow::FrameRate, SettingsRow::WindowSize, SettingsRow::Fullscreen, SettingsRow::Msaa, SettingsRow::UiScale,
+		SettingsRow::Debug, SettingsRow::Volume, SettingsRow::TractionControl, SettingsRow::Units, SettingsRow::ScoreAttackTime,
+		SettingsRow::Weather, SettingsRow::Telemetry, SettingsRow::Controls, SettingsRow::Back,
+	];
+
+	#[inline]
+	fn index(&self) -> usize {
+		SettingsRow::ALL.iter().position(|r| r == self).unwrap()
+	}
+}
 
 struct Game {
+	state: GameState,
+	menu: menu::Menu,
+	mode: GameMode,
+	personal_bests: personal_best::PersonalBests,
+	high_scores: high_scores::HighScores,
+	settings: settings::Settings,
+	rebinding: Option<usize>,   // Control waiting for a key press on the controls screen
+	levels: Vec<level::Level>,
+	cars: Vec<car_spec::CarSpec>,
+	level_index: usize,
+	car_index: usize,
 	player: car::Car,
-	pillars: Vec<pillar::Pillar>,
+	others: Vec<players::Player>,   // Players two and up in split screen
+	local_players: usize,
+	versus_ai: bool,                // Player two is the AI rather than a person
+	car_contacts: u32,              // Pairs of cars touching on the last tick, one bit each
+	net: Option<net::Session>,      // Playing online
+	rollback: Option<rollback::Rollback>,
+	level: level::Level,
+	weather_override: Option<weather::Weather>,   // Chosen in the settings, otherwise each level has its own
+	sim_time: f64,   // Only moves while playing, so pausing freezes everything
 	closest_pillar_to_player: (i32, f32),
 	player_is_scoring_points: bool,
 	score: u32,
-	use_debug: bool,
+	best_score: u32,
+	run_time: f32,
+	combo: combo::Combo,
+	course_run: course::CourseRun,
+	judge: judging::Judge,
+	lap_timer: lap_timer::LapTimer,
+	tandem: tandem::Tandem,
+	best_laps: HashMap<(String, String), lap_timer::Lap>,   // Best valid lap this session for each level and car
+	stats: run_stats::RunStats,
+	hud: hud::Hud,
+	view: Option<view::View>,     // None when running headless
+	audio: Option<audio::Audio>,
+	seed: u64,                    // For particle effects
+	particle_budget: usize,       // From the settings unless given on the command line
+	tick_accumulator: f32,        // Frame time not yet simulated
+	recording: replay::Replay,    // This run's inputs so far
+	playback: Option<replay::Replay>,   // Replay being watched
+	ai_driver: Option<ai::AiDriver>,    // Drives the player's car instead of the keys
+	demo_driver: ai::AiDriver,          // Drives behind the title menu
+	trail_log: trail_export::TrailLog,
+	telemetry: Option<telemetry::Telemetry>,   // Recording this run, if turned on
+	telemetry_path: Option<String>,            // From the command line, otherwise runs are saved in the telemetry folder
+	graphs: graphs::Graphs,       // Shown with the debug overlay
+	debug_layers: debug_draw::DebugLayers,
+	save_results: bool,           // Off for headless runs, so scripts don't fill the high score tables
+	player_touching_pillar: bool,
+	quit: bool,
 }
 
 impl Game {
-	fn new(rl: &mut RaylibHandle, rl_thread: &RaylibThread, player_pos: Vector2) -> Game {
-		Game {
-			player: car::Car::new(rl, rl_thread, player_pos),
-			pillars: vec![],
+	// Everything needed to simulate. Call attach_window to draw and play sound.
+	fn new(settings: settings::Settings, seed: u64) -> Result<Game, String> {
+		let levels = level::load_all(level::LEVEL_DIR);
+		if levels.is_empty() {
+			return Err(format!("Couldn't load any levels from {}.", level::LEVEL_DIR));
+		}
+		let mut cars = car_spec::load_all(car_spec::CAR_DIR);
+		if cars.is_empty() {
+			cars.push(car_spec::CarSpec::default());
+		}
+		let level = levels[0].clone();
+
+		let mut g = Game {
+			state: GameState::Title,
+			menu: menu::Menu::new("", vec![]),
+			mode: GameMode::FreeDrift,
+			personal_bests: personal_best::PersonalBests::load(personal_best::PERSONAL_BESTS_PATH),
+			high_scores: high_scores::HighScores::load(high_scores::HIGH_SCORES_PATH),
+			player: car::Car::new(&cars[0], level.start_pos, level.start_angle, seed, settings.particle_budget),
+			others: vec![],
+			local_players: 1,
+			versus_ai: false,
+			car_contacts: 0,
+			net: None,
+			rollback: None,
+			levels,
+			cars,
+			level_index: 0,
+			car_index: 0,
+			level,
+			weather_override: None,
+			sim_time: 0.0,
 			closest_pillar_to_player: (0, -1.0),
 			player_is_scoring_points: false,
 			score: 0,
-			use_debug: true,
+			best_score: 0,
+			run_time: 0.0,
+			combo: combo::Combo::default(),
+			course_run: course::CourseRun::new(0),
+			judge: judging::Judge::new(0),
+			lap_timer: lap_timer::LapTimer::new(None),
+			tandem: tandem::Tandem::default(),
+			best_laps: HashMap::new(),
+			stats: run_stats::RunStats::default(),
+			hud: hud::Hud::default(),
+			view: None,
+			audio: None,
+			seed,
+			particle_budget: settings.particle_budget,
+			tick_accumulator: 0.0,
+			recording: replay::Replay {
+				level: String::new(), car: String::new(), mode: String::new(), score_attack_time: 0,
+				rain: false, traction_control: false, seed, tick_rate: TICK_RATE, inputs: vec![],
+			},
+			playback: None,
+			ai_driver: None,
+			demo_driver: ai::AiDriver::default(),
+			trail_log: trail_export::TrailLog::default(),
+			graphs: graphs::Graphs::new(),
+			debug_layers: debug_draw::DebugLayers::default(),
+			telemetry: None,
+			telemetry_path: None,
+			save_results: true,
+			settings,
+			rebinding: None,
+			player_touching_pillar: false,
+			quit: false,
+		};
+		g.player.traction_control = g.settings.traction_control;
+		g.set_state(GameState::Title);
+		Ok(g)
+	}
+
+	fn attach_window(&mut self, rl: &mut RaylibHandle, rl_thread: &RaylibThread, mut view: view::View) {
+		if self.settings.fullscreen {
+			view.toggle_fullscreen(rl);
+		}
+		self.view = Some(view);
+		self.load_car_textures(rl, rl_thread);
+
+		self.audio = audio::Audio::new(rl_thread);
+		if let Some(audio) = self.audio.as_mut() {
+			audio.set_volume(self.settings.volume);
+			audio.set_paused(self.state != GameState::Playing);
+		}
+	}
+
+	// Applies the command line's level, car, mode and replay, returning whether to skip the menus
+	fn apply_options(&mut self, opts: &cli::Options) -> Result<bool, String> {
+		self.telemetry_path = opts.telemetry.clone();   // Replays can be recorded too
+		if let Some(budget) = opts.particle_budget {
+			self.particle_budget = budget.clamp(particle_pool::MIN_PARTICLE_BUDGET, particle_pool::MAX_PARTICLE_BUDGET);
+			self.select_car(self.car_index);
+		}
+		if let Some(path) = &opts.replay {
+			self.start_replay(replay::Replay::load(path)?)?;
+			return Ok(true);
+		}
+
+		if opts.ai {
+			self.ai_driver = Some(ai::AiDriver::default());
+		}
+		if let Some(key) = &opts.mode {
+			self.mode = GameMode::from_key(key).ok_or(format!("unknown mode `{}`", key))?;
+		}
+		let mut start = false;
+		if let Some(path) = &opts.level {
+			let level = level::Level::load(path)?;
+			if !self.mode.can_play(&level) {
+				return Err(format!("{}: can't be played in {}", path, self.mode.name()));
+			}
+			// Replaces a loaded level with the same name, so edited copies can be tried out
+			let i = match self.levels.iter().position(|l| l.name == level.name) {
+				Some(i) => { self.levels[i] = level; i },
+				None => { self.levels.push(level); self.levels.len() - 1 },
+			};
+			self.select_level(i);
+			start = true;
+		}
+		if let Some(path) = &opts.car {
+			let spec = car_spec::CarSpec::load(path)?;
+			let i = match self.cars.iter().position(|c| c.name == spec.name) {
+				Some(i) => { self.cars[i] = spec; i },
+				None => { self.cars.push(spec); self.cars.len() - 1 },
+			};
+			self.select_car(i);
+		}
+		if self.mode == GameMode::Tandem {
+			self.spawn_others();
+		}
+		Ok(start)
+	}
+
+	// Sets up the level, car and conditions a replay was recorded with
+	fn start_replay(&mut self, r: replay::Replay) -> Result<(), String> {
+		if r.tick_rate != TICK_RATE {
+			return Err(format!("replay was recorded at {} ticks per second, this version runs at {}", r.tick_rate, TICK_RATE));
+		}
+		self.mode = GameMode::from_key(&r.mode).ok_or(format!("replay has unknown mode `{}`", r.mode))?;
+		let level = self.levels.iter().position(|l| l.name == r.level).ok_or(format!("replay's level `{}` isn't loaded", r.level))?;
+		let car = self.cars.iter().position(|c| c.name == r.car).ok_or(format!("replay's car `{}` isn't loaded", r.car))?;
+
+		self.seed = r.seed;
+		self.local_players = 1;   // Replays only have player one's inputs
+		self.others.clear();
+		self.select_level(level);
+		self.select_car(car);
+		self.playback = Some(r);
+		Ok(())
+	}
+
+	fn load_car_textures(&mut self, rl: &mut RaylibHandle, rl_thread: &RaylibThread) {
+		self.player.load_texture(rl, rl_thread);
+		for p in self.others.iter_mut() {
+			p.car.load_texture(rl, rl_thread);
+		}
+	}
+
+	#[inline]
+	fn start_run(&mut self) {
+		self.reload();
+		self.set_state(GameState::Playing);
+	}
+
+	// The replay's input while watching one, the AI's if it's driving, or the keys otherwise. None once a replay runs out.
+	#[inline]
+	fn next_input(&mut self, keys: input::Input) -> Option<input::Input> {
+		match (&self.playback, self.ai_driver.as_mut()) {
+			(Some(r), _) => r.inputs.get(self.recording.inputs.len()).copied(),
+			(None, Some(ai)) => Some(ai.update(&self.player, &self.level)),
+			(None, None) => Some(keys),
+		}
+	}
+
+	// The title screen has the AI drifting round the level behind the menu
+	fn update_demo(&mut self, dt: f32) {
+		self.tick_accumulator = (self.tick_accumulator + dt).min(MAX_TICKS_PER_FRAME as f32 * TICK_DT);
+		while self.tick_accumulator >= TICK_DT {
+			self.tick_accumulator -= TICK_DT;
+			let input = self.demo_driver.update(&self.player, &self.level);
+			self.sim_time += TICK_DT as f64;
+			self.player.update(&input, TICK_DT, self.sim_time, &self.level);
+		}
+	}
+
+	// Runs as many fixed ticks as the frame time covers
+	fn update_ticks(&mut self, dt: f32, keys: input::Input) {
+		self.tick_accumulator = (self.tick_accumulator + dt).min(MAX_TICKS_PER_FRAME as f32 * TICK_DT);
+		while self.tick_accumulator >= TICK_DT && self.state == GameState::Playing {
+			self.tick_accumulator -= TICK_DT;
+			match self.next_input(keys) {
+				Some(input) => self.tick(input),
+				None => self.finish_run(),
+			}
+		}
+	}
+
+	fn save_telemetry(&mut self) {
+		let t = match self.telemetry.take() {
+			Some(t) => t,
+			None => return,
+		};
+		let path = self.telemetry_path.clone().unwrap_or_else(|| format!("{}/{}_{}_{}.csv", telemetry::TELEMETRY_DIR,
+			self.level.name.to_lowercase().replace(' ', "_"), self.player.spec.name.to_lowercase().replace(' ', "_"), high_scores::get_timestamp()));
+		match t.save(&path) {
+			Ok(()) => println!("Wrote {}", path),
+			Err(e) => println!("Couldn't save telemetry: {}", e),
+		}
+	}
+
+	fn export_trails(&self, path: &str) {
+		match self.trail_log.write_svg(path, &self.level) {
+			Ok(()) => println!("Wrote {}", path),
+			Err(e) => println!("Couldn't export trails: {}", e),
 		}
 	}
 
 	fn draw(&mut self, rl: &mut RaylibHandle, rl_thread: &RaylibThread) {
-        let time = rl.get_time();
-        let mut d = rl.begin_drawing(&rl_thread);
-        d.clear_background(BG_COLOR);
+		let mut view = match self.view.take() {
+			Some(view) => view,
+			None => return,   // Headless
+		};
+		let time = self.sim_time;
+		let camera = view.get_camera(self.level.size);
+		let mut d = rl.begin_drawing(&rl_thread);
+
+		// Online there's one view, following our own car
+		let online = self.net.as_ref().filter(|n| n.setup.is_some()).map(|n| n.local);
+		{   // World, drawn at the internal resolution
+			let viewports = view.get_viewports(if online.is_some() { 1 } else { self.others.len() + 1 });
+			let cameras: Vec<_> = viewports.iter().enumerate().map(|(i, rect)| match self.get_car(online.unwrap_or(i)) {
+				Some(car) => view.get_follow_camera(self.level.size, *rect, car.pos),
+				None => view.get_overview_camera(self.level.size, *rect),
+			}).collect();
+
+			let mut t = d.begin_texture_mode(rl_thread, view.target_mut());
+			t.clear_background(self.level.base_surface.properties().ground_colour);
+			if self.others.is_empty() {
+				let mut w = t.begin_mode2D(camera);
+				self.draw_world(&mut w, time);
+			} else {
+				for (rect, camera) in viewports.iter().zip(cameras) {
+					let mut s = t.begin_scissor_mode(rect.x as i32, rect.y as i32, rect.width as i32, rect.height as i32);
+					let mut w = s.begin_mode2D(camera);
+					self.draw_world(&mut w, time);
+				}
+				for rect in viewports.iter() {
+					t.draw_rectangle_lines_ex(*rect, 4, CHARCOAL);
+				}
+			}
+		}
+		view.draw_to_screen(&mut d);
+
+		if self.settings.debug {
+			let unit = d.get_screen_height() as f32/800.0 * view.ui_scale;
+			let line = |i: i32| (10.0 + 22.0 * i as f32 * unit) as i32;
+			let font = (20.0 * unit) as i32;
+			d.draw_text(format!("Trail nodes: {}", self.player.get_trail_node_count()).as_str(), 10, line(1), font, CHARCOAL);
+			d.draw_text(format!("Player speed: {:.1}", self.player.vel_mag).as_str(), 10, line(2), font, CHARCOAL);
+			d.draw_text(format!("Player perp: {:.3}", self.player.perp).as_str(), 10, line(3), font, CHARCOAL);
+			d.draw_text(format!("Weather: {:?}", self.level.weather).as_str(), 10, line(4), font, CHARCOAL);
+			d.draw_text(format!("Particle count: {}", self.player.get_particle_count()).as_str(), 10, line(5), font, CHARCOAL);
+			d.draw_text(format!("UI scale: {:.1}", view.ui_scale).as_str(), 10, line(6), font, CHARCOAL);
+			if let Some(r) = &self.rollback {
+				d.draw_text(format!("Rollback: {} ticks re-run", r.resimulated).as_str(), 10, line(7), font, CHARCOAL);
+			}
+			for (i, layer) in debug_draw::Layer::ALL.iter().enumerate() {
+				let text = format!("{}: {} {}", i + 1, layer.name(), if self.debug_layers.is_on(*layer) { "on" } else { "off" });
+				d.draw_text(&text, 10, line(8 + i as i32), font, CHARCOAL);
+			}
+			if self.graphs.visible {
+				self.graphs.draw(&mut d, view.ui_scale);
+			}
+		}
+		let focus = self.get_player_score(online.unwrap_or(0));
+		if let (Some((score, combo, car)), true) = (focus, self.state == GameState::Playing || self.state == GameState::Paused) {
+			let info = hud::HudInfo {
+				speed: car.vel_mag,
+				units: self.settings.units,
+				perp: car.perp,
+				score,
+				best_score: if online.unwrap_or(0) == 0 { self.best_score } else { score },
+				combo_points: combo.points,
+				combo_multiplier: combo.multiplier,
+				combo_grace: combo.get_grace_left(),
+				time: self.run_time,
+				time_limit: self.get_time_limit(),
+				objective: self.get_objective(),
+				tyres: &car.tyres,
+			};
+			if self.others.is_empty() || online.is_some() {
+				let quiet = hud::Hud::default();   // Popups are player one's
+				let hud = if online.unwrap_or(0) == 0 { &self.hud } else { &quiet };
+				hud.draw(&mut d, &info, view.ui_scale);
+			} else {
+				let (w, h) = (d.get_screen_width() as f32, d.get_screen_height() as f32);
+				let panels: Vec<_> = view.get_viewports(self.others.len() + 1).iter().enumerate().filter_map(|(i, rect)| {
+					let (score, combo, car) = self.get_player_score(i)?;
+					Some(hud::PlayerPanel {
+						rect: view.to_screen_rect(*rect, w, h),
+						label: format!("P{}", i + 1),
+						colour: players::PLAYER_COLORS[i],
+						score,
+						combo_points: combo.points,
+						combo_multiplier: combo.multiplier,
+						speed: car.vel_mag,
+					})
+				}).collect();
+				self.hud.draw_split(&mut d, &info, &panels, view.ui_scale);
+			}
+		}
+		if self.state != GameState::Playing {
+			self.menu.draw(&mut d, view.ui_scale);
+		}
+
+		d.draw_fps(10, 10);
+		drop(d);
+		self.view = Some(view);
+	}
+
+	fn draw_world<D: RaylibDraw>(&self, w: &mut D, time: f64) {
+		self.level.draw(w);
 
 		// draw trails below stuff
-		self.player.draw_trails(&mut d, time);
+		self.player.draw_trails(w, time);
+		for p in self.others.iter() {
+			p.car.draw_trails(w, time);
+		}
 
-		for p in self.pillars.iter() {
-			p.draw(&mut d);
+		for p in self.level.pillars.iter() {
+			p.draw(w);
+		}
+		if self.mode == GameMode::Gymkhana && self.state != GameState::Title {
+			self.course_run.draw(w, &self.level.pillars, &self.level.course);
+		}
+		if let Some(start) = &self.level.start_line {
+			self.lap_timer.draw(w, start, &self.level.checkpoints);
 		}
 
-		self.player.draw(&mut d);
+		self.player.draw(w);
+		for p in self.others.iter() {
+			p.car.draw(w);
+		}
 
-		if self.use_debug {
-			let closest_pillar_pos = self.pillars[self.closest_pillar_to_player.0 as usize].pos;
-			if self.player_is_scoring_points {
-				d.draw_line_ex(closest_pillar_pos, self.player.pos, 2.0, Color::BLUE);
-			}
-			d.draw_circle_v(closest_pillar_pos, POINT_DIST_THRESHOLD, Color::new(0, 100, 0, 100));
+		if self.settings.debug {
+			self.debug_layers.draw(w, &self.player, &self.level.pillars,
+				(self.closest_pillar_to_player.0 as usize, self.player_is_scoring_points));
+		}
+	}
 
-			d.draw_text(format!("Trail nodes: {}", self.player.get_trail_node_count()).as_str(), 10, 32, 20, CHARCOAL);
-			d.draw_text(format!("Player speed: {:.1}", self.player.vel_mag).as_str(), 10, 54, 20, CHARCOAL);
-			d.draw_text(format!("Player perp: {:.3}", self.player.perp).as_str(), 10, 76, 20, CHARCOAL);
-			d.draw_text(format!("Particle count: {}", self.player.get_particle_count()).as_str(), 10, 120, 20, CHARCOAL);
+	// Player one's car, then the others'
+	#[inline]
+	fn get_car(&self, i: usize) -> Option<&car::Car> {
+		match i {
+			0 => Some(&self.player),
+			_ => self.others.get(i - 1).map(|p| &p.car),
 		}
-		d.draw_text(format!("Score: {}", self.score).as_str(), 400, 10, 20, RED_2);
+	}
 
-        d.draw_fps(10, 10);
+	#[inline]
+	fn get_player_score(&self, i: usize) -> Option<(u32, &combo::Combo, &car::Car)> {
+		match i {
+			0 => Some((self.score, &self.combo, &self.player)),
+			_ => self.others.get(i - 1).map(|p| (p.score, &p.combo, &p.car)),
+		}
 	}
 
-	fn update(&mut self, dt: f32, rl: &mut RaylibHandle) {
-		self.player.update(&rl, dt);
+	fn update(&mut self, dt: f32, rl: &mut RaylibHandle, rl_thread: &RaylibThread) {
+		if self.state == GameState::Results {
+			self.update_online(dt, input::Input::default());   // Others may still be waiting on our last inputs
+		}
+		if self.state == GameState::Playing {
+			for p in self.others.iter_mut() {
+				p.read_input(rl);
+			}
+			let keys = input::Input::from_keys(rl, &self.settings.controls);
+			if self.net.is_some() {
+				self.update_online(dt, keys);
+			} else {
+				self.update_ticks(dt, keys);
+			}
+			if self.settings.debug {
+				self.graphs.push(self.sim_time, dt, &self.player, self.recording.inputs.last().copied().unwrap_or_default());
+			}
+			if self.state == GameState::Playing && self.net.is_some() {
+				// Nothing can pause or restart for everyone, so escape leaves
+				if rl.is_key_pressed(consts::KeyboardKey::KEY_ESCAPE) { self.leave_online(None) }
+			} else if self.state == GameState::Playing && (rl.is_key_pressed(consts::KeyboardKey::KEY_ESCAPE) || rl.is_key_pressed(self.settings.controls.pause)) {
+				self.set_state(GameState::Paused);
+			}
+			if self.state == GameState::Playing && self.net.is_none() && rl.is_key_pressed(self.settings.controls.retry) { self.reload() }
+		} else if self.state == GameState::Lobby {
+			self.update_lobby(dt, rl, rl_thread);
+		} else if self.state == GameState::Title {
+			self.update_demo(dt);
+			if let Some(action) = self.menu.update(rl) {
+				self.handle_menu_action(action, rl, rl_thread);
+			}
+		} else if let Some(i) = self.rebinding {
+			if let Some(key) = rl.get_key_pressed() {
+				if key != consts::KeyboardKey::KEY_ESCAPE {   // Escape cancels
+					self.settings.controls.rebind(i, key);
+				}
+				self.rebinding = None;
+				self.menu.items = self.get_controls_items();
+			}
+		} else if let Some(action) = self.menu.update(rl) {
+			self.handle_menu_action(action, rl, rl_thread);
+		}
+
+		if rl.is_key_pressed(consts::KeyboardKey::KEY_F10) { self.settings.debug = !self.settings.debug }
+		if rl.is_key_pressed(consts::KeyboardKey::KEY_F8) && self.settings.debug { self.graphs.visible = !self.graphs.visible }
+		if self.settings.debug {
+			for layer in debug_draw::Layer::ALL.iter() {
+				if rl.is_key_pressed(layer.key()) { self.debug_layers.toggle(*layer) }
+			}
+		}
+		if rl.is_key_pressed(consts::KeyboardKey::KEY_F9) && !self.is_mid_run() && self.net.is_none() {   // Online the host's setup decides
+			self.level.weather = self.level.weather.toggled();
+		}
+		if rl.is_key_pressed(consts::KeyboardKey::KEY_F11) { self.toggle_fullscreen(rl) }
+		if rl.is_key_pressed(consts::KeyboardKey::KEY_EQUAL) { self.change_ui_scale(1) }
+		if rl.is_key_pressed(consts::KeyboardKey::KEY_MINUS) { self.change_ui_scale(-1) }
+	}
+
+	// One fixed step of the run
+	fn tick(&mut self, input: input::Input) {
+		let dt = TICK_DT;
+		self.recording.inputs.push(input);
+		self.sim_time += dt as f64;
+		let prev_pos = self.player.pos;
+		self.player.update(&input, dt, self.sim_time, &self.level);
+		let tandem = self.mode == GameMode::Tandem;
+		for (i, p) in self.others.iter_mut().enumerate() {
+			let role = match (tandem, self.tandem.get_leader() == i + 1) {
+				(false, _) => players::Role::Solo,
+				(true, true) => players::Role::Lead,
+				(true, false) => players::Role::Chase(&self.player),
+			};
+			p.tick(dt, self.sim_time, &self.level, role);
+		}
+		self.collide_cars();
+		if let (true, Some(other)) = (tandem, self.others.first_mut()) {
+			self.tandem.update(dt, [&self.player, &other.car], &self.level.pillars);
+			self.score = self.tandem.get_total(0);
+			self.best_score = self.best_score.max(self.score);
+			other.score = self.tandem.get_total(1);
+		}
+		self.trail_log.update(&self.player);
+		self.run_time += dt;
+		self.stats.update(dt, self.player.vel_mag, self.player.drifting);
+
+		if let Some(limit) = self.get_time_limit() {
+			if self.run_time >= limit && self.mode == GameMode::Tandem && self.tandem.leg == 0 {
+				self.start_second_leg();
+				return;
+			}
+			if self.run_time >= limit {
+				self.run_time = limit;
+				self.finish_run();
+				return;
+			}
+		}
+
+		if self.mode == GameMode::Gymkhana {
+			match self.course_run.update(self.player.pos, &self.level.pillars, &self.level.course) {
+				Some(course::CourseEvent::StepDone) => self.play_sound(OneShot::PillarComplete),
+				Some(course::CourseEvent::Skipped(n)) => self.hud.push_popup(format!("Skipped {}!", n), RED_1),
+				Some(course::CourseEvent::WrongDirection) => self.hud.push_popup("Wrong way!".to_string(), RED_1),
+				Some(course::CourseEvent::Finished) => {
+					self.play_sound(OneShot::PillarComplete);
+					self.finish_run();
+					return;
+				},
+				None => (),
+			}
+		}
+
+		if self.mode == GameMode::TimeTrial {
+			if let Some(start) = &self.level.start_line {
+				let event = self.lap_timer.update(dt, prev_pos, self.player.pos, start, &self.level.checkpoints);
+				if self.handle_lap_event(event) {
+					self.finish_run();
+					return;
+				}
+			}
+		}
+
+		for (zone, score) in self.judge.update(dt, &self.player, &self.level.zones) {
+			self.hud.push_popup(format!("Zone {}: {:.0}", zone + 1, score.get_total()), CHARCOAL);
+		}
 
 		self.closest_pillar_to_player = self.get_closest_pillar_to_player();
 
 		// Player has to do full 360 around pillar before moving on.
-		let mut pillar = &mut self.pillars[self.closest_pillar_to_player.0 as usize];
+		let mut pillar = &mut self.level.pillars[self.closest_pillar_to_player.0 as usize];
+		let mut orbit_completed = false;
 		if !pillar.done && self.closest_pillar_to_player.1 <= POINT_DIST_THRESHOLD {//self.player.drifting && !self.pillars[self.closest_pillar_to_player.0 as usize].done && self.closest_pillar_to_player.1 <= POINT_DIST_THRESHOLD {
 			let curr_angle = pillar.pos.angle_to(self.player.pos);
 			if self.player_is_scoring_points {  // If already scoring points, then check for full 360
-				let angle_diff = misc::get_angle_diff(pillar.player_start_angle, curr_angle).abs();
-				println!("Start angle: {}, Curr angle: {}, diff {}", pillar.player_start_angle, curr_angle, angle_diff);
-
-				pillar.progress = angle_diff/TWO_PI;
-				println!("Pillar progress: {}", pillar.progress);
-				
-				/*
-				if pillar.progress >= 0.99 {
-					pillar.done = true
+				pillar.progress += misc::wrap_angle(curr_angle - pillar.last_angle)/TWO_PI;
+				pillar.last_angle = curr_angle;
+				if pillar.progress.abs() >= 1.0 {
+					pillar.progress -= pillar.progress.signum();
+					orbit_completed = true;
 				}
-				*/
 			} else {
-				pillar.player_start_angle = curr_angle;
+				pillar.last_angle = curr_angle;
 				self.player_is_scoring_points = true;
 			}
-			self.score += self.get_points_from_dist(dt, self.closest_pillar_to_player.1);
+			if self.mode != GameMode::Tandem {   // Scored by the tandem rules instead
+				let points = get_points_from_dist(dt, self.closest_pillar_to_player.1);
+				self.combo.add(points);
+			}
 		} else if self.player_is_scoring_points {
 			pillar.progress = 0.0;
 			self.player_is_scoring_points = false;
 		}
 
-		if rl.is_key_pressed(consts::KeyboardKey::KEY_R) { self.reload() }
-		if rl.is_key_pressed(consts::KeyboardKey::KEY_F10) { self.use_debug = !self.use_debug }
+		let pillar = &self.level.pillars[self.closest_pillar_to_player.0 as usize];
+		let touching_pillar = self.closest_pillar_to_player.1 <= pillar.radius + car::HALF_CAR_W;
+		if orbit_completed && self.mode != GameMode::Gymkhana {   // Gymkhana plays it for each course step instead
+			self.play_sound(OneShot::PillarComplete);
+		}
+
+		match self.combo.update(dt, self.player_is_scoring_points) {
+			Some(ComboEvent::MultiplierUp(m)) => {
+				self.hud.pulse_combo();
+				self.hud.push_popup(format!("x{}!", m), RED_2);
+			},
+			Some(ComboEvent::Banked(points, multiplier)) => self.add_score(points, multiplier),
+			None => (),
+		}
+		self.hud.update(dt);
+		if let Some(t) = self.telemetry.as_mut() {
+			t.record(self.run_time, &self.player, input, self.score);
+		}
+
+		if touching_pillar && !self.player_touching_pillar {
+			self.play_sound(OneShot::Collision);
+		}
+		self.player_touching_pillar = touching_pillar;
+
+		if let Some(audio) = self.audio.as_mut() {
+			audio.update(&self.player);
+		}
 	}
 
+	fn set_state(&mut self, state: GameState) {
+		let items = |names: &[&str]| names.iter().map(|n| n.to_string()).collect();
+		self.menu = match state {
+			GameState::Title => menu::Menu::new("Drift", items(&["Play", "Split screen", "Settings", "Quit"])),
+			GameState::PlayerSelect => {
+				let mut m = menu::Menu::new("Split screen", items(&["2 players", "3 players", "4 players", "Versus AI", "Back"]));
+				m.lines = (1..players::MAX_PLAYERS).map(|i| format!("P{}: {}", i + 1, players::Device::for_player(i).name())).collect();
+				m.lines.insert(0, "P1: Keys".to_string());
+				m
+			},
+			GameState::Lobby => {
+				let hosting = self.net.as_ref().is_some_and(|n| n.hosting);
+				let mut m = menu::Menu::new("Online", items(if hosting { &["Start", "Leave"] } else { &["Leave"] }));
+				m.lines = self.get_lobby_lines();
+				m
+			},
+			GameState::ModeSelect => {
+				let modes = self.get_modes();
+				let mut names: Vec<String> = modes.iter().map(|m| m.name().to_string()).collect();
+				names.push("Back".to_string());
+				let mut m = menu::Menu::new("Choose a mode", names);
+				m.selected = modes.iter().position(|m| *m == self.mode).unwrap_or(0);
+				m
+			},
+			GameState::LevelSelect => {
+				let playable = self.get_playable_levels();
+				let mut names: Vec<String> = playable.iter().map(|i| {
+					let name = &self.levels[*i].name;
+					match self.high_scores.get(&self.get_table_name(), name).first() {
+						Some(top) => format!("{}  ({} {})", name, self.format_high_score(top.score), top.name),
+						None => name.clone(),
+					}
+				}).collect();
+				names.push("Back".to_string());
+				let mut m = menu::Menu::new("Choose a level", names);
+				m.selected = playable.iter().position(|i| *i == self.level_index).unwrap_or(0);
+				m
+			},
+			GameState::CarSelect => {
+				let mut names: Vec<String> = self.cars.iter().map(|c| c.name.clone()).collect();
+				names.push("Back".to_string());
+				let mut m = menu::Menu::new("Choose a car", names);
+				m.selected = self.car_index;
+				m
+			},
+			GameState::Settings { .. } => menu::Menu::new("Settings", self.get_settings_items()),
+			GameState::Controls { .. } => menu::Menu::new("Controls", self.get_controls_items()),
+			GameState::Playing => menu::Menu::new("", vec![]),
+			GameState::Paused => menu::Menu::new("Paused", items(&["Resume", "Retry", "Settings", "Finish run", "Quit to title"])),
+			GameState::Results => {
+				let mut m = menu::Menu::new("Results", items(if self.net.is_some() { &["Leave"] } else { &["Retry", "Change level", "Title"] }));
+				m.lines = vec![
+					self.mode.name().to_string(),
+					format!("{} - {}", self.level.name, self.player.spec.name),
+					format!("Score: {}", self.score),
+					format!("Best: {}", self.best_score),
+					format!("Time: {:.2}s", self.run_time + self.tandem.first_leg_time),   // Both legs in tandem
+				];
+				m.lines.extend(self.stats.get_breakdown(self.settings.units));
+				m
+			},
+		};
+
+		if matches!(state, GameState::Title | GameState::PlayerSelect | GameState::ModeSelect | GameState::LevelSelect | GameState::CarSelect) {
+			self.playback = None;
+		}
+		if state == GameState::Title {
+			self.others.clear();
+		}
+		if state == GameState::Title && matches!(self.state, GameState::Paused | GameState::Results) {   // Fresh start for the demo
+			self.player.reset(self.level.start_pos, self.level.start_angle);
+			self.level.pillars = self.levels[self.level_index].pillars.clone();
+			self.demo_driver = ai::AiDriver::default();
+			self.tick_accumulator = 0.0;
+		}
+		if let Some(audio) = self.audio.as_mut() {
+			audio.set_paused(state != GameState::Playing);
+		}
+		self.state = state;
+	}
+
+	fn handle_menu_action(&mut self, action: MenuAction, rl: &mut RaylibHandle, rl_thread: &RaylibThread) {
+		match (self.state, action) {
+			(GameState::Title, MenuAction::Select(0)) => {
+				self.local_players = 1;
+				self.versus_ai = false;
+				self.set_state(GameState::ModeSelect);
+			},
+			(GameState::Title, MenuAction::Select(1)) => self.set_state(GameState::PlayerSelect),
+			(GameState::Title, MenuAction::Select(2)) => self.set_state(GameState::Settings { paused: false }),
+			(GameState::Title, MenuAction::Select(3)) | (GameState::Title, MenuAction::Back) => self.quit = true,
+
+			(GameState::PlayerSelect, MenuAction::Select(i)) if i < 4 => {
+				self.versus_ai = i == 3;
+				self.local_players = if self.versus_ai { 2 } else { i + 2 };
+				self.set_state(GameState::ModeSelect);
+			},
+			(GameState::PlayerSelect, MenuAction::Select(_)) | (GameState::PlayerSelect, MenuAction::Back) => self.set_state(GameState::Title),
+
+			(GameState::Lobby, MenuAction::Select(0)) if self.net.as_ref().is_some_and(|n| n.hosting) => self.host_online_game(rl, rl_thread),
+			(GameState::Lobby, MenuAction::Select(_)) | (GameState::Lobby, MenuAction::Back) => self.leave_online(None),
+
+			(GameState::ModeSelect, MenuAction::Select(i)) if i < self.get_modes().len() => {
+				self.mode = self.get_modes()[i];
+				self.set_state(GameState::LevelSelect);
+			},
+			(GameState::ModeSelect, MenuAction::Select(_)) | (GameState::ModeSelect, MenuAction::Back) => {
+				self.set_state(if self.local_players > 1 { GameState::PlayerSelect } else { GameState::Title });
+			},
+
+			(GameState::LevelSelect, MenuAction::Select(i)) if i < self.get_playable_levels().len() => {
+				self.select_level(self.get_playable_levels()[i]);
+				self.set_state(GameState::CarSelect);
+			},
+			(GameState::LevelSelect, MenuAction::Select(_)) | (GameState::LevelSelect, MenuAction::Back) => self.set_state(GameState::ModeSelect),
+
+			(GameState::CarSelect, MenuAction::Select(i)) if i < self.cars.len() => {
+				self.select_car(i);
+				self.spawn_others();
+				self.load_car_textures(rl, rl_thread);
+				self.start_run();
+			},
+			(GameState::CarSelect, MenuAction::Select(_)) | (GameState::CarSelect, MenuAction::Back) => self.set_state(GameState::LevelSelect),
+
+			(GameState::Settings { paused }, MenuAction::Back) => self.leave_settings(paused),
+			(GameState::Settings { paused }, MenuAction::Select(i)) => match SettingsRow::ALL.get(i) {
+				Some(SettingsRow::Back) | None => self.leave_settings(paused),
+				Some(SettingsRow::Controls) => self.set_state(GameState::Controls { paused }),
+				Some(row) => self.change_setting(*row, 1, rl),
+			},
+			(GameState::Settings { .. }, MenuAction::Adjust(i, dir)) => {
+				if let Some(row) = SettingsRow::ALL.get(i) {
+					self.change_setting(*row, dir, rl);
+				}
+			},
+
+			(GameState::Controls { .. }, MenuAction::Select(i)) if i < settings::Controls::NAMES.len() => {
+				self.rebinding = Some(i);
+				self.menu.items = self.get_controls_items();
+			},
+			(GameState::Controls { .. }, MenuAction::Select(i)) if i == CONTROLS_RESET_ROW => {
+				self.settings.controls = settings::Controls::default();
+				self.menu.items = self.get_controls_items();
+			},
+			(GameState::Controls { paused }, MenuAction::Select(_)) | (GameState::Controls { paused }, MenuAction::Back) => {
+				self.set_state(GameState::Settings { paused });
+				self.menu.selected = SettingsRow::Controls.index();
+			},
+
+			(GameState::Paused, MenuAction::Select(0)) | (GameState::Paused, MenuAction::Back) => self.set_state(GameState::Playing),
+			(GameState::Paused, MenuAction::Select(1)) => {
+				self.reload();
+				self.set_state(GameState::Playing);
+			},
+			(GameState::Paused, MenuAction::Select(2)) => self.set_state(GameState::Settings { paused: true }),
+			(GameState::Paused, MenuAction::Select(3)) => self.finish_run(),
+			(GameState::Paused, MenuAction::Select(4)) => self.set_state(GameState::Title),
+
+			(GameState::Results, MenuAction::Select(_)) | (GameState::Results, MenuAction::Back) if self.net.is_some() => self.leave_online(None),
+			(GameState::Results, MenuAction::Select(0)) => {
+				self.reload();
+				self.set_state(GameState::Playing);
+			},
+			(GameState::Results, MenuAction::Select(1)) => self.set_state(GameState::LevelSelect),
+			(GameState::Results, MenuAction::Select(2)) | (GameState::Results, MenuAction::Back) => self.set_state(GameState::Title),
+			_ => (),
+		}
+	}
+
+	fn get_settings_items(&self) -> Vec<String> {
+		let s = &self.settings;
+		let on_off = |b: bool| if b { "On" } else { "Off" };
+		let locked = if self.is_mid_run() { " (locked mid-run)" } else { "" };
+		SettingsRow::ALL.iter().map(|row| match row {
+			SettingsRow::FrameRate => format!("Frame rate cap: {}", if s.target_fps == 0 { "Uncapped".to_string() } else { s.target_fps.to_string() }),
+			SettingsRow::WindowSize => format!("Window size: {}x{}", s.window_size.0, s.window_size.1),
+			SettingsRow::Fullscreen => format!("Fullscreen: {}", on_off(s.fullscreen)),
+			SettingsRow::Msaa => format!("MSAA: {} (needs restart)", on_off(s.msaa)),
+			SettingsRow::UiScale => format!("UI scale: {:.1}", self.get_ui_scale()),
+			SettingsRow::Debug => format!("Debug overlay: {}", on_off(s.debug)),
+			SettingsRow::Volume => format!("Volume: {:.0}%", s.volume * 100.0),
+			SettingsRow::TractionControl => format!("Traction control: {}{}", on_off(s.traction_control), locked),
+			SettingsRow::Units => format!("Speed units: {}", s.units.label()),
+			SettingsRow::ScoreAttackTime => format!("Score attack length: {}s", s.score_attack_time),
+			SettingsRow::Weather => format!("Weather: {:?}{}", self.level.weather, locked),
+			SettingsRow::Telemetry => format!("Telemetry: {}", on_off(s.telemetry)),
+			SettingsRow::Controls => "Controls".to_string(),
+			SettingsRow::Back => "Back".to_string(),
+		}).collect()
+	}
+
+	fn change_setting(&mut self, row: SettingsRow, dir: i32, rl: &mut RaylibHandle) {
+		match row {
+			SettingsRow::FrameRate => {
+				self.settings.cycle_fps(dir);
+				rl.set_target_fps(self.settings.target_fps);
+			},
+			SettingsRow::WindowSize => {
+				self.settings.cycle_window_size(dir);
+				if !rl.is_window_fullscreen() {
+					rl.set_window_size(self.settings.window_size.0, self.settings.window_size.1);
+				}
+			},
+			SettingsRow::Fullscreen => self.toggle_fullscreen(rl),
+			SettingsRow::Msaa => self.settings.msaa = !self.settings.msaa,
+			SettingsRow::UiScale => self.change_ui_scale(dir),
+			SettingsRow::Debug => self.settings.debug = !self.settings.debug,
+			SettingsRow::Volume => {
+				self.settings.change_volume(dir);
+				if let Some(audio) = self.audio.as_mut() {
+					audio.set_volume(self.settings.volume);
+				}
+			},
+			SettingsRow::TractionControl | SettingsRow::Weather if self.is_mid_run() => (),
+			SettingsRow::TractionControl => {
+				self.settings.traction_control = !self.settings.traction_control;
+				self.player.traction_control = self.settings.traction_control;
+			},
+			SettingsRow::Units => self.settings.units = self.settings.units.toggled(),
+			SettingsRow::ScoreAttackTime => {
+				let secs = self.settings.score_attack_time as i32 + dir * SCORE_ATTACK_TIME_STEP as i32;
+				self.settings.score_attack_time = (secs.max(0) as u32).clamp(MIN_SCORE_ATTACK_TIME, MAX_SCORE_ATTACK_TIME);
+			},
+			SettingsRow::Weather => {
+				self.level.weather = self.level.weather.toggled();
+				self.weather_override = Some(self.level.weather);
+			},
+			SettingsRow::Telemetry => self.settings.telemetry = !self.settings.telemetry,
+			SettingsRow::Controls | SettingsRow::Back => (),
+		}
+		self.menu.items = self.get_settings_items();
+	}
+
+	fn get_controls_items(&self) -> Vec<String> {
+		let mut items: Vec<String> = settings::Controls::NAMES.iter().enumerate().map(|(i, name)| {
+			if self.rebinding == Some(i) {
+				format!("{}: press a key", name)
+			} else {
+				format!("{}: {}", name, settings::key_name(self.settings.controls.get(i).unwrap()))
+			}
+		}).collect();
+		items.push("Reset to defaults".to_string());
+		items.push("Back".to_string());
+		items
+	}
+
+	fn leave_settings(&mut self, paused: bool) {
+		self.save_settings();
+		self.set_state(if paused { GameState::Paused } else { GameState::Title });
+	}
+
+	#[inline]
+	fn save_settings(&self) {
+		if let Err(e) = self.settings.save(settings::SETTINGS_PATH) {
+			println!("Couldn't save settings: {}", e);
+		}
+	}
+
+	#[inline]
+	fn toggle_fullscreen(&mut self, rl: &mut RaylibHandle) {
+		if let Some(view) = self.view.as_mut() {
+			view.toggle_fullscreen(rl);
+			self.settings.fullscreen = rl.is_window_fullscreen();
+		}
+	}
+
+	#[inline]
+	fn change_ui_scale(&mut self, steps: i32) {
+		if let Some(view) = self.view.as_mut() {
+			view.change_ui_scale(steps);
+			self.settings.ui_scale = view.ui_scale;
+		}
+	}
+
+	// Anything that changes the simulation has to wait until the run is over, or its replay would play back differently
+	#[inline]
+	fn is_mid_run(&self) -> bool {
+		matches!(self.state, GameState::Playing | GameState::Paused | GameState::Settings { paused: true } | GameState::Controls { paused: true })
+	}
+
+	#[inline]
+	fn get_ui_scale(&self) -> f32 {
+		self.view.as_ref().map_or(self.settings.ui_scale, |v| v.ui_scale)
+	}
+
+	// Weather is chosen in the menus, so it stays the same whichever level is picked
+	fn select_level(&mut self, i: usize) {
+		self.level_index = i;
+		self.level = self.levels[i].clone();
+		if let Some(weather) = self.weather_override {
+			self.level.weather = weather;
+		}
+	}
+
+	// The new car has no texture, so load one if there's a window
+	fn select_car(&mut self, i: usize) {
+		self.car_index = i;
+		self.player = car::Car::new(&self.cars[i], self.level.start_pos, self.level.start_angle, self.seed, self.particle_budget);
+		self.player.traction_control = self.settings.traction_control;
+	}
+
+	// Leader on the start, chaser behind it, for the current leg
+	fn place_tandem_cars(&mut self) {
+		let lead = self.tandem.get_leader();
+		let (pos, angle) = tandem::get_start(&self.level, lead == 0);
+		self.player.reset(pos, angle);
+		if let Some(p) = self.others.first_mut() {
+			let (pos, angle) = tandem::get_start(&self.level, lead == 1);
+			p.car.reset(pos, angle);
+		}
+	}
+
+	// Swaps who leads and goes again from the start
+	fn start_second_leg(&mut self) {
+		self.tandem.leg = 1;
+		self.tandem.first_leg_time = self.run_time;
+		self.run_time = 0.0;
+		for (i, p) in self.others.iter_mut().enumerate() {
+			p.reset(i + 1, &self.level);
+		}
+		self.place_tandem_cars();
+		self.car_contacts = 0;
+		self.hud.push_popup(format!("Swap! P{} leads", self.tandem.get_leader() + 1), RED_2);
+	}
+
+	#[inline]
+	fn enter_lobby(&mut self, net: net::Session) {
+		self.net = Some(net);
+		self.set_state(GameState::Lobby);
+	}
+
+	fn update_lobby(&mut self, dt: f32, rl: &mut RaylibHandle, rl_thread: &RaylibThread) {
+		let events = match self.net.as_mut() {
+			Some(net) => net.poll(dt),
+			None => return,
+		};
+		for event in events {
+			let error = match event {
+				net::NetEvent::Lobby => {
+					self.menu.lines = self.get_lobby_lines();
+					None
+				},
+				net::NetEvent::Started(setup) => self.start_online(&setup).map(|_| self.load_car_textures(rl, rl_thread)).err(),
+				net::NetEvent::Refused(reason) => Some(format!("Couldn't join: {}", reason)),
+				net::NetEvent::Left(0) | net::NetEvent::Lost(0) => Some("The host left".to_string()),
+				net::NetEvent::Left(_) | net::NetEvent::Lost(_) => None,
+			};
+			if error.is_some() {
+				self.leave_online(error);
+				return;
+			}
+		}
+		if let Some(action) = self.menu.update(rl) {
+			self.handle_menu_action(action, rl, rl_thread);
+		}
+	}
+
+	fn get_lobby_lines(&self) -> Vec<String> {
+		let net = match &self.net {
+			Some(net) => net,
+			None => return vec![],
+		};
+		if !net.hosting && net.players == 0 {
+			return vec!["Connecting to the host".to_string()];
+		}
+		let mut lines = vec![if net.hosting {
+			format!("Hosting on {}", net.get_local_addr())
+		} else {
+			"Waiting for the host to start".to_string()
+		}];
+		lines.extend((0..net.players).map(|i| {
+			format!("P{}{}{}", i + 1, if i == 0 { " (host)" } else { "" }, if i == net.local { " - you" } else { "" })
+		}));
+		if net.hosting {
+			lines.push(format!("{} - {} - {}", self.get_online_mode().name(), self.level.name, self.cars[self.car_index].name));
+		}
+		lines
+	}
+
+	// Modes scored off the pillars are the ones everyone can play at once
+	#[inline]
+	fn get_online_mode(&self) -> GameMode {
+		if self.mode.allows_split_screen() { self.mode } else { GameMode::FreeDrift }
+	}
+
+	// The host's level, car and mode, as chosen on the command line
+	fn host_online_game(&mut self, rl: &mut RaylibHandle, rl_thread: &RaylibThread) {
+		if self.net.as_ref().is_some_and(|n| n.players < 2) {
+			self.menu.lines = self.get_lobby_lines();
+			self.menu.lines.push("Nobody has joined yet".to_string());
+			return;
+		}
+		let setup = net::Setup {
+			players: 0,   // Filled in by the session
+			seed: self.seed,
+			mode: self.get_online_mode().key().to_string(),
+			score_attack_time: self.settings.score_attack_time,
+			rain: self.level.weather == weather::Weather::Rain,
+			traction_control: self.settings.traction_control,
+			level: self.level.name.clone(),
+			car: self.cars[self.car_index].name.clone(),
+		};
+		let setup = match self.net.as_mut() {
+			Some(net) => net.start(setup),
+			None => return,
+		};
+		match self.start_online(&setup) {
+			Ok(()) => self.load_car_textures(rl, rl_thread),
+			Err(e) => self.leave_online(Some(e)),
+		}
+	}
+
+	// Everyone sets up the same run from the host's setup, with a car for each player in player order
+	fn start_online(&mut self, setup: &net::Setup) -> Result<(), String> {
+		let mode = GameMode::from_key(&setup.mode).filter(|m| m.allows_split_screen()).ok_or(format!("the host's mode `{}` can't be played online", setup.mode))?;
+		let level = self.levels.iter().position(|l| l.name == setup.level).ok_or(format!("the host's level `{}` isn't loaded", setup.level))?;
+		let car = self.cars.iter().position(|c| c.name == setup.car).ok_or(format!("the host's car `{}` isn't loaded", setup.car))?;
+
+		self.mode = mode;
+		self.seed = setup.seed;
+		self.select_level(level);
+		self.level.weather = if setup.rain { weather::Weather::Rain } else { weather::Weather::Dry };
+		self.select_car(car);
+		self.player.traction_control = setup.traction_control;
+		self.player.spec.tint = players::PLAYER_COLORS[0];
+		self.others = (1..setup.players).map(|i| {
+			let mut p = players::Player::new(&self.cars[car], i, players::Device::Net, &self.level, self.seed, self.particle_budget);
+			p.car.traction_control = setup.traction_control;
+			p
+		}).collect();
+		self.rollback = Some(rollback::Rollback::default());
+		self.start_run();
+		Ok(())
+	}
+
+	// Online runs go through the rollback, and keep sending inputs after they end for anyone still catching up
+	fn update_online(&mut self, dt: f32, keys: input::Input) {
+		let events = match self.net.as_mut() {
+			Some(net) => net.poll(dt),
+			None => return,
+		};
+		if self.state == GameState::Playing {
+			if let Some(mut rollback) = self.rollback.take() {
+				rollback.update(self, dt, keys);
+				self.rollback = Some(rollback);
+			}
+		}
+		if let Some(net) = self.net.as_mut() {
+			net.send_inputs();
+		}
+
+		let gone = events.iter().find_map(|e| match e {
+			net::NetEvent::Left(i) | net::NetEvent::Lost(i) => Some(*i),
+			_ => None,
+		});
+		if let (Some(i), GameState::Playing) = (gone, self.state) {
+			self.finish_run();
+			self.menu.title = "Connection lost".to_string();
+			self.menu.lines.insert(0, format!("P{} left", i + 1));
+		}
+	}
+
+	fn leave_online(&mut self, reason: Option<String>) {
+		self.net = None;   // Says goodbye as it's dropped
+		self.rollback = None;
+		self.set_state(GameState::Title);
+		if let Some(reason) = reason {
+			self.menu.lines = vec![reason];
+		}
+	}
+
+	// Banks everyone's combos and lists the scores, best first
+	fn get_standings(&mut self) -> Vec<String> {
+		for p in self.others.iter_mut() {
+			if p.combo.is_active() {
+				let (points, multiplier) = p.combo.bank();
+				p.add_score(points, multiplier);
+			}
+		}
+		let first = if self.net.is_some() { "Online" } else { "Keys" };
+		let mut scores: Vec<(usize, u32, String)> = vec![(0, self.score, first.to_string())];
+		scores.extend(self.others.iter().enumerate().map(|(i, p)| (i + 1, p.score, p.device.name())));
+		scores.sort_by_key(|s| std::cmp::Reverse(s.1));   // Stable, so ties keep player order
+
+		let mut lines = vec![match scores.as_slice() {
+			[first, second, ..] if first.1 == second.1 => "Draw!".to_string(),
+			[first, ..] => format!("P{} wins!", first.0 + 1),
+			[] => String::new(),
+		}];
+		lines.extend(scores.iter().map(|(i, score, device)| format!("P{} ({}): {}", i + 1, device, score)));
+		lines
+	}
+
+	// Split screen players, in the same model of car as player one
+	fn spawn_others(&mut self) {
+		let players = if self.mode == GameMode::Tandem { 2 } else { self.local_players };
+		let ai = self.versus_ai || self.local_players == 1;
+		self.others = (1..players).map(|i| {
+			let device = if ai { players::Device::Ai(Box::default()) } else { players::Device::for_player(i) };
+			players::Player::new(&self.cars[self.car_index], i, device, &self.level, self.seed, self.particle_budget)
+		}).collect();
+		if !self.others.is_empty() {
+			self.player.spec.tint = players::PLAYER_COLORS[0];
+		}
+	}
+
+	#[inline]
+	fn get_modes(&self) -> Vec<GameMode> {
+		GameMode::ALL.iter().copied().filter(|m| match m {
+			GameMode::Tandem => self.local_players <= 2,   // Against the AI on your own
+			_ => self.local_players == 1 || m.allows_split_screen(),
+		}).collect()
+	}
+
+	// Puts everything back to the start of the level, for retrying
 	fn reload(&mut self) {
-		self.player.reset();
+		self.player.reset(self.level.start_pos, self.level.start_angle);
+		self.level.pillars = self.levels[self.level_index].pillars.clone();
+		self.closest_pillar_to_player = (0, -1.0);
+		self.player_is_scoring_points = false;
+		self.player_touching_pillar = false;
 		self.score = 0;
+		self.run_time = 0.0;
+		self.combo = combo::Combo::default();
+		self.stats = run_stats::RunStats::default();
+		self.course_run = course::CourseRun::new(self.level.pillars.len());
+		self.judge = judging::Judge::new(self.level.zones.len());
+		self.lap_timer = lap_timer::LapTimer::new(self.best_laps.get(&self.get_run_key()).cloned());
+		self.hud = hud::Hud::default();
+		self.tick_accumulator = 0.0;
+		self.trail_log = trail_export::TrailLog::default();
+		self.graphs.clear();
+		for (i, p) in self.others.iter_mut().enumerate() {
+			p.reset(i + 1, &self.level);
+		}
+		self.car_contacts = 0;
+		self.tandem = tandem::Tandem::default();
+		if self.mode == GameMode::Tandem {
+			self.place_tandem_cars();
+		}
+		if let Some(ai) = self.ai_driver.as_mut() {
+			*ai = ai::AiDriver::default();
+		}
+
+		if let Some(r) = &self.playback {
+			self.level.weather = if r.rain { weather::Weather::Rain } else { weather::Weather::Dry };
+			self.player.traction_control = r.traction_control;
+		}
+		self.telemetry = if (self.settings.telemetry || self.telemetry_path.is_some()) && self.net.is_none() {   // Rollback would muddle it
+			Some(telemetry::Telemetry::new(&self.player, &self.level.name, self.mode.key(), TICK_RATE))
+		} else {
+			None
+		};
+		self.recording = replay::Replay {
+			level: self.level.name.clone(),
+			car: self.player.spec.name.clone(),
+			mode: self.mode.key().to_string(),
+			score_attack_time: self.get_time_limit().map_or(0, |t| t as u32),
+			rain: self.level.weather == weather::Weather::Rain,
+			traction_control: self.player.traction_control,
+			seed: self.seed,
+			tick_rate: TICK_RATE,
+			inputs: vec![],
+		};
+
+		if self.mode == GameMode::ScoreAttack {
+			self.best_score = self.personal_bests.get(&self.level.name, &self.player.spec.name, self.settings.score_attack_time).unwrap_or(0);
+		}
+	}
+
+	fn finish_run(&mut self) {
+		if self.combo.is_active() {
+			let (points, multiplier) = self.combo.bank();
+			self.add_score(points, multiplier);
+		}
+
+		let recorded = self.save_results && self.playback.is_none() && self.ai_driver.is_none() && self.others.is_empty();
+		let mut mode_lines = if recorded { self.submit_personal_best() } else { vec![] };
+		if !self.others.is_empty() {
+			mode_lines.extend(self.get_standings());
+		}
+		if self.mode == GameMode::Tandem {
+			mode_lines.extend(self.tandem.get_breakdown());
+		}
+		if self.mode == GameMode::Gymkhana {
+			mode_lines.extend(self.get_course_results());
+		}
+		if self.mode == GameMode::TimeTrial {
+			mode_lines.extend(self.lap_timer.get_breakdown());
+		}
+		if !self.level.zones.is_empty() {
+			self.judge.finish();
+			mode_lines.extend(self.judge.get_breakdown());
+		}
+		self.save_telemetry();
+		if recorded {
+			mode_lines.extend(self.submit_high_score());
+			if let Err(e) = self.recording.save(replay::LAST_REPLAY_PATH) {
+				println!("Couldn't save replay: {}", e);
+			}
+		}
+		self.set_state(GameState::Results);
+		if self.playback.is_some() {
+			self.menu.title = "Replay".to_string();
+		} else if self.ai_driver.is_some() {
+			self.menu.title = "AI run".to_string();
+		}
+		self.menu.lines.extend(mode_lines);
+	}
+
+	fn get_course_results(&self) -> Vec<String> {
+		let run = &self.course_run;
+		if !run.is_finished(&self.level.course) {
+			return vec![format!("Course not finished ({}/{} pillars)", run.current, self.level.course.len())];
+		}
+		vec![
+			format!("Penalties: +{:.0}s ({} skipped, {} wrong way)", run.penalty_time, run.skipped, run.wrong_direction),
+			format!("Course time: {:.2}s", self.run_time + run.penalty_time),
+		]
+	}
+
+	fn get_objective(&self) -> Option<String> {
+		match self.mode {
+			GameMode::Gymkhana => Some(self.course_run.get_objective(&self.level.course)),
+			GameMode::TimeTrial => Some(self.lap_timer.get_objective(self.level.laps)),
+			GameMode::Tandem => Some(self.tandem.get_objective()),
+			_ => None,
+		}
+	}
+
+	// Shows what happened on the HUD, returning true once all the laps are done
+	fn handle_lap_event(&mut self, event: Option<lap_timer::LapEvent>) -> bool {
+		match event {
+			Some(lap_timer::LapEvent::Started) => self.hud.push_popup("Go!".to_string(), RED_2),
+			Some(lap_timer::LapEvent::Sector(Some(delta))) => {
+				let col = if delta <= 0.0 { Color::new(40, 150, 60, 255) } else { RED_1 };
+				self.hud.push_popup(format!("{:+.2}", delta), col);
+			},
+			Some(lap_timer::LapEvent::Sector(None)) => (),
+			Some(lap_timer::LapEvent::Invalidated) => self.hud.push_popup("Checkpoint missed!".to_string(), RED_1),
+			Some(lap_timer::LapEvent::Finished(lap, is_best)) => {
+				if is_best {
+					self.best_laps.insert(self.get_run_key(), lap.clone());
+					self.play_sound(OneShot::PillarComplete);
+				}
+				let text = if is_best { format!("Best lap {:.2}!", lap.time) } else { format!("Lap {:.2}", lap.time) };
+				self.hud.push_popup(text, if lap.valid { CHARCOAL } else { RED_1 });
+				return self.lap_timer.laps.len() as u32 >= self.level.laps;
+			},
+			None => (),
+		}
+		false
+	}
+
+	#[inline]
+	fn get_run_key(&self) -> (String, String) {
+		(self.level.name.clone(), self.player.spec.name.clone())
+	}
+
+	fn get_playable_levels(&self) -> Vec<usize> {   // Indices of the levels the current mode works on
+		(0..self.levels.len()).filter(|i| self.mode.can_play(&self.levels[*i])).collect()
+	}
+
+	// Saves a finished score attack run if it's a new best, returning lines for the results screen
+	fn submit_personal_best(&mut self) -> Vec<String> {
+		let limit = match self.get_time_limit() {
+			Some(limit) => limit,
+			None => return vec![],
+		};
+		if self.run_time < limit {
+			return vec!["Run ended early, not counted for personal bests".to_string()];
+		}
+
+		let previous = self.personal_bests.submit(&self.level.name, &self.player.spec.name, self.settings.score_attack_time, self.score);
+		let line = match previous {
+			Some(best) if self.score <= best => format!("Personal best: {} ({} short)", best, best - self.score),
+			Some(best) => format!("New personal best! (was {})", best),
+			None => "First personal best set!".to_string(),
+		};
+		if previous.map_or(true, |best| self.score > best) {
+			if let Err(e) = self.personal_bests.save(personal_best::PERSONAL_BESTS_PATH) {
+				println!("Couldn't save personal bests: {}", e);
+			}
+		}
+		vec![line]
+	}
+
+	// Name of the leaderboard for the current mode. Score attack runs of different lengths go in separate tables.
+	fn get_table_name(&self) -> String {
+		match self.mode {
+			GameMode::FreeDrift => self.mode.key().to_string(),
+			GameMode::ScoreAttack => format!("{}_{}s", self.mode.key(), self.settings.score_attack_time),
+			GameMode::Gymkhana | GameMode::TimeTrial | GameMode::Tandem => self.mode.key().to_string(),
+		}
+	}
+
+	// What the run puts on the leaderboard, or None if it didn't count
+	fn get_high_score_result(&self) -> Option<u32> {
+		match self.mode {
+			GameMode::FreeDrift => Some(self.score).filter(|s| *s > 0),
+			GameMode::ScoreAttack => self.get_time_limit().filter(|limit| self.run_time >= *limit).map(|_| self.score),
+			GameMode::Gymkhana if self.course_run.is_finished(&self.level.course) => {
+				Some(((self.run_time + self.course_run.penalty_time) * 1000.0) as u32)
+			},
+			GameMode::Gymkhana => None,
+			GameMode::TimeTrial => self.lap_timer.laps.iter()
+				.filter(|l| l.valid)
+				.map(|l| (l.time * 1000.0) as u32)
+				.min(),
+			GameMode::Tandem => None,   // Always against someone, so never recorded
+		}
+	}
+
+	#[inline]
+	fn format_high_score(&self, score: u32) -> String {
+		if self.mode.is_timed() { format!("{:.2}s", score as f32/1000.0) } else { score.to_string() }
+	}
+
+	// Adds the run to the leaderboard and saves it, returning the table for the results screen
+	fn submit_high_score(&mut self) -> Vec<String> {
+		let table = self.get_table_name();
+		let replay_path = format!("{}/{}_{}_{}.{}", replay::REPLAY_DIR, table, self.level.name.to_lowercase().replace(' ', "_"),
+			high_scores::get_timestamp(), replay::REPLAY_EXTENSION);
+		let rank = self.get_high_score_result().and_then(|score| {
+			let entry = high_scores::HighScore {
+				score,
+				name: self.settings.player_name.clone(),
+				date: high_scores::get_today(),
+				car: self.player.spec.name.clone(),
+				replay: Some(replay_path.clone()),
+			};
+			self.high_scores.submit(&table, &self.level.name, entry, self.mode.is_timed())
+		});
+		if rank.is_some() {
+			if let Err(e) = self.recording.save(&replay_path) {
+				println!("Couldn't save replay: {}", e);
+			}
+			if let Err(e) = self.high_scores.save(high_scores::HIGH_SCORES_PATH) {
+				println!("Couldn't save high scores: {}", e);
+			}
+		}
+
+		let mut lines = vec![match rank {
+			Some(r) => format!("High scores - you placed #{}!", r + 1),
+			None => "High scores".to_string(),
+		}];
+		for (i, s) in self.high_scores.get(&table, &self.level.name).iter().take(RESULTS_HIGH_SCORES).enumerate() {
+			let marker = if Some(i) == rank { "> " } else { "" };
+			lines.push(format!("{}{}. {}  {}  {}  {}", marker, i + 1, self.format_high_score(s.score), s.name, s.car, s.date));
+		}
+		lines
 	}
 
 	#[inline]
-	fn add_pillar(&mut self, p: Vector2, r: f32) {
-		self.pillars.push( pillar::Pillar::new(p, r) );
+	fn get_time_limit(&self) -> Option<f32> {
+		match self.mode {
+			GameMode::ScoreAttack => {
+				let online = self.net.as_ref().and_then(|n| n.setup.as_ref()).map(|s| s.score_attack_time);
+				let replay = self.playback.as_ref().map(|r| r.score_attack_time);
+				Some(online.or(replay).unwrap_or(self.settings.score_attack_time) as f32)
+			},
+			GameMode::Tandem => Some(tandem::LEG_TIME),
+			GameMode::FreeDrift | GameMode::Gymkhana | GameMode::TimeTrial => None,
+		}
+	}
+
+	fn add_score(&mut self, points: u32, multiplier: u32) {
+		let total = points * multiplier;
+		self.score += total;
+		self.best_score = self.best_score.max(self.score);
+		self.stats.add_combo(points, multiplier);
+		self.hud.push_popup(format!("+{}", total), RED_1);
 	}
 
 	#[inline]
-	fn get_points_from_dist(&mut self, dt: f32, dist: f32) -> u32 {    // Gets the points scored from the distance to the peg
-		(dt * (POINT_DIST_THRESHOLD - dist) * MAX_POINTS_PER_FRAME as f32).ceil() as u32
+	fn play_sound(&mut self, shot: OneShot) {
+		if let Some(audio) = self.audio.as_mut() {
+			audio.play(shot);
+		}
+	}
+
+	// Every pair of cars, with a crash sound when a pair first touches
+	fn collide_cars(&mut self) {
+		let mut contacts = 0;
+		let mut pair = 0;
+		for i in 0..self.others.len() {
+			if car::collide(&mut self.player, &mut self.others[i].car) { contacts |= 1 << pair }
+			pair += 1;
+			let (left, right) = self.others.split_at_mut(i + 1);
+			for other in right.iter_mut() {
+				if car::collide(&mut left[i].car, &mut other.car) { contacts |= 1 << pair }
+				pair += 1;
+			}
+		}
+		if contacts & !self.car_contacts != 0 {
+			self.play_sound(OneShot::Collision);
+		}
+		self.car_contacts = contacts;
 	}
 
 	fn get_closest_pillar_to_player(&self) -> (i32, f32) {
 		let mut closest = (0, -1.0);
-		for (i, p) in self.pillars.iter().enumerate() {
+		for (i, p) in self.level.pillars.iter().enumerate() {
 			let dist = p.distance_to(self.player.pos);
 			if dist < closest.1 || closest.1 < 0.0 {
 				closest = (i as i32, dist);
@@ -137,27 +1591,143 @@ impl Game {
 	}
 }
 
+#[inline]
+fn get_points_from_dist(dt: f32, dist: f32) -> u32 {    // Gets the points scored from the distance to the peg
+	(dt * (POINT_DIST_THRESHOLD - dist) * MAX_POINTS_PER_FRAME as f32).ceil() as u32
+}
+
 fn main() {
-	let (mut rl, rl_thread) = raylib::init()
-		.size(1000, 800)
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	let opts = match cli::parse(&args) {
+		Ok(opts) => opts,
+		Err(e) => {
+			println!("{}\n\n{}", e, cli::USAGE);
+			std::process::exit(1);
+		},
+	};
+	if opts.help {
+		print!("{}", cli::USAGE);
+		return;
+	}
+	if let Some(path) = &opts.render_audio {
+		let wav = synth::encode_wav(&synth::render_demo(6.0), synth::SAMPLE_RATE);
+		std::fs::write(path, wav).expect("Couldn't write audio file.");
+		println!("Wrote {}", path);
+		return;
+	}
+
+	let settings = settings::Settings::load(settings::SETTINGS_PATH);
+	let mut g = match Game::new(settings, opts.seed.unwrap_or_else(rand::random)) {
+		Ok(g) => g,
+		Err(e) => {
+			println!("{}", e);
+			std::process::exit(1);
+		},
+	};
+	let start = match g.apply_options(&opts) {
+		Ok(start) => start,
+		Err(e) => {
+			println!("{}", e);
+			std::process::exit(1);
+		},
+	};
+
+	if opts.env || opts.env_listen.is_some() {
+		run_env(g, &opts);
+		return;
+	}
+	if opts.headless {
+		run_headless(&mut g, &opts);
+		return;
+	}
+	let session = match open_session(&opts) {
+		Ok(session) => session,
+		Err(e) => {
+			println!("{}", e);
+			std::process::exit(1);
+		},
+	};
+
+	let window_size = opts.window_size.unwrap_or(g.settings.window_size);
+	let mut builder = raylib::init();
+	builder.size(window_size.0, window_size.1)
 		.title("Drift")
-		.msaa_4x()
-		.build();
+		.resizable();
+	if g.settings.msaa {
+		builder.msaa_4x();
+	}
+	let (mut rl, rl_thread) = builder.build();
 
-	rl.set_target_fps(144 * 2);
+	rl.set_target_fps(opts.fps.unwrap_or(g.settings.target_fps));
+	rl.set_window_min_size(view::MIN_WINDOW_W, view::MIN_WINDOW_H);
 
-	let mut g = Game::new(&mut rl, &rl_thread, Vector2::new(300.0, 300.0));
-	/*
-	g.add_pillar(Vector2::new(300.0, 400.0), 7.0);
-	g.add_pillar(Vector2::new(700.0, 400.0), 7.0);
-	g.add_pillar(Vector2::new(500.0, 300.0), 7.0);
-	*/
+	rl.set_exit_key(None);   // Escape pauses instead
 
-	g.add_pillar(Vector2::new(500.0, 400.0), 7.0);
+	let internal_res = opts.internal_res.unwrap_or((view::DEF_INTERNAL_W, view::DEF_INTERNAL_H));
+	let view = view::View::new(&mut rl, &rl_thread, internal_res.0, internal_res.1, opts.ui_scale.unwrap_or(g.settings.ui_scale));
+	g.attach_window(&mut rl, &rl_thread, view);
+	if let Some(session) = session {
+		g.enter_lobby(session);
+	} else if start {
+		g.start_run();
+	}
 
-    while !rl.window_should_close() {
-        g.update(rl.get_frame_time(), &mut rl);
-        g.draw(&mut rl, &rl_thread);
-    }
+	while !rl.window_should_close() && !g.quit {
+		g.update(rl.get_frame_time(), &mut rl, &rl_thread);
+		g.draw(&mut rl, &rl_thread);
+	}
+	g.save_settings();   // Keeps anything changed with the hotkeys
+	if let Some(path) = &opts.export_trails {
+		g.export_trails(path);
+	}
 }
 
+fn open_session(opts: &cli::Options) -> Result<Option<net::Session>, String> {
+	let conditions = net::Conditions {
+		latency: opts.net_latency.unwrap_or(0) as f32/1000.0,
+		loss: opts.net_loss.unwrap_or(0.0)/100.0,
+	};
+	match (&opts.host, &opts.join) {
+		(Some(addr), _) => net::Session::host(addr, conditions).map(Some),
+		(None, Some(addr)) => net::Session::join(addr, conditions).map(Some),
+		(None, None) => Ok(None),
+	}
+}
+
+fn run_env(g: Game, opts: &cli::Options) {
+	let mut env = env::DriftEnv::new(g, opts.env_ticks.unwrap_or(env::DEF_TICKS_PER_STEP), opts.ticks.map(|t| t as u32));
+	let result = match &opts.env_listen {
+		Some(addr) => env.listen(addr),
+		None => env.serve(std::io::stdin().lock(), std::io::stdout().lock()).map_err(|e| e.to_string()),
+	};
+	if let Err(e) = result {
+		eprintln!("{}", e);
+		std::process::exit(1);
+	}
+}
+
+// Simulates a run as fast as possible and prints the results screen
+fn run_headless(g: &mut Game, opts: &cli::Options) {
+	g.save_results = false;
+	g.start_run();
+
+	let ticks = opts.ticks.or(g.playback.as_ref().map(|r| r.inputs.len() as u64)).unwrap_or(0);
+	for _ in 0..ticks {
+		if g.state != GameState::Playing { break }
+		match g.next_input(input::Input::default()) {
+			Some(input) => g.tick(input),
+			None => break,
+		}
+	}
+	if g.state == GameState::Playing {
+		g.finish_run();
+	}
+
+	println!("{} ({} ticks at {} per second)", g.menu.title, g.recording.inputs.len(), TICK_RATE);
+	for line in g.menu.lines.iter() {
+		println!("  {}", line);
+	}
+	if let Some(path) = &opts.export_trails {
+		g.export_trails(path);
+	}
+}
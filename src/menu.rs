@@ -0,0 +1,102 @@
+use raylib::{math::Rectangle, drawing::{RaylibDraw, RaylibDrawHandle}, color::Color, consts::KeyboardKey, RaylibHandle};
+
+use crate::{
+	hud::draw_text_centred,
+	CHARCOAL, RED_1, RED_2,
+};
+
+static OVERLAY_COLOR: Color = Color { r: 230, g: 230, b: 220, a: 200 };
+static HIGHLIGHT_COLOR: Color = Color { r: 232, g: 89, b: 79, a: 50 };
+
+pub enum MenuAction {
+	Select(usize),
+	Adjust(usize, i32),   // Left or right pressed on an item, for changing settings
+	Back,
+}
+
+pub struct Menu {    // Vertical list of items picked with the keyboard, drawn over the top of the game
+	pub title: String,
+	pub lines: Vec<String>,   // Text between the title and the items, e.g. a score breakdown
+	pub items: Vec<String>,
+	pub selected: usize,
+}
+
+impl Menu {
+	pub fn new(title: &str, items: Vec<String>) -> Menu {
+		Menu {
+			title: title.to_string(),
+			lines: vec![],
+			items,
+			selected: 0,
+		}
+	}
+
+	pub fn update(&mut self, rl: &RaylibHandle) -> Option<MenuAction> {
+		let pressed = |a: KeyboardKey, b: KeyboardKey| rl.is_key_pressed(a) || rl.is_key_pressed(b);
+		let count = self.items.len();
+		if count == 0 { return None }
+
+		if pressed(KeyboardKey::KEY_UP, KeyboardKey::KEY_W) {
+			self.selected = (self.selected + count - 1) % count;
+		}
+		if pressed(KeyboardKey::KEY_DOWN, KeyboardKey::KEY_S) {
+			self.selected = (self.selected + 1) % count;
+		}
+
+		if pressed(KeyboardKey::KEY_ENTER, KeyboardKey::KEY_SPACE) {
+			Some(MenuAction::Select(self.selected))
+		} else if pressed(KeyboardKey::KEY_LEFT, KeyboardKey::KEY_A) {
+			Some(MenuAction::Adjust(self.selected, -1))
+		} else if pressed(KeyboardKey::KEY_RIGHT, KeyboardKey::KEY_D) {
+			Some(MenuAction::Adjust(self.selected, 1))
+		} else if pressed(KeyboardKey::KEY_ESCAPE, KeyboardKey::KEY_BACKSPACE) {
+			Some(MenuAction::Back)
+		} else {
+			None
+		}
+	}
+
+	pub fn draw(&self, d: &mut RaylibDrawHandle, ui_scale: f32) {
+		let w = d.get_screen_width() as f32;
+		let h = d.get_screen_height() as f32;
+		let unit = h/800.0 * ui_scale;
+		let font = |size: f32| (size * unit) as i32;
+
+		d.draw_rectangle(0, 0, w as i32, h as i32, OVERLAY_COLOR);
+
+		let mut y = h * 0.18;
+		draw_text_centred(d, &self.title, w/2.0, y, font(56.0), RED_1);
+		y += 80.0 * unit;
+
+		for line in self.lines.iter() {
+			draw_text_centred(d, line, w/2.0, y, font(22.0), CHARCOAL);
+			y += 28.0 * unit;
+		}
+		if !self.lines.is_empty() {
+			y += 20.0 * unit;
+		}
+
+		// Scroll long lists to keep the selected item on screen
+		let item_h = 46.0 * unit;
+		let visible = (((h - y)/item_h) as usize).max(1);
+		let first = (self.selected + 1).saturating_sub(visible);
+		if first > 0 {
+			draw_text_centred(d, "...", w/2.0, y - 24.0 * unit, font(20.0), CHARCOAL);
+		}
+
+		for (i, item) in self.items.iter().enumerate().skip(first).take(visible) {
+			let col = if i == self.selected {
+				let bar_w = 320.0 * unit;
+				d.draw_rectangle_rec(Rectangle::new(w/2.0 - bar_w/2.0, y - 6.0 * unit, bar_w, 40.0 * unit), HIGHLIGHT_COLOR);
+				RED_2
+			} else {
+				CHARCOAL
+			};
+			draw_text_centred(d, item, w/2.0, y, font(28.0), col);
+			y += item_h;
+		}
+		if first + visible < self.items.len() {
+			draw_text_centred(d, "...", w/2.0, y - 12.0 * unit, font(20.0), CHARCOAL);
+		}
+	}
+}
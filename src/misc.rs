@@ -1,3 +1,5 @@
+use std::fs;
+
 use raylib::{math::Vector2, consts};
 use crate::TWO_PI;
 
@@ -6,6 +8,11 @@ pub fn get_components(mag: f32, angle: f32) -> Vector2 {
 	Vector2 { x: mag * angle.sin(), y: mag * angle.cos() }
 }
 
+#[inline]
+pub fn get_heading(v: Vector2) -> f32 {   // Opposite of get_components, the angle a car pointing along v would have
+	v.x.atan2(v.y)
+}
+
 #[inline]
 pub fn rotate_vec(v: Vector2, angle: f32) -> Vector2 {
 	let a_c = angle.cos();
@@ -19,10 +26,15 @@ pub fn get_angle_to(from: &Vector2, to: &Vector2) -> f32 {
     (to.y - from.y).atan2(to.x - from.x)
 }
 
-// For 0 -> 2pi range. Returns reflex angles too
-pub fn get_angle_diff(target: f32, current: f32) -> f32 {
-	let a = (target - current) % TWO_PI;
-	let b = (current - target) % TWO_PI;
+// Writes to a temporary file first then renames it over the old one, so a crash part way through can't leave a half written file
+pub fn write_atomic(path: &str, text: &str) -> Result<(), String> {
+	let tmp_path = format!("{}.tmp", path);
+	fs::write(&tmp_path, text).map_err(|e| format!("{}: {}", tmp_path, e))?;
+	fs::rename(&tmp_path, path).map_err(|e| format!("{}: {}", path, e))
+}
 
-	if a < b { println!("returning a"); -a } else { println!("returning b"); b }
+// Wraps into -pi -> pi, for the change in an angle between frames
+#[inline]
+pub fn wrap_angle(angle: f32) -> f32 {
+	(angle + TWO_PI/2.0).rem_euclid(TWO_PI) - TWO_PI/2.0
 }
@@ -0,0 +1,513 @@
+// Online play over UDP. One player hosts and the others join by address. The host runs the lobby
+// and decides the level, car and mode, then every player sends their inputs straight to every
+// other player each frame. Nothing else is sent: the simulation is deterministic, so the same
+// inputs give the same run everywhere. Rollback (rollback.rs) hides the wait for them.
+//
+// Messages are one line of text per datagram, apart from `start` which has the names on their own lines:
+//   join <version>                      client -> host, repeated until the game starts
+//   refuse <reason>                     host -> client
+//   lobby <your index> <players>        host -> client, whenever the lobby changes and as a keep alive
+//   start <your index> <seed> <mode> <score attack secs> <rain 0/1> <traction control 0/1> <address per player>
+//         <level name>
+//         <car name>                    host -> client, repeated until the client's inputs arrive
+//   input <player> <ack> <first> <hex>  everyone -> everyone, one hex digit of Input bits per tick from tick
+//                                       `first`, and how many of the receiver's inputs the sender has
+//   leave
+//
+// Latency and packet loss can be added to everything sent, for trying it out over loopback.
+
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Instant;
+
+use crate::input::Input;
+
+pub const DEF_PORT: u16 = 7777;
+pub const MAX_PLAYERS: usize = 8;
+pub const INPUT_DELAY: usize = 6;   // Ticks local input is held back, so it usually reaches everyone before they need it
+const PROTOCOL_VERSION: u32 = 1;
+const RESEND_INTERVAL: f32 = 0.2;   // Seconds between repeats of lobby and start messages
+const TIMEOUT: f32 = 5.0;           // Seconds without hearing from a player before giving up on them
+const MAX_RESEND: usize = 480;      // Most ticks of input in one message, two seconds' worth
+const MAX_DATAGRAM: usize = 1500;
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Conditions {   // Made worse on purpose, for testing
+	pub latency: f32,     // Seconds added to every message sent
+	pub loss: f32,        // 0 -> 1, share of messages dropped
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Setup {   // What the host chose, the same for everyone
+	pub players: usize,
+	pub seed: u64,
+	pub mode: String,
+	pub score_attack_time: u32,
+	pub rain: bool,
+	pub traction_control: bool,
+	pub level: String,
+	pub car: String,
+}
+
+pub enum NetEvent {
+	Lobby,             // Players joined or left, or our index changed
+	Started(Setup),
+	Refused(String),
+	Left(usize),       // Player said they were going
+	Lost(usize),       // Player went quiet
+}
+
+struct Peer {
+	addr: SocketAddr,
+	silent: f32,      // Seconds since we last heard from them
+	acked: usize,     // Ticks of our inputs they have
+	started: bool,    // Host only, whether they've got the start message
+}
+
+impl Peer {
+	fn new(addr: SocketAddr) -> Peer {
+		Peer { addr, silent: 0.0, acked: 0, started: false }
+	}
+}
+
+// Sends through the simulated conditions
+struct Link {
+	socket: UdpSocket,
+	conditions: Conditions,
+	clock: Instant,
+	queue: VecDeque<(f32, SocketAddr, String)>,   // Delayed messages and when they go, in order since the delay is fixed
+}
+
+impl Link {
+	fn new(socket: UdpSocket, conditions: Conditions) -> Result<Link, String> {
+		socket.set_nonblocking(true).map_err(|e| e.to_string())?;
+		Ok(Link { socket, conditions, clock: Instant::now(), queue: VecDeque::new() })
+	}
+
+	fn send(&mut self, addr: SocketAddr, msg: String) {
+		if self.conditions.loss > 0.0 && rand::random::<f32>() < self.conditions.loss {
+			return;
+		}
+		if self.conditions.latency > 0.0 {
+			self.queue.push_back((self.clock.elapsed().as_secs_f32() + self.conditions.latency, addr, msg));
+		} else {
+			self.send_now(addr, &msg);
+		}
+	}
+
+	#[inline]
+	fn send_now(&self, addr: SocketAddr, msg: &str) {
+		// Nothing to be done about a failed send, the same as a lost one
+		let _ = self.socket.send_to(msg.as_bytes(), addr);
+	}
+
+	fn flush(&mut self) {
+		let now = self.clock.elapsed().as_secs_f32();
+		while self.queue.front().is_some_and(|m| m.0 <= now) {
+			if let Some((_, addr, msg)) = self.queue.pop_front() {
+				self.send_now(addr, &msg);
+			}
+		}
+	}
+
+	fn recv(&self) -> Option<(SocketAddr, String)> {
+		let mut buf = [0; MAX_DATAGRAM];
+		loop {
+			match self.socket.recv_from(&mut buf) {
+				Ok((len, addr)) => return Some((addr, String::from_utf8_lossy(&buf[..len]).into_owned())),
+				Err(e) if e.kind() == ErrorKind::WouldBlock => return None,
+				Err(_) => continue,   // e.g. a previous send bouncing, ignore it
+			}
+		}
+	}
+}
+
+pub struct Session {
+	link: Link,
+	pub hosting: bool,
+	pub local: usize,                 // Our player index
+	pub players: usize,               // Including us, as of the last lobby message for clients
+	peers: Vec<Option<Peer>>,         // By player index, None for us. Clients only know the host until the start.
+	pub setup: Option<Setup>,         // Once the game has started
+	inputs: Vec<Vec<Input>>,          // By player, every tick so far that we know about
+	resend_timer: f32,
+}
+
+impl Session {
+	pub fn host(addr: &str, conditions: Conditions) -> Result<Session, String> {
+		let socket = UdpSocket::bind(addr).map_err(|e| format!("{}: {}", addr, e))?;
+		Ok(Session::new(Link::new(socket, conditions)?, true, vec![None]))
+	}
+
+	pub fn join(addr: &str, conditions: Conditions) -> Result<Session, String> {
+		let host = addr.to_socket_addrs().ok().and_then(|mut a| a.next()).ok_or(format!("can't find `{}`", addr))?;
+		let bind = if host.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+		let socket = UdpSocket::bind(bind).map_err(|e| e.to_string())?;
+		let mut session = Session::new(Link::new(socket, conditions)?, false, vec![Some(Peer::new(host))]);
+		session.link.send(host, format!("join {}", PROTOCOL_VERSION));
+		Ok(session)
+	}
+
+	fn new(link: Link, hosting: bool, peers: Vec<Option<Peer>>) -> Session {
+		Session {
+			link,
+			hosting,
+			local: 0,
+			players: if hosting { 1 } else { 0 },
+			peers,
+			setup: None,
+			inputs: vec![],
+			resend_timer: 0.0,
+		}
+	}
+
+	#[inline]
+	pub fn get_local_addr(&self) -> String {
+		self.link.socket.local_addr().map_or("?".to_string(), |a| a.to_string())
+	}
+
+	// Host only, sends everyone the setup with their address list, and starts
+	pub fn start(&mut self, mut setup: Setup) -> Setup {
+		setup.players = self.peers.len();
+		self.players = setup.players;
+		self.begin(&setup);
+		self.send_start();
+		setup
+	}
+
+	fn begin(&mut self, setup: &Setup) {
+		self.inputs = vec![vec![]; setup.players];
+		self.inputs[self.local] = vec![Input::default(); INPUT_DELAY];
+		self.setup = Some(setup.clone());
+	}
+
+	fn send_start(&mut self) {
+		let setup = match &self.setup {
+			Some(s) => s.clone(),
+			None => return,
+		};
+		let addrs: Vec<String> = self.peers.iter().map(|p| p.as_ref().map_or("-".to_string(), |p| p.addr.to_string())).collect();
+		for i in 0..self.peers.len() {
+			let addr = match &self.peers[i] {
+				Some(p) if !p.started => p.addr,
+				_ => continue,
+			};
+			self.link.send(addr, get_start(i, &setup, &addrs));
+		}
+	}
+
+	fn send_lobby(&mut self) {
+		let count = self.peers.len();
+		for i in 1..count {
+			if let Some(addr) = self.peers[i].as_ref().map(|p| p.addr) {
+				self.link.send(addr, format!("lobby {} {}", i, count));
+			}
+		}
+	}
+
+	// Reads everything that's arrived and keeps the lobby going. Call every frame.
+	pub fn poll(&mut self, dt: f32) -> Vec<NetEvent> {
+		let mut events = vec![];
+		while let Some((addr, msg)) = self.link.recv() {
+			let from = self.peers.iter().position(|p| p.as_ref().is_some_and(|p| p.addr == addr));
+			if let Some(p) = from.and_then(|i| self.peers[i].as_mut()) {
+				p.silent = 0.0;
+			}
+			self.handle(addr, from, &msg, &mut events);
+		}
+
+		for i in 0..self.peers.len() {
+			let lost = match self.peers[i].as_mut() {
+				Some(p) => { p.silent += dt; p.silent > TIMEOUT },
+				None => false,
+			};
+			if lost && self.hosting && self.setup.is_none() {
+				self.peers.remove(i);
+				self.players = self.peers.len();
+				self.send_lobby();
+				events.push(NetEvent::Lobby);
+				break;   // Indices have moved, the rest get checked next time
+			} else if lost {
+				self.peers[i] = None;
+				events.push(NetEvent::Lost(i));
+			}
+		}
+
+		self.resend_timer += dt;
+		if self.resend_timer >= RESEND_INTERVAL {
+			self.resend_timer = 0.0;
+			match (self.hosting, self.setup.is_some()) {
+				(true, false) => self.send_lobby(),
+				(true, true) => self.send_start(),
+				(false, false) => {
+					if let Some(host) = self.peers[0].as_ref().map(|p| p.addr) {
+						self.link.send(host, format!("join {}", PROTOCOL_VERSION));
+					}
+				},
+				(false, true) => (),   // Inputs keep everyone in touch
+			}
+		}
+		self.link.flush();
+		events
+	}
+
+	fn handle(&mut self, addr: SocketAddr, from: Option<usize>, msg: &str, events: &mut Vec<NetEvent>) {
+		let mut lines = msg.lines();
+		let mut words = lines.next().unwrap_or("").split_whitespace();
+		match (words.next(), from) {
+			(Some("join"), _) if self.hosting => {
+				let version = words.next().and_then(|v| v.parse::<u32>().ok());
+				let refusal = if version != Some(PROTOCOL_VERSION) {
+					Some("different version of the game".to_string())
+				} else if from.is_none() && self.setup.is_some() {
+					Some("game has already started".to_string())
+				} else if from.is_none() && self.peers.len() >= MAX_PLAYERS {
+					Some("lobby is full".to_string())
+				} else {
+					None
+				};
+				match refusal {
+					Some(reason) => self.link.send(addr, format!("refuse {}", reason)),
+					None if from.is_none() => {
+						self.peers.push(Some(Peer::new(addr)));
+						self.players = self.peers.len();
+						self.send_lobby();
+						events.push(NetEvent::Lobby);
+					},
+					None => (),   // Already in, the next lobby message answers it
+				}
+			},
+			(Some("refuse"), Some(0)) if !self.hosting => events.push(NetEvent::Refused(words.collect::<Vec<_>>().join(" "))),
+			(Some("lobby"), Some(0)) if !self.hosting && self.setup.is_none() => {
+				if let (Some(Ok(local)), Some(Ok(players))) = (words.next().map(str::parse), words.next().map(str::parse)) {
+					if (local, players) != (self.local, self.players) {
+						self.local = local;
+						self.players = players;
+						events.push(NetEvent::Lobby);
+					}
+				}
+			},
+			(Some("start"), Some(0)) if !self.hosting && self.setup.is_none() => {
+				let host = addr;
+				match parse_start(&mut words, &mut lines) {
+					Some((local, setup, addrs)) => {
+						self.local = local;
+						self.players = setup.players;
+						self.peers = addrs.iter().enumerate().map(|(i, a)| match (i, a) {
+							(0, _) => Some(Peer::new(host)),
+							(i, _) if i == local => None,
+							(_, a) => a.map(Peer::new),
+						}).collect();
+						self.begin(&setup);
+						events.push(NetEvent::Started(setup));
+					},
+					None => events.push(NetEvent::Refused("couldn't read the host's setup".to_string())),
+				}
+			},
+			(Some("input"), Some(i)) => self.receive_inputs(i, &mut words),
+			(Some("leave"), Some(i)) if self.hosting && self.setup.is_none() => {
+				self.peers.remove(i);
+				self.players = self.peers.len();
+				self.send_lobby();
+				events.push(NetEvent::Lobby);
+			},
+			(Some("leave"), Some(i)) => {
+				self.peers[i] = None;
+				events.push(NetEvent::Left(i));
+			},
+			_ => (),   // From a stranger, or out of turn
+		}
+	}
+
+	fn receive_inputs<'a, I: Iterator<Item = &'a str>>(&mut self, from: usize, words: &mut I) {
+		let mut number = || words.next().and_then(|w| w.parse::<usize>().ok());
+		let (player, ack, first) = match (number(), number(), number()) {
+			(Some(p), Some(a), Some(f)) if p == from && p < self.inputs.len() => (p, a, f),
+			_ => return,
+		};
+		if let Some(peer) = self.peers[from].as_mut() {
+			peer.acked = peer.acked.max(ack);
+			peer.started = true;
+		}
+		let known = &mut self.inputs[player];
+		for (tick, c) in (first..).zip(words.next().unwrap_or("").chars()) {
+			if tick > known.len() { break }   // A gap, what's missing will be sent again
+			if tick < known.len() { continue }
+			match c.to_digit(16) {
+				Some(bits) => known.push(Input::from_bits(bits as u8)),
+				None => break,
+			}
+		}
+	}
+
+	// Everyone gets our inputs from where they last said they were up to
+	pub fn send_inputs(&mut self) {
+		let ours = &self.inputs[self.local];
+		let mut msgs = vec![];
+		for (i, peer) in self.peers.iter().enumerate() {
+			let peer = match peer {
+				Some(p) => p,
+				None => continue,
+			};
+			let first = peer.acked.min(ours.len());
+			let last = ours.len().min(first + MAX_RESEND);
+			let hex: String = ours[first..last].iter().map(|input| format!("{:x}", input.to_bits())).collect();
+			msgs.push((peer.addr, format!("input {} {} {} {}", self.local, self.inputs[i].len(), first, hex)));
+		}
+		for (addr, msg) in msgs {
+			self.link.send(addr, msg);
+		}
+		self.link.flush();
+	}
+
+	#[inline]
+	pub fn add_local_input(&mut self, input: Input) {
+		self.inputs[self.local].push(input);
+	}
+
+	// Ticks up to which everyone's inputs are known
+	#[inline]
+	pub fn get_confirmed(&self) -> usize {
+		self.inputs.iter().map(|i| i.len()).min().unwrap_or(0)
+	}
+
+	#[inline]
+	pub fn get_known(&self, player: usize, tick: usize) -> Option<Input> {
+		self.inputs.get(player).and_then(|i| i.get(tick)).copied()
+	}
+
+	// The real input if it's arrived, otherwise a guess that they're still pressing what they last were
+	#[inline]
+	pub fn get_input(&self, player: usize, tick: usize) -> Input {
+		let known = &self.inputs[player];
+		known.get(tick).or_else(|| known.last()).copied().unwrap_or_default()
+	}
+
+	// Tells everyone straight away, without the simulated conditions
+	pub fn leave(&mut self) {
+		for p in self.peers.iter().flatten() {
+			self.link.send_now(p.addr, "leave");
+		}
+		self.peers.iter_mut().for_each(|p| *p = None);
+	}
+}
+
+impl Drop for Session {
+	fn drop(&mut self) {
+		self.leave();
+	}
+}
+
+// The start message for player `local`, with `-` standing in for any address that isn't known
+fn get_start(local: usize, setup: &Setup, addrs: &[String]) -> String {
+	format!("start {} {} {} {} {} {} {}\n{}\n{}", local, setup.seed, setup.mode, setup.score_attack_time,
+		setup.rain as u8, setup.traction_control as u8, addrs.join(" "), setup.level, setup.car)
+}
+
+// Index, setup and addresses from a start message, with the host's address left as None
+fn parse_start<'a, W: Iterator<Item = &'a str>, L: Iterator<Item = &'a str>>(words: &mut W, lines: &mut L) -> Option<(usize, Setup, Vec<Option<SocketAddr>>)> {
+	let local = words.next()?.parse().ok()?;
+	let seed = words.next()?.parse().ok()?;
+	let mode = words.next()?.to_string();
+	let score_attack_time = words.next()?.parse().ok()?;
+	let rain = words.next()? == "1";
+	let traction_control = words.next()? == "1";
+	let addrs: Vec<Option<SocketAddr>> = words.map(|w| w.parse().ok()).collect();
+	if addrs.len() < 2 || addrs.len() > MAX_PLAYERS || local == 0 || local >= addrs.len() {
+		return None;
+	}
+	let setup = Setup {
+		players: addrs.len(),
+		seed,
+		mode,
+		score_attack_time,
+		rain,
+		traction_control,
+		level: lines.next()?.to_string(),
+		car: lines.next()?.to_string(),
+	};
+	Some((local, setup, addrs))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn get_setup(players: usize) -> Setup {
+		Setup {
+			players,
+			seed: 1234567890123,
+			mode: "score_attack".to_string(),
+			score_attack_time: 90,
+			rain: true,
+			traction_control: false,
+			level: "Three Pillars".to_string(),
+			car: "Default Car".to_string(),
+		}
+	}
+
+	fn parse(msg: &str) -> Option<(usize, Setup, Vec<Option<SocketAddr>>)> {
+		let mut lines = msg.lines();
+		let mut words = lines.next()?.split_whitespace();
+		assert_eq!(words.next(), Some("start"));
+		parse_start(&mut words, &mut lines)
+	}
+
+	// A host with the setup started and a peer for everyone else, without any of them there
+	fn get_started(players: usize) -> Session {
+		let mut session = Session::host("127.0.0.1:0", Conditions::default()).unwrap();
+		for i in 1..players {
+			session.peers.push(Some(Peer::new(SocketAddr::from(([127, 0, 0, 1], 1000 + i as u16)))));
+		}
+		session.begin(&get_setup(players));
+		session
+	}
+
+	fn get_known(session: &Session, player: usize) -> Vec<u8> {
+		(0..).map_while(|tick| session.get_known(player, tick)).map(|i| i.to_bits()).collect()
+	}
+
+	#[test]
+	fn start_round_trips() {
+		let setup = get_setup(3);
+		let addrs = ["-".to_string(), "127.0.0.1:7777".to_string(), "[::1]:8000".to_string()];
+		let (local, parsed, parsed_addrs) = parse(&get_start(2, &setup, &addrs)).unwrap();
+		assert_eq!(local, 2);
+		assert_eq!(parsed, setup);
+		assert_eq!(parsed_addrs, vec![None, addrs[1].parse().ok(), addrs[2].parse().ok()]);
+	}
+
+	#[test]
+	fn bad_starts_are_refused() {
+		let addrs = ["-".to_string(), "127.0.0.1:7777".to_string()];
+		assert!(parse(&get_start(0, &get_setup(2), &addrs)).is_none());   // Nobody else is the host
+		assert!(parse(&get_start(2, &get_setup(2), &addrs)).is_none());
+		assert!(parse(&get_start(1, &get_setup(1), &addrs[..1])).is_none());
+		assert!(parse("start 1 2 free_drift 90 0 0 - 127.0.0.1:7777\nThree Pillars").is_none());   // No car
+	}
+
+	#[test]
+	fn inputs_arrive_in_any_order_once() {
+		let mut session = get_started(2);
+		let mut receive = |msg: &str| session.receive_inputs(1, &mut msg.split_whitespace());
+		receive("1 0 0 12");
+		receive("1 0 3 45");     // Past a gap, waits for it to be sent again
+		receive("1 0 0 123");    // Repeats what it already has
+		receive("1 0 2 3456");
+		receive("0 0 4 f");      // Claims to be someone else
+		receive("1 0 6 x7");     // Not hex
+		assert_eq!(get_known(&session, 1), vec![1, 2, 3, 4, 5, 6]);
+		assert_eq!(session.get_confirmed(), INPUT_DELAY);   // Only our held back inputs so far
+		assert_eq!(session.peers[1].as_ref().map(|p| p.started), Some(true));
+	}
+
+	#[test]
+	fn guesses_repeat_the_last_input() {
+		let mut session = get_started(2);
+		session.receive_inputs(1, &mut "1 0 0 93".split_whitespace());
+		assert_eq!(session.get_input(1, 1).to_bits(), 3);
+		assert_eq!(session.get_input(1, 50).to_bits(), 3);
+		assert_eq!(session.get_known(1, 50), None);
+	}
+}
@@ -0,0 +1,217 @@
+use raylib::{math::Vector2, drawing::RaylibDraw, color::Color};
+use rayon::prelude::*;
+
+use crate::emitter::EmitterConfig;
+
+pub const DEF_PARTICLE_BUDGET: usize = 4096;
+pub const MIN_PARTICLE_BUDGET: usize = 64;
+pub const MAX_PARTICLE_BUDGET: usize = 65536;
+pub const DEF_PARALLEL_THRESHOLD: usize = 2048;  // Below this many particles, rayon's overhead costs more than it saves
+
+// Fixed capacity particle storage, laid out as a structure of arrays.
+// Everything is allocated up front, live particles are packed into 0..count, and dead ones are
+// swapped out with the last live particle so updates never allocate.
+pub struct ParticlePool {
+	pos: Vec<Vector2>,
+	vel: Vec<Vector2>,
+	start_radius: Vec<f32>,
+	radius: Vec<f32>,
+	time_created: Vec<f64>,
+	lifespan: Vec<f64>,
+	tint: Vec<Color>,     // Multiplied with the emitter's colour gradient
+	colour: Vec<Color>,
+
+	count: usize,
+	pub parallel_threshold: usize,
+}
+
+impl Default for ParticlePool {
+	fn default() -> ParticlePool {
+		ParticlePool::with_budget(DEF_PARTICLE_BUDGET)
+	}
+}
+
+impl ParticlePool {
+	pub fn with_budget(budget: usize) -> ParticlePool {
+		ParticlePool {
+			pos: vec![Vector2::zero(); budget],
+			vel: vec![Vector2::zero(); budget],
+			start_radius: vec![0.0; budget],
+			radius: vec![0.0; budget],
+			time_created: vec![0.0; budget],
+			lifespan: vec![0.0; budget],
+			tint: vec![Color::WHITE; budget],
+			colour: vec![Color::BLANK; budget],
+
+			count: 0,
+			parallel_threshold: DEF_PARALLEL_THRESHOLD,
+		}
+	}
+
+	#[inline]
+	pub fn len(&self) -> usize {
+		self.count
+	}
+
+	#[inline]
+	pub fn budget(&self) -> usize {
+		self.pos.len()
+	}
+
+	pub fn spawn(&mut self, pos: Vector2, vel: Vector2, radius: f32, time: f64, lifespan: f64, tint: Color) {
+		let budget = self.budget();
+		if budget == 0 { return }
+
+		let i = if self.count < budget {
+			self.count += 1;
+			self.count - 1
+		} else {
+			// Out of budget, so replace the oldest particle rather than growing. Killing reorders the
+			// slots, so it has to be searched for.
+			(0..budget).min_by(|a, b| self.time_created[*a].total_cmp(&self.time_created[*b])).unwrap_or(0)
+		};
+
+		self.pos[i] = pos;
+		self.vel[i] = vel;
+		self.start_radius[i] = radius;
+		self.radius[i] = radius;
+		self.time_created[i] = time;
+		self.lifespan[i] = lifespan;
+		self.tint[i] = tint;
+		self.colour[i] = Color::BLANK;  // Not visible until the first update
+	}
+
+	pub fn kill_dead(&mut self, time: f64) {
+		let mut i = 0;
+		while i < self.count {
+			if time - self.time_created[i] > self.lifespan[i] {
+				self.swap_remove(i);
+			} else {
+				i += 1;
+			}
+		}
+	}
+
+	fn swap_remove(&mut self, i: usize) {
+		let last = self.count - 1;
+		self.pos.swap(i, last);
+		self.vel.swap(i, last);
+		self.start_radius.swap(i, last);
+		self.radius.swap(i, last);
+		self.time_created.swap(i, last);
+		self.lifespan.swap(i, last);
+		self.tint.swap(i, last);
+		self.colour.swap(i, last);
+		self.count = last;
+	}
+
+	pub fn update(&mut self, dt: f32, time: f64, config: &EmitterConfig, wind: Vector2) {
+		let n = self.count;
+		let drag_mult = (1.0 - config.drag * dt).max(0.0);
+		let pos = &mut self.pos[..n];
+		let vel = &mut self.vel[..n];
+		let radius = &mut self.radius[..n];
+		let colour = &mut self.colour[..n];
+		let start_radius = &self.start_radius[..n];
+		let tint = &self.tint[..n];
+		let time_created = &self.time_created[..n];
+		let lifespan = &self.lifespan[..n];
+
+		let move_particle = |p: &mut Vector2, v: &mut Vector2| {
+			// Drag acts relative to the air, so particles are carried along by the wind
+			*v = wind + (*v - wind).scale_by(drag_mult);
+			*p += v.scale_by(dt);
+		};
+		let resize_particle = |r: &mut f32, start: f32, created: f64, lifespan: f64| {
+			let age = (time - created) as f32;
+			*r = (start + age * config.growth) * config.sample_size_mult(norm_life(age, lifespan));
+		};
+		let colour_particle = |c: &mut Color, tint: Color, created: f64, lifespan: f64| {
+			let col = config.sample_colour(norm_life((time - created) as f32, lifespan));
+			*c = multiply_colour(col, tint);
+		};
+
+		if n >= self.parallel_threshold {
+			let life = || time_created.par_iter().zip(lifespan.par_iter());
+
+			pos.par_iter_mut().zip(vel.par_iter_mut())
+				.for_each(|(p, v)| move_particle(p, v));
+			radius.par_iter_mut().zip(start_radius.par_iter()).zip(life())
+				.for_each(|((r, start), (created, lifespan))| resize_particle(r, *start, *created, *lifespan));
+			colour.par_iter_mut().zip(tint.par_iter()).zip(life())
+				.for_each(|((c, tint), (created, lifespan))| colour_particle(c, *tint, *created, *lifespan));
+		} else {
+			let life = || time_created.iter().zip(lifespan.iter());
+
+			for (p, v) in pos.iter_mut().zip(vel.iter_mut()) {
+				move_particle(p, v);
+			}
+			for ((r, start), (created, lifespan)) in radius.iter_mut().zip(start_radius.iter()).zip(life()) {
+				resize_particle(r, *start, *created, *lifespan);
+			}
+			for ((c, tint), (created, lifespan)) in colour.iter_mut().zip(tint.iter()).zip(life()) {
+				colour_particle(c, *tint, *created, *lifespan);
+			}
+		}
+	}
+
+	pub fn draw<D: RaylibDraw>(&self, d: &mut D) {
+		for i in 0..self.count {
+			d.draw_circle_v(self.pos[i], self.radius[i], self.colour[i]);
+		}
+	}
+}
+
+#[inline]
+fn norm_life(age: f32, lifespan: f64) -> f32 {
+	(age/lifespan as f32).clamp(0.0, 1.0)
+}
+
+#[inline]
+fn multiply_colour(a: Color, b: Color) -> Color {
+	let mul = |x: u8, y: u8| ((x as u16 * y as u16)/255) as u8;
+	Color::new(mul(a.r, b.r), mul(a.g, b.g), mul(a.b, b.b), mul(a.a, b.a))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn sorted(v: &[f64]) -> Vec<f64> {
+		let mut v = v.to_vec();
+		v.sort_by(f64::total_cmp);
+		v
+	}
+
+	#[test]
+	fn full_pool_recycles_oldest_first() {
+		let mut pool = ParticlePool::with_budget(4);
+		for (time, lifespan) in [(0.0, 1.0), (1.0, 10.0), (2.0, 10.0), (3.0, 10.0)] {
+			pool.spawn(Vector2::zero(), Vector2::zero(), 1.0, time, lifespan, Color::WHITE);
+		}
+		pool.kill_dead(3.5);   // The first one, so the last moves into slot 0
+		assert_eq!(pool.len(), 3);
+
+		for i in 4..7 {
+			pool.spawn(Vector2::zero(), Vector2::zero(), 1.0, i as f64, 10.0, Color::WHITE);
+		}
+		assert_eq!(pool.len(), 4);
+		assert_eq!(sorted(&pool.time_created), vec![3.0, 4.0, 5.0, 6.0]);
+
+		for i in 7..9 {
+			pool.spawn(Vector2::zero(), Vector2::zero(), 1.0, i as f64, 10.0, Color::WHITE);
+		}
+		assert_eq!(sorted(&pool.time_created), vec![5.0, 6.0, 7.0, 8.0]);
+	}
+
+	#[test]
+	fn kill_dead_keeps_live_packed() {
+		let mut pool = ParticlePool::with_budget(4);
+		for i in 0..4 {
+			pool.spawn(Vector2::zero(), Vector2::zero(), 1.0, 0.0, i as f64, Color::WHITE);
+		}
+		pool.kill_dead(1.5);
+		assert_eq!(pool.len(), 2);
+		assert!(pool.lifespan[..2].iter().all(|&l| l >= 2.0));
+	}
+}
@@ -0,0 +1,118 @@
+use std::{fs, collections::HashMap};
+
+use crate::misc;
+
+pub const PERSONAL_BESTS_PATH: &str = "personal_bests.txt";
+
+// Best score-attack scores, kept per level, car and run length since runs of different lengths can't be compared.
+// Stored one per line as `score <tab> seconds <tab> level <tab> car`, with tabs, newlines and backslashes in names escaped.
+#[derive(Default)]
+pub struct PersonalBests {
+	scores: HashMap<(String, String, u32), u32>,
+}
+
+impl PersonalBests {
+	pub fn load(path: &str) -> PersonalBests {
+		match fs::read_to_string(path) {
+			Ok(text) => PersonalBests::parse(&text, path),
+			Err(_) => PersonalBests::default(),   // Nothing saved yet
+		}
+	}
+
+	fn parse(text: &str, path: &str) -> PersonalBests {
+		let mut bests = PersonalBests::default();
+		for (line_num, line) in text.lines().enumerate() {
+			if line.trim().is_empty() || line.starts_with('#') { continue }
+
+			let parts: Vec<&str> = line.split('\t').collect();
+			match parts.as_slice() {
+				[score, secs, level, car] => match (score.parse(), secs.parse()) {
+					(Ok(score), Ok(secs)) => { bests.scores.insert((unescape(level), unescape(car), secs), score); },
+					_ => println!("{}: line {}: bad number", path, line_num + 1),
+				},
+				_ => println!("{}: line {}: expected 4 tab separated fields", path, line_num + 1),
+			}
+		}
+		bests
+	}
+
+	pub fn save(&self, path: &str) -> Result<(), String> {
+		misc::write_atomic(path, &self.to_text())
+	}
+
+	fn to_text(&self) -> String {
+		let mut entries: Vec<_> = self.scores.iter().collect();
+		entries.sort_by(|a, b| a.0.cmp(b.0));
+
+		let mut text = String::from("# score\tseconds\tlevel\tcar\n");
+		for ((level, car, secs), score) in entries {
+			text += &format!("{}\t{}\t{}\t{}\n", score, secs, escape(level), escape(car));
+		}
+		text
+	}
+
+	#[inline]
+	pub fn get(&self, level: &str, car: &str, secs: u32) -> Option<u32> {
+		self.scores.get(&(level.to_string(), car.to_string(), secs)).copied()
+	}
+
+	// Records the score if it beats the saved one, returning the previous best
+	pub fn submit(&mut self, level: &str, car: &str, secs: u32, score: u32) -> Option<u32> {
+		let previous = self.get(level, car, secs);
+		if previous.map_or(true, |p| score > p) {
+			self.scores.insert((level.to_string(), car.to_string(), secs), score);
+		}
+		previous
+	}
+}
+
+// Names come from level and car files, so could have anything in them
+fn escape(name: &str) -> String {
+	name.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+fn unescape(field: &str) -> String {
+	let mut out = String::with_capacity(field.len());
+	let mut chars = field.chars();
+	while let Some(c) = chars.next() {
+		if c != '\\' {
+			out.push(c);
+			continue;
+		}
+		match chars.next() {
+			Some('t') => out.push('\t'),
+			Some('n') => out.push('\n'),
+			Some('r') => out.push('\r'),
+			Some(other) => out.push(other),
+			None => out.push('\\'),
+		}
+	}
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trips_awkward_names() {
+		let mut bests = PersonalBests::default();
+		bests.submit("Tab\there", "New\nline", 60, 1200);
+		bests.submit("C:\\back\\slash", "Hatch", 90, 800);
+
+		let loaded = PersonalBests::parse(&bests.to_text(), "test");
+		assert_eq!(loaded.get("Tab\there", "New\nline", 60), Some(1200));
+		assert_eq!(loaded.get("C:\\back\\slash", "Hatch", 90), Some(800));
+		assert_eq!(loaded.scores.len(), 2);
+	}
+
+	#[test]
+	fn only_keeps_better_scores() {
+		let mut bests = PersonalBests::default();
+		assert_eq!(bests.submit("Level", "Car", 60, 500), None);
+		assert_eq!(bests.submit("Level", "Car", 60, 400), Some(500));
+		assert_eq!(bests.submit("Level", "Car", 60, 700), Some(500));
+		assert_eq!(bests.get("Level", "Car", 60), Some(700));
+		assert_eq!(bests.get("Level", "Car", 90), None);
+	}
+}
@@ -1,14 +1,15 @@
-use raylib::{math::Vector2, drawing::{RaylibDraw, RaylibDrawHandle}, RaylibHandle, color::Color};
+use raylib::{math::Vector2, drawing::RaylibDraw, RaylibHandle, color::Color};
 
 use crate::{RED_1, BG_COLOR};
 
 const DEF_PILLAR_RADIUS: f32 = 7.0;
 
+#[derive(Clone)]
 pub struct Pillar {    // Pillars for the player to drift around
 	pub pos: Vector2,
 	pub radius: f32,
-	pub progress: f32, // Progress completed (in radians)
-	pub player_start_angle: f32,	// Start angle around pillar (so progress can be calculated)
+	pub progress: f32, // Turns round the pillar towards the next full 360, signed by direction
+	pub last_angle: f32,	// Player's angle around pillar last tick (so progress can be accumulated)
 	pub done: bool,	   // If player has done full 360 around it yet.
 }
 
@@ -18,7 +19,7 @@ impl Default for Pillar {
 			pos: Vector2 { x: 300.0, y: 400.0 },
 			radius: DEF_PILLAR_RADIUS,
 			progress: 0.0,
-			player_start_angle: 0.0,
+			last_angle: 0.0,
 			done: false,
 		}
 	}
@@ -33,7 +34,7 @@ impl Pillar {
 		}
 	}
 
-	pub fn draw(&self, d: &mut RaylibDrawHandle) {
+	pub fn draw<D: RaylibDraw>(&self, d: &mut D) {
 		d.draw_circle_v(self.pos, self.radius, RED_1);
 		let col = match self.done {
 			true => Color::LIME,
@@ -0,0 +1,169 @@
+// Local split screen multiplayer. Player one is still the game's own car and scoring, these are
+// everyone else: each has their own car, input device, score and combo, and scores off the same
+// pillars in the same way, just without player one's popups and sounds.
+
+use raylib::{math::Vector2, color::Color, consts::{KeyboardKey, GamepadAxis, GamepadButton}, RaylibHandle};
+
+use crate::{
+	ai::AiDriver,
+	car::{self, Car},
+	car_spec::CarSpec,
+	combo::{Combo, ComboEvent},
+	input::Input,
+	level::Level,
+	run_stats::RunStats,
+	settings::Controls,
+	POINT_DIST_THRESHOLD,
+};
+
+pub const MAX_PLAYERS: usize = 4;
+const START_SPACING: f32 = car::CAR_W * 2.0;   // Sideways gap between cars on the start line
+const STICK_DEADZONE: f32 = 0.3;
+
+pub static PLAYER_COLORS: [Color; crate::net::MAX_PLAYERS] = [   // Enough for online games too
+	Color { r: 190, g: 36, b: 25, a: 255 },
+	Color { r: 40, g: 90, b: 170, a: 255 },
+	Color { r: 40, g: 140, b: 60, a: 255 },
+	Color { r: 210, g: 140, b: 20, a: 255 },
+	Color { r: 120, g: 50, b: 150, a: 255 },
+	Color { r: 30, g: 150, b: 160, a: 255 },
+	Color { r: 220, g: 90, b: 140, a: 255 },
+	Color { r: 90, g: 90, b: 90, a: 255 },
+];
+
+#[derive(Clone, Copy)]
+pub enum Role<'a> {
+	Solo,             // Scores off the pillars
+	Lead,             // Tandem, scored by the tandem rules instead
+	Chase(&'a Car),   // Tandem, following this car
+}
+
+pub enum Device {
+	Keys(Controls),
+	Gamepad(i32),
+	Ai(Box<AiDriver>),
+	Net,   // Someone online, their input is set by the rollback each tick
+}
+
+impl Device {
+	// Player two gets the arrow keys, since player one's default is WASD, and the rest get gamepads
+	pub fn for_player(i: usize) -> Device {
+		match i {
+			1 => Device::Keys(Controls {
+				accelerate: KeyboardKey::KEY_UP,
+				brake: KeyboardKey::KEY_DOWN,
+				steer_left: KeyboardKey::KEY_LEFT,
+				steer_right: KeyboardKey::KEY_RIGHT,
+				..Controls::default()
+			}),
+			_ => Device::Gamepad(i as i32 - 2),
+		}
+	}
+
+	pub fn name(&self) -> String {
+		match self {
+			Device::Keys(_) => "Arrow keys".to_string(),
+			Device::Gamepad(pad) => format!("Gamepad {}", pad + 1),
+			Device::Ai(_) => "AI".to_string(),
+			Device::Net => "Online".to_string(),
+		}
+	}
+}
+
+pub struct Player {
+	pub car: Car,
+	pub device: Device,
+	pub input: Input,     // Read once a frame and used for every tick in it
+	pub score: u32,
+	pub combo: Combo,
+	pub stats: RunStats,
+	pub closest_pillar: (usize, f32),
+	pub scoring: bool,
+}
+
+impl Player {
+	pub fn new(spec: &CarSpec, index: usize, device: Device, level: &Level, seed: u64, particle_budget: usize) -> Player {
+		let mut spec = spec.clone();
+		spec.tint = PLAYER_COLORS[index];
+		let (pos, angle) = get_start(level, index);
+		Player {
+			car: Car::new(&spec, pos, angle, seed.wrapping_add(index as u64 * 8), particle_budget),
+			device,
+			input: Input::default(),
+			score: 0,
+			combo: Combo::default(),
+			stats: RunStats::default(),
+			closest_pillar: (0, -1.0),
+			scoring: false,
+		}
+	}
+
+	pub fn reset(&mut self, index: usize, level: &Level) {
+		let (pos, angle) = get_start(level, index);
+		self.car.reset(pos, angle);
+		self.input = Input::default();
+		self.score = 0;
+		self.combo = Combo::default();
+		self.stats = RunStats::default();
+		self.closest_pillar = (0, -1.0);
+		self.scoring = false;
+		if let Device::Ai(ai) = &mut self.device {
+			**ai = AiDriver::default();
+		}
+	}
+
+	pub fn read_input(&mut self, rl: &RaylibHandle) {
+		self.input = match &self.device {
+			Device::Keys(controls) => Input::from_keys(rl, controls),
+			Device::Gamepad(pad) if rl.is_gamepad_available(*pad) => {
+				let axis = |a: GamepadAxis| rl.get_gamepad_axis_movement(*pad, a);
+				let button = |b: GamepadButton| rl.is_gamepad_button_down(*pad, b);
+				let stick = axis(GamepadAxis::GAMEPAD_AXIS_LEFT_X);
+				Input {   // Triggers rest at -1
+					accelerate: axis(GamepadAxis::GAMEPAD_AXIS_RIGHT_TRIGGER) > 0.0 || button(GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_DOWN),
+					brake: axis(GamepadAxis::GAMEPAD_AXIS_LEFT_TRIGGER) > 0.0 || button(GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_LEFT),
+					steer_left: stick < -STICK_DEADZONE || button(GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_LEFT),
+					steer_right: stick > STICK_DEADZONE || button(GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_RIGHT),
+				}
+			},
+			Device::Gamepad(_) => Input::default(),
+			Device::Ai(_) => Input::default(),   // Worked out each tick instead
+			Device::Net => self.input,
+		};
+	}
+
+	pub fn tick(&mut self, dt: f32, time: f64, level: &Level, role: Role) {
+		if let Device::Ai(ai) = &mut self.device {
+			self.input = match role {
+				Role::Chase(leader) => ai.update_chase(&self.car, leader, level),
+				_ => ai.update(&self.car, level),
+			};
+		}
+		self.car.update(&self.input, dt, time, level);
+		self.stats.update(dt, self.car.vel_mag, self.car.drifting);
+
+		self.closest_pillar = level.pillars.iter().enumerate()
+			.map(|(i, p)| (i, p.distance_to(self.car.pos)))
+			.fold((0, -1.0), |closest, (i, dist)| if dist < closest.1 || closest.1 < 0.0 { (i, dist) } else { closest });
+		self.scoring = matches!(role, Role::Solo) && !level.pillars.is_empty() && self.closest_pillar.1 <= POINT_DIST_THRESHOLD;
+		if self.scoring {
+			self.combo.add(crate::get_points_from_dist(dt, self.closest_pillar.1));
+		}
+		if let Some(ComboEvent::Banked(points, multiplier)) = self.combo.update(dt, self.scoring) {
+			self.add_score(points, multiplier);
+		}
+	}
+
+	#[inline]
+	pub fn add_score(&mut self, points: u32, multiplier: u32) {
+		self.score += points * multiplier;
+		self.stats.add_combo(points, multiplier);
+	}
+}
+
+// Players line up side by side across the start, player one in the middle of the level's start
+pub fn get_start(level: &Level, index: usize) -> (Vector2, f32) {
+	let side = crate::misc::get_components(1.0, level.start_angle + crate::TWO_PI/4.0);
+	let slot = (index as f32/2.0).ceil() * if index % 2 == 1 { 1.0 } else { -1.0 };   // 0, 1, -1, 2
+	(level.start_pos + side.scale_by(slot * START_SPACING), level.start_angle)
+}
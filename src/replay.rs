@@ -0,0 +1,158 @@
+// Recorded runs. The simulation runs at a fixed tick rate, so the inputs for every tick plus the
+// conditions at the start are enough to play a run back exactly. Saved as `key = value` text with
+// the inputs run length encoded as `input = <ticks> <bits>` lines.
+
+use std::fs;
+
+use crate::{input::Input, keyvalue, misc};
+
+pub const REPLAY_DIR: &str = "replays";
+pub const LAST_REPLAY_PATH: &str = "replays/last.replay";
+pub const REPLAY_EXTENSION: &str = "replay";
+
+const FORMAT_VERSION: u32 = 1;
+const MAX_INPUTS: usize = crate::TICK_RATE as usize * 60 * 60 * 4;   // Four hours, so a bad file can't use up all the memory
+
+#[derive(Clone, Debug)]
+pub struct Replay {
+	pub level: String,   // By name, as the high score tables are
+	pub car: String,
+	pub mode: String,    // GameMode key
+	pub score_attack_time: u32,
+	pub rain: bool,
+	pub traction_control: bool,
+	pub seed: u64,
+	pub tick_rate: u32,
+	pub inputs: Vec<Input>,   // One per tick
+}
+
+impl Replay {
+	pub fn load(path: &str) -> Result<Replay, String> {
+		let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+		Replay::parse(&text).map_err(|e| format!("{}: {}", path, e))
+	}
+
+	pub fn parse(text: &str) -> Result<Replay, String> {
+		let mut replay = Replay {
+			level: String::new(),
+			car: String::new(),
+			mode: String::new(),
+			score_attack_time: 0,
+			rain: false,
+			traction_control: false,
+			seed: 0,
+			tick_rate: 0,
+			inputs: vec![],
+		};
+
+		for entry in keyvalue::entries(text) {
+			let e = entry?;
+			match e.key {
+				"version" => match e.value.parse::<u32>() {
+					Ok(v) if v <= FORMAT_VERSION => (),
+					_ => return Err(e.err(&format!("unsupported replay version `{}`", e.value))),
+				},
+				"level" => replay.level = e.value.to_string(),
+				"car" => replay.car = e.value.to_string(),
+				"mode" => replay.mode = e.value.to_string(),
+				"score_attack_time" => replay.score_attack_time = e.single()? as u32,
+				"rain" => replay.rain = e.bool()?,
+				"traction_control" => replay.traction_control = e.bool()?,
+				"seed" => replay.seed = e.value.parse().map_err(|_| e.err("seed must be a whole number"))?,
+				"tick_rate" => replay.tick_rate = e.single()? as u32,
+				"input" => {
+					let nums: Vec<_> = e.value.split_whitespace().map(|v| v.parse::<usize>()).collect();
+					let (count, bits) = match nums.as_slice() {
+						[Ok(count), Ok(bits)] if *bits < 16 => (*count, *bits as u8),
+						_ => return Err(e.err("input must be `<ticks> <bits>`, with bits from 0 to 15")),
+					};
+					if count > MAX_INPUTS - replay.inputs.len() {
+						return Err(e.err("replay is too long"));
+					}
+					replay.inputs.extend(std::iter::repeat(Input::from_bits(bits)).take(count));
+				},
+				_ => return Err(e.err(&format!("unknown key `{}`", e.key))),
+			}
+		}
+
+		if replay.level.is_empty() || replay.car.is_empty() || replay.mode.is_empty() {
+			return Err("replay needs a level, car and mode".to_string());
+		}
+		if replay.tick_rate == 0 {
+			return Err("replay needs a tick_rate".to_string());
+		}
+		Ok(replay)
+	}
+
+	pub fn save(&self, path: &str) -> Result<(), String> {
+		if let Some(dir) = std::path::Path::new(path).parent() {
+			fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+		}
+		misc::write_atomic(path, &self.to_text())
+	}
+
+	fn to_text(&self) -> String {
+		let on = |b: bool| if b { "true" } else { "false" };
+		let mut text = format!(
+			"version = {}\nlevel = {}\ncar = {}\nmode = {}\nscore_attack_time = {}\nrain = {}\ntraction_control = {}\nseed = {}\ntick_rate = {}\n",
+			FORMAT_VERSION, self.level.replace('#', ""), self.car.replace('#', ""), self.mode, self.score_attack_time,
+			on(self.rain), on(self.traction_control), self.seed, self.tick_rate,
+		);
+
+		let mut inputs = self.inputs.iter().peekable();
+		while let Some(input) = inputs.next() {
+			let mut count = 1;
+			while inputs.next_if_eq(&input).is_some() {
+				count += 1;
+			}
+			text += &format!("input = {} {}\n", count, input.to_bits());
+		}
+		text
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const HEADER: &str = "version = 1\nlevel = Proving Ground\ncar = Hatch\nmode = free_drift\ntick_rate = 240\n";
+
+	#[test]
+	fn round_trips() {
+		let replay = Replay {
+			level: "Proving Ground".to_string(),
+			car: "Hatch".to_string(),
+			mode: "score_attack".to_string(),
+			score_attack_time: 90,
+			rain: true,
+			traction_control: false,
+			seed: u64::MAX,
+			tick_rate: 240,
+			inputs: [0, 0, 0, 5, 5, 15, 0].iter().map(|b| Input::from_bits(*b)).collect(),
+		};
+
+		let text = replay.to_text();
+		assert!(text.contains("input = 3 0\ninput = 2 5\ninput = 1 15\ninput = 1 0\n"));
+		let loaded = Replay::parse(&text).unwrap();
+		assert_eq!(loaded.inputs, replay.inputs);
+		assert_eq!(loaded.seed, u64::MAX);
+		assert_eq!((loaded.mode.as_str(), loaded.score_attack_time, loaded.rain), ("score_attack", 90, true));
+	}
+
+	#[test]
+	fn rejects_bad_inputs() {
+		let err = |inputs: &str| Replay::parse(&format!("{}{}", HEADER, inputs)).err().unwrap();
+		assert!(err("input = 10 16\n").contains("bits from 0 to 15"));
+		assert!(err("input = 2.5 1\n").contains("bits from 0 to 15"));
+		assert!(err("input = -1 1\n").contains("bits from 0 to 15"));
+		assert_eq!(err(&format!("input = {} 1\n", usize::MAX)), "line 6: replay is too long");
+		assert_eq!(err(&format!("input = {} 1\ninput = 1 1\n", MAX_INPUTS)), "line 7: replay is too long");
+		assert!(Replay::parse(&format!("{}input = {} 1\n", HEADER, MAX_INPUTS)).is_ok());
+	}
+
+	#[test]
+	fn needs_a_header() {
+		assert_eq!(Replay::parse("level = A\ncar = B\n").err().unwrap(), "replay needs a level, car and mode");
+		assert!(Replay::parse("version = 9\n").err().unwrap().contains("unsupported replay version"));
+	}
+}
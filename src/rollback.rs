@@ -0,0 +1,292 @@
+// Rollback for online games. Each tick runs straight away with a guess at the other players' inputs
+// (whatever they were last pressing). When their real inputs arrive and a guess was wrong, the game
+// goes back to a snapshot from before that tick and runs forward again to the present, quietly, so
+// only the cars' positions jump. Nothing gets far ahead of what's known: past MAX_ROLLBACK ticks of
+// guessing it waits, and the tick that ends a timed run only runs once everyone's inputs are in.
+
+use std::collections::VecDeque;
+
+use crate::{
+	car, combo::Combo, hud::Hud, input::Input, judging::Judge, net::Session, pillar::Pillar, run_stats::RunStats,
+	Game, GameState, MAX_TICKS_PER_FRAME, TICK_DT,
+};
+
+const MAX_ROLLBACK: usize = 72;   // Ticks, 0.3s
+
+struct PlayerSnapshot {
+	car: car::Snapshot,
+	input: Input,
+	score: u32,
+	combo: Combo,
+	stats: RunStats,
+	closest_pillar: (usize, f32),
+	scoring: bool,
+}
+
+// What a tick can change, for the modes that can be played online
+struct Snapshot {
+	player: car::Snapshot,
+	others: Vec<PlayerSnapshot>,
+	pillars: Vec<Pillar>,
+	sim_time: f64,
+	run_time: f32,
+	score: u32,
+	best_score: u32,
+	combo: Combo,
+	stats: RunStats,
+	judge: Judge,
+	hud: Hud,
+	closest_pillar_to_player: (i32, f32),
+	player_is_scoring_points: bool,
+	player_touching_pillar: bool,
+	car_contacts: u32,
+	recorded: usize,   // Inputs in the recording
+}
+
+impl Snapshot {
+	fn take(g: &Game) -> Snapshot {
+		Snapshot {
+			player: g.player.save(),
+			others: g.others.iter().map(|p| PlayerSnapshot {
+				car: p.car.save(),
+				input: p.input,
+				score: p.score,
+				combo: p.combo.clone(),
+				stats: p.stats.clone(),
+				closest_pillar: p.closest_pillar,
+				scoring: p.scoring,
+			}).collect(),
+			pillars: g.level.pillars.clone(),
+			sim_time: g.sim_time,
+			run_time: g.run_time,
+			score: g.score,
+			best_score: g.best_score,
+			combo: g.combo.clone(),
+			stats: g.stats.clone(),
+			judge: g.judge.clone(),
+			hud: g.hud.clone(),
+			closest_pillar_to_player: g.closest_pillar_to_player,
+			player_is_scoring_points: g.player_is_scoring_points,
+			player_touching_pillar: g.player_touching_pillar,
+			car_contacts: g.car_contacts,
+			recorded: g.recording.inputs.len(),
+		}
+	}
+
+	fn restore(&self, g: &mut Game) {
+		g.player.restore(&self.player);
+		for (p, s) in g.others.iter_mut().zip(self.others.iter()) {
+			p.car.restore(&s.car);
+			p.input = s.input;
+			p.score = s.score;
+			p.combo = s.combo.clone();
+			p.stats = s.stats.clone();
+			p.closest_pillar = s.closest_pillar;
+			p.scoring = s.scoring;
+		}
+		g.level.pillars = self.pillars.clone();
+		g.sim_time = self.sim_time;
+		g.run_time = self.run_time;
+		g.score = self.score;
+		g.best_score = self.best_score;
+		g.combo = self.combo.clone();
+		g.stats = self.stats.clone();
+		g.judge = self.judge.clone();
+		g.hud = self.hud.clone();
+		g.closest_pillar_to_player = self.closest_pillar_to_player;
+		g.player_is_scoring_points = self.player_is_scoring_points;
+		g.player_touching_pillar = self.player_touching_pillar;
+		g.car_contacts = self.car_contacts;
+		g.recording.inputs.truncate(self.recorded);
+	}
+}
+
+struct Frame {
+	tick: usize,
+	before: Snapshot,
+	used: Vec<Input>,   // Everyone's input the tick ran with, guessed or not
+}
+
+#[derive(Default)]
+pub struct Rollback {
+	pub tick: usize,            // Ticks run so far
+	frames: VecDeque<Frame>,    // Ticks from the first one still based on a guess
+	pub resimulated: usize,     // Ticks run again on the last frame, for the debug overlay
+}
+
+impl Rollback {
+	// Fixes up any wrong guesses, then runs as many new ticks as the frame time covers. The game's
+	// session has to be borrowed a bit at a time, around the ticks.
+	pub fn update(&mut self, g: &mut Game, dt: f32, keys: Input) {
+		self.resimulated = 0;
+		let net = match g.net.as_ref() {
+			Some(net) => net,
+			None => return,
+		};
+		let wrong = self.frames.iter().position(|f| f.used.iter().enumerate().any(|(p, used)| net.get_known(p, f.tick).is_some_and(|real| real != *used)));
+		if let Some(from) = wrong {
+			let inputs: Vec<_> = self.frames.iter().skip(from).map(|f| get_inputs(net, f.tick)).collect();
+			self.resimulate(g, from, inputs);
+		}
+
+		g.tick_accumulator = (g.tick_accumulator + dt).min(MAX_TICKS_PER_FRAME as f32 * TICK_DT);
+		while g.tick_accumulator >= TICK_DT && g.state == GameState::Playing {
+			let last = g.get_time_limit().is_some_and(|limit| g.run_time + TICK_DT >= limit);
+			let net = match g.net.as_mut() {
+				Some(net) => net,
+				None => return,
+			};
+			let confirmed = net.get_confirmed();
+			if self.tick >= confirmed + MAX_ROLLBACK || (last && self.tick >= confirmed) {
+				break;   // Wait for the others
+			}
+			g.tick_accumulator -= TICK_DT;
+			net.add_local_input(keys);
+			let used = get_inputs(net, self.tick);
+			self.frames.push_back(Frame { tick: self.tick, before: Snapshot::take(g), used: used.clone() });
+			run_tick(g, &used);
+			self.tick += 1;
+		}
+
+		let confirmed = g.net.as_ref().map_or(0, |n| n.get_confirmed());
+		while self.frames.front().is_some_and(|f| f.tick < confirmed) {
+			self.frames.pop_front();
+		}
+	}
+
+	// Goes back to before frames[from] and runs every tick since again, with the inputs known now
+	fn resimulate(&mut self, g: &mut Game, from: usize, inputs: Vec<Vec<Input>>) {
+		self.frames[from].before.restore(g);
+		let audio = g.audio.take();   // Sounds have already played
+		set_effects(g, false);
+		for (i, used) in (from..self.frames.len()).zip(inputs) {
+			if i > from {
+				self.frames[i].before = Snapshot::take(g);
+			}
+			run_tick(g, &used);
+			self.frames[i].used = used;
+			self.resimulated += 1;
+		}
+		set_effects(g, true);
+		g.audio = audio;
+	}
+}
+
+#[inline]
+fn get_inputs(net: &Session, tick: usize) -> Vec<Input> {
+	(0..net.players).map(|p| net.get_input(p, tick)).collect()
+}
+
+// Player one's input goes through tick, everyone else's is left on their player
+fn run_tick(g: &mut Game, inputs: &[Input]) {
+	for (p, input) in g.others.iter_mut().zip(inputs.iter().skip(1)) {
+		p.input = *input;
+	}
+	g.tick(inputs.first().copied().unwrap_or_default());
+}
+
+#[inline]
+fn set_effects(g: &mut Game, on: bool) {
+	g.player.effects = on;
+	for p in g.others.iter_mut() {
+		p.car.effects = on;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{thread, time::Duration};
+
+	use super::*;
+	use crate::{net::{Conditions, NetEvent, Setup}, settings::Settings};
+
+	const TICKS: usize = 480;
+	const MAX_FRAMES: usize = 20_000;   // Of at least a millisecond, gives up well before this
+
+	fn get_game(net: Session) -> Game {
+		let mut g = Game::new(Settings::default(), 1).unwrap();
+		g.save_results = false;
+		g.net = Some(net);
+		g
+	}
+
+	#[inline]
+	fn get_tick(g: &Game) -> usize {
+		g.rollback.as_ref().map_or(0, |r| r.tick)
+	}
+
+	// Each player steers their own way, changing every so often
+	fn get_keys(g: &Game) -> Input {
+		let local = g.net.as_ref().map_or(0, |n| n.local);
+		Input::from_bits(((get_tick(g)/(20 + local * 7) + local * 5) % 16) as u8)
+	}
+
+	// A frame of exactly one tick, or of none once the game has run `ticks`. Returns how many ticks were run again.
+	fn run_frame(g: &mut Game, ticks: usize) -> usize {
+		g.tick_accumulator = 0.0;
+		let dt = if get_tick(g) < ticks { TICK_DT } else { 0.0 };
+		let keys = get_keys(g);
+		g.update_online(dt, keys);
+		g.rollback.as_ref().map_or(0, |r| r.resimulated)
+	}
+
+	#[test]
+	fn lossy_loopback_games_agree() {
+		let conditions = Conditions { latency: 0.05, loss: 0.3 };   // Inputs come later than the input delay covers
+		let mut host = get_game(Session::host("127.0.0.1:0", conditions).unwrap());
+		let addr = host.net.as_ref().unwrap().get_local_addr();
+		let mut client = get_game(Session::join(&addr, conditions).unwrap());
+
+		let mut frames = 0;
+		while host.net.as_ref().unwrap().players < 2 {
+			host.net.as_mut().unwrap().poll(TICK_DT);
+			client.net.as_mut().unwrap().poll(TICK_DT);
+			thread::sleep(Duration::from_millis(1));
+			frames += 1;
+			assert!(frames < MAX_FRAMES, "client never joined");
+		}
+		let setup = Setup {
+			players: 0,
+			seed: 42,
+			mode: "free_drift".to_string(),
+			score_attack_time: 90,
+			rain: false,
+			traction_control: false,
+			level: host.levels[0].name.clone(),
+			car: host.cars[0].name.clone(),
+		};
+		let setup = host.net.as_mut().unwrap().start(setup);
+		host.start_online(&setup).unwrap();
+		while client.rollback.is_none() {
+			for event in client.net.as_mut().unwrap().poll(TICK_DT) {
+				if let NetEvent::Started(setup) = event {
+					client.start_online(&setup).unwrap();
+				}
+			}
+			host.net.as_mut().unwrap().poll(TICK_DT);
+			thread::sleep(Duration::from_millis(1));
+			frames += 1;
+			assert!(frames < MAX_FRAMES, "client never started");
+		}
+
+		// Runs both ahead on guesses, then lets the real inputs catch up without running any more ticks
+		let confirmed = |g: &Game| g.net.as_ref().map_or(0, |n| n.get_confirmed());
+		let mut resimulated = 0;
+		while get_tick(&host) < TICKS || get_tick(&client) < TICKS || confirmed(&host) < TICKS || confirmed(&client) < TICKS {
+			resimulated += run_frame(&mut host, TICKS) + run_frame(&mut client, TICKS);
+			thread::sleep(Duration::from_millis(1));
+			frames += 1;
+			assert!(frames < MAX_FRAMES, "stuck at ticks {} and {}", get_tick(&host), get_tick(&client));
+		}
+		assert!(resimulated > 0);   // Or it wasn't tested
+		assert_eq!(host.state, GameState::Playing);
+		assert_eq!(client.state, GameState::Playing);
+
+		for g in [&host, &client] {
+			assert!(g.player.vel_mag > 0.0 && g.others[0].car.vel_mag > 0.0);
+		}
+		let cars = |g: &Game| [&g.player, &g.others[0].car].map(|c| (c.pos, c.vel, c.angle.to_bits(), c.perp.to_bits()));
+		assert_eq!(cars(&host), cars(&client));
+		assert_eq!((host.score, host.others[0].score), (client.score, client.others[0].score));
+	}
+}
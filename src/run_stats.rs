@@ -0,0 +1,49 @@
+use crate::hud::SpeedUnit;
+
+#[derive(Default, Clone)]
+pub struct RunStats {    // Collected over a run for the results screen
+	pub drift_points: u32,   // Before multipliers
+	pub bonus_points: u32,   // Added on by multipliers
+	pub combos: u32,
+	pub best_combo: u32,
+	pub best_multiplier: u32,
+	pub top_speed: f32,
+	pub drift_time: f32,     // In seconds
+	pub longest_drift: f32,
+	current_drift: f32,
+}
+
+impl RunStats {
+	pub fn add_combo(&mut self, points: u32, multiplier: u32) {
+		let total = points * multiplier;
+		self.drift_points += points;
+		self.bonus_points += total - points;
+		self.combos += 1;
+		self.best_combo = self.best_combo.max(total);
+		self.best_multiplier = self.best_multiplier.max(multiplier);
+	}
+
+	pub fn update(&mut self, dt: f32, speed: f32, drifting: bool) {
+		self.top_speed = self.top_speed.max(speed);
+		if drifting {
+			self.drift_time += dt;
+			self.current_drift += dt;
+			self.longest_drift = self.longest_drift.max(self.current_drift);
+		} else {
+			self.current_drift = 0.0;
+		}
+	}
+
+	pub fn get_breakdown(&self, units: SpeedUnit) -> Vec<String> {
+		vec![
+			format!("Drift points: {}", self.drift_points),
+			format!("Multiplier bonus: {}", self.bonus_points),
+			format!("Combos: {}", self.combos),
+			format!("Best combo: {}", self.best_combo),
+			format!("Best multiplier: x{}", self.best_multiplier.max(1)),
+			format!("Top speed: {:.0} {}", units.convert(self.top_speed), units.label()),
+			format!("Time drifting: {:.1}s", self.drift_time),
+			format!("Longest drift: {:.1}s", self.longest_drift),
+		]
+	}
+}
@@ -0,0 +1,313 @@
+// Player settings, kept in a `key = value` file next to the game. A file with the defaults is written
+// on first run. Bad lines are reported and skipped, and out of range values are clamped, so a typo
+// never stops the game from starting.
+
+use std::fs;
+
+use raylib::{consts::KeyboardKey, input::key_from_i32};
+
+use crate::{
+	keyvalue,
+	hud::SpeedUnit,
+	misc,
+	particle_pool::{DEF_PARTICLE_BUDGET, MIN_PARTICLE_BUDGET, MAX_PARTICLE_BUDGET},
+	view::{MIN_WINDOW_W, MIN_WINDOW_H, MIN_UI_SCALE, MAX_UI_SCALE},
+	DEF_SCORE_ATTACK_TIME, MIN_SCORE_ATTACK_TIME, MAX_SCORE_ATTACK_TIME,
+};
+
+pub const SETTINGS_PATH: &str = "settings.cfg";
+
+pub const FPS_OPTIONS: [u32; 6] = [30, 60, 120, 144, 288, 0];   // 0 is uncapped
+pub const WINDOW_SIZES: [(i32, i32); 5] = [(1000, 800), (1280, 720), (1280, 1024), (1600, 900), (1920, 1080)];
+const MAX_FPS: u32 = 1000;
+const VOLUME_STEP: f32 = 0.1;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Controls {
+	pub accelerate: KeyboardKey,
+	pub brake: KeyboardKey,
+	pub steer_left: KeyboardKey,
+	pub steer_right: KeyboardKey,
+	pub pause: KeyboardKey,
+	pub retry: KeyboardKey,
+}
+
+impl Default for Controls {
+	fn default() -> Controls {
+		Controls {
+			accelerate: KeyboardKey::KEY_W,
+			brake: KeyboardKey::KEY_S,
+			steer_left: KeyboardKey::KEY_A,
+			steer_right: KeyboardKey::KEY_D,
+			pause: KeyboardKey::KEY_P,
+			retry: KeyboardKey::KEY_R,
+		}
+	}
+}
+
+impl Controls {
+	pub const NAMES: [&'static str; 6] = ["Accelerate", "Brake", "Steer left", "Steer right", "Pause", "Retry"];
+	const KEYS: [&'static str; 6] = ["accelerate", "brake", "steer_left", "steer_right", "pause", "retry"];   // In the settings file
+
+	// In the same order as the names, so menus can index them
+	pub fn get_mut(&mut self, i: usize) -> Option<&mut KeyboardKey> {
+		match i {
+			0 => Some(&mut self.accelerate),
+			1 => Some(&mut self.brake),
+			2 => Some(&mut self.steer_left),
+			3 => Some(&mut self.steer_right),
+			4 => Some(&mut self.pause),
+			5 => Some(&mut self.retry),
+			_ => None,
+		}
+	}
+
+	#[inline]
+	pub fn get(&self, i: usize) -> Option<KeyboardKey> {
+		let mut c = *self;
+		c.get_mut(i).copied()
+	}
+
+	// A key already used for something else swaps over, so no two controls share a key
+	pub fn rebind(&mut self, i: usize, key: KeyboardKey) {
+		let old = match self.get(i) {
+			Some(old) => old,
+			None => return,
+		};
+		if let Some(other) = (0..Controls::NAMES.len()).find(|j| *j != i && self.get(*j) == Some(key)) {
+			*self.get_mut(other).unwrap() = old;
+		}
+		*self.get_mut(i).unwrap() = key;
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct Settings {
+	// Graphics
+	pub target_fps: u32,   // 0 is uncapped
+	pub msaa: bool,        // Only takes effect on restart
+	pub window_size: (i32, i32),
+	pub fullscreen: bool,
+	pub ui_scale: f32,
+	pub debug: bool,
+	pub telemetry: bool,   // Saves a CSV of every finished run
+	pub particle_budget: usize,   // Most particles per wheel for each effect
+
+	// Audio
+	pub volume: f32,   // 0 -> 1, 0 mutes
+
+	// Controls
+	pub controls: Controls,
+
+	// Gameplay
+	pub traction_control: bool,   // Eases off the throttle while sideways
+	pub units: SpeedUnit,
+	pub score_attack_time: u32,   // In seconds
+	pub player_name: String,      // For the high score tables
+}
+
+impl Default for Settings {
+	fn default() -> Settings {
+		Settings {
+			target_fps: 144 * 2,
+			msaa: true,
+			window_size: WINDOW_SIZES[0],
+			fullscreen: false,
+			ui_scale: 1.0,
+			debug: true,
+			telemetry: false,
+			particle_budget: DEF_PARTICLE_BUDGET,
+			volume: 0.8,
+			controls: Controls::default(),
+			traction_control: false,
+			units: SpeedUnit::Kmh,
+			score_attack_time: DEF_SCORE_ATTACK_TIME,
+			player_name: crate::high_scores::get_default_name(),
+		}
+	}
+}
+
+impl Settings {
+	// Writes the defaults out if there's no file yet
+	pub fn load(path: &str) -> Settings {
+		let text = match fs::read_to_string(path) {
+			Ok(text) => text,
+			Err(_) => {
+				let settings = Settings::default();
+				if let Err(e) = settings.save(path) {
+					println!("Couldn't write default settings: {}", e);
+				}
+				return settings;
+			},
+		};
+
+		let (settings, errors) = Settings::parse(&text);
+		for e in errors {
+			println!("{}: {}", path, e);
+		}
+		settings
+	}
+
+	// Never fails, anything wrong is left at its default and returned as an error message
+	pub fn parse(text: &str) -> (Settings, Vec<String>) {
+		let mut s = Settings::default();
+		let mut errors = vec![];
+
+		for entry in keyvalue::entries(text) {
+			let e = match entry {
+				Ok(e) => e,
+				Err(err) => {
+					errors.push(err);
+					continue;
+				},
+			};
+
+			let result = match e.key {
+				"target_fps" => e.single().map(|v| s.target_fps = (v.max(0.0) as u32).min(MAX_FPS)),
+				"msaa" => e.bool().map(|v| s.msaa = v),
+				"window_size" => e.pair().map(|(w, h)| s.window_size = ((w as i32).max(MIN_WINDOW_W), (h as i32).max(MIN_WINDOW_H))),
+				"fullscreen" => e.bool().map(|v| s.fullscreen = v),
+				"ui_scale" => e.single().map(|v| s.ui_scale = v.clamp(MIN_UI_SCALE, MAX_UI_SCALE)),
+				"debug" => e.bool().map(|v| s.debug = v),
+				"telemetry" => e.bool().map(|v| s.telemetry = v),
+				"particle_budget" => e.single().map(|v| s.particle_budget = (v.max(0.0) as usize).clamp(MIN_PARTICLE_BUDGET, MAX_PARTICLE_BUDGET)),
+				"volume" => e.single().map(|v| s.volume = v.clamp(0.0, 1.0)),
+				"traction_control" => e.bool().map(|v| s.traction_control = v),
+				"units" => SpeedUnit::from_name(e.value)
+					.map(|u| s.units = u)
+					.ok_or_else(|| e.err("units must be `kmh` or `mph`")),
+				"score_attack_time" => e.single().map(|v| s.score_attack_time = (v.max(0.0) as u32).clamp(MIN_SCORE_ATTACK_TIME, MAX_SCORE_ATTACK_TIME)),
+				"player_name" if !e.value.is_empty() => {
+					s.player_name = e.value.to_string();
+					Ok(())
+				},
+				"player_name" => Err(e.err("player_name can't be empty")),
+				key => match Controls::KEYS.iter().position(|k| *k == key) {
+					Some(i) => key_from_name(e.value)
+						.map(|k| *s.controls.get_mut(i).unwrap() = k)
+						.ok_or_else(|| e.err(&format!("unknown key `{}`", e.value))),
+					None => Err(e.err(&format!("unknown setting `{}`", key))),
+				},
+			};
+			if let Err(err) = result {
+				errors.push(err);
+			}
+		}
+		(s, errors)
+	}
+
+	#[inline]
+	pub fn save(&self, path: &str) -> Result<(), String> {
+		misc::write_atomic(path, &self.to_text())
+	}
+
+	fn to_text(&self) -> String {
+		let on = |b: bool| if b { "true" } else { "false" };
+		let mut text = format!(
+			"# Graphics\n\
+			target_fps = {}   # 0 for uncapped\n\
+			msaa = {}   # Needs a restart\n\
+			window_size = {} {}\n\
+			fullscreen = {}\n\
+			ui_scale = {:.1}\n\
+			debug = {}\n\
+			telemetry = {}   # CSV of each run in the telemetry folder\n\
+			particle_budget = {}   # Per wheel for each effect, from the next run\n\
+			\n# Audio\n\
+			volume = {:.1}   # 0 -> 1\n\
+			\n# Controls, using raylib's key names without the KEY_ prefix\n",
+			self.target_fps, on(self.msaa), self.window_size.0, self.window_size.1, on(self.fullscreen),
+			self.ui_scale, on(self.debug), on(self.telemetry), self.particle_budget, self.volume,
+		);
+		for (i, key) in Controls::KEYS.iter().enumerate() {
+			text += &format!("{} = {}\n", key, key_name(self.controls.get(i).unwrap()));
+		}
+		text += &format!(
+			"\n# Gameplay\n\
+			traction_control = {}\n\
+			units = {}   # kmh or mph\n\
+			score_attack_time = {}   # Seconds\n\
+			player_name = {}\n",
+			on(self.traction_control), self.units.file_name(), self.score_attack_time, self.player_name.replace('#', ""),
+		);
+		text
+	}
+
+	#[inline]
+	pub fn change_volume(&mut self, steps: i32) {
+		self.volume = ((self.volume + steps as f32 * VOLUME_STEP) * 10.0).round()/10.0;
+		self.volume = self.volume.clamp(0.0, 1.0);
+	}
+
+	pub fn cycle_fps(&mut self, dir: i32) {
+		let i = FPS_OPTIONS.iter().position(|f| *f == self.target_fps).unwrap_or(0) as i32;
+		self.target_fps = FPS_OPTIONS[(i + dir).rem_euclid(FPS_OPTIONS.len() as i32) as usize];
+	}
+
+	pub fn cycle_window_size(&mut self, dir: i32) {
+		let i = WINDOW_SIZES.iter().position(|s| *s == self.window_size).unwrap_or(0) as i32;
+		self.window_size = WINDOW_SIZES[(i + dir).rem_euclid(WINDOW_SIZES.len() as i32) as usize];
+	}
+}
+
+// e.g. KEY_LEFT_SHIFT is "LEFT_SHIFT"
+pub fn key_name(key: KeyboardKey) -> String {
+	let name = format!("{:?}", key);
+	name.strip_prefix("KEY_").unwrap_or(&name).to_string()
+}
+
+fn key_from_name(name: &str) -> Option<KeyboardKey> {
+	let name = name.to_uppercase();
+	(0..400).filter_map(key_from_i32).find(|k| key_name(*k) == name)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trips() {
+		let mut s = Settings {
+			target_fps: 60,
+			window_size: (1600, 900),
+			ui_scale: 1.5,
+			volume: 0.3,
+			traction_control: true,
+			units: SpeedUnit::Mph,
+			score_attack_time: 120,
+			particle_budget: 1000,
+			player_name: "Someone Else".to_string(),
+			..Default::default()
+		};
+		s.controls.rebind(0, KeyboardKey::KEY_UP);
+
+		let (loaded, errors) = Settings::parse(&s.to_text());
+		assert!(errors.is_empty(), "{:?}", errors);
+		assert_eq!(loaded.to_text(), s.to_text());
+		assert_eq!(loaded.controls.accelerate, KeyboardKey::KEY_UP);
+	}
+
+	#[test]
+	fn bad_lines_are_skipped() {
+		let (s, errors) = Settings::parse("volume = 0.5\nshadows = true\nmsaa = maybe\nbrake = NOT_A_KEY\nui_scale = 50\n");
+		assert_eq!(errors, vec![
+			"line 2: unknown setting `shadows`".to_string(),
+			"line 3: expected `true` or `false`".to_string(),
+			"line 4: unknown key `NOT_A_KEY`".to_string(),
+		]);
+		assert_eq!(s.volume, 0.5);
+		assert_eq!(s.msaa, Settings::default().msaa);
+		assert_eq!(s.controls.brake, KeyboardKey::KEY_S);
+		assert_eq!(s.ui_scale, MAX_UI_SCALE);
+	}
+
+	#[test]
+	fn rebinding_a_used_key_swaps() {
+		let mut c = Controls::default();
+		c.rebind(0, KeyboardKey::KEY_S);   // Brake's key
+		assert_eq!((c.accelerate, c.brake), (KeyboardKey::KEY_S, KeyboardKey::KEY_W));
+		c.rebind(2, KeyboardKey::KEY_LEFT);
+		assert_eq!(c.steer_left, KeyboardKey::KEY_LEFT);
+		assert_eq!(c.steer_right, KeyboardKey::KEY_D);
+	}
+}
@@ -0,0 +1,123 @@
+use raylib::{math::{Vector2, Rectangle}, drawing::RaylibDraw, color::Color};
+
+use crate::{BG_COLOR, CHARCOAL, car::DRIFT_TRAIL_WIDTH};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SurfaceType {
+	Asphalt,
+	Gravel,
+	Grass,
+	Ice,
+	WetTarmac,
+}
+
+pub struct SurfaceProperties {
+	pub grip: f32,                // Multiplier for sideways resistance
+	pub rolling_resistance: f32,  // Multiplier for all deceleration
+	pub ground_colour: Color,
+
+	pub dust_colour: Color,
+	pub dust_size: f32,      // Multiplier for particle radius
+	pub dust_lifespan: f32,  // Multiplier for particle lifespan
+	pub dust_rate: f32,      // Multiplier for emission rate
+
+	pub trail_colour: Color,
+	pub trail_width: f32,
+}
+
+impl SurfaceType {
+	pub fn from_name(name: &str) -> Option<SurfaceType> {   // As written in level files
+		match name {
+			"asphalt" => Some(SurfaceType::Asphalt),
+			"gravel" => Some(SurfaceType::Gravel),
+			"grass" => Some(SurfaceType::Grass),
+			"ice" => Some(SurfaceType::Ice),
+			"wet_tarmac" => Some(SurfaceType::WetTarmac),
+			_ => None,
+		}
+	}
+
+	pub fn properties(&self) -> SurfaceProperties {
+		match self {
+			SurfaceType::Asphalt => SurfaceProperties {
+				grip: 1.0,
+				rolling_resistance: 1.0,
+				ground_colour: BG_COLOR,
+				dust_colour: CHARCOAL,
+				dust_size: 1.0,
+				dust_lifespan: 1.0,
+				dust_rate: 1.0,
+				trail_colour: CHARCOAL,
+				trail_width: DRIFT_TRAIL_WIDTH,
+			},
+			SurfaceType::Gravel => SurfaceProperties {
+				grip: 0.55,
+				rolling_resistance: 1.6,
+				ground_colour: Color { r: 205, g: 190, b: 160, a: 255 },
+				dust_colour: Color { r: 150, g: 120, b: 80, a: 255 },
+				dust_size: 1.6,
+				dust_lifespan: 1.8,
+				dust_rate: 1.5,
+				trail_colour: Color { r: 120, g: 95, b: 60, a: 200 },
+				trail_width: DRIFT_TRAIL_WIDTH * 2.0,
+			},
+			SurfaceType::Grass => SurfaceProperties {
+				grip: 0.45,
+				rolling_resistance: 2.0,
+				ground_colour: Color { r: 170, g: 200, b: 140, a: 255 },
+				dust_colour: Color { r: 90, g: 120, b: 60, a: 255 },
+				dust_size: 0.6,
+				dust_lifespan: 0.8,
+				dust_rate: 0.7,
+				trail_colour: Color { r: 80, g: 100, b: 50, a: 220 },
+				trail_width: DRIFT_TRAIL_WIDTH * 1.5,
+			},
+			SurfaceType::Ice => SurfaceProperties {
+				grip: 0.15,
+				rolling_resistance: 0.6,
+				ground_colour: Color { r: 210, g: 230, b: 240, a: 255 },
+				dust_colour: Color { r: 245, g: 250, b: 255, a: 255 },
+				dust_size: 0.4,
+				dust_lifespan: 0.5,
+				dust_rate: 0.3,
+				trail_colour: Color { r: 170, g: 190, b: 205, a: 150 },
+				trail_width: DRIFT_TRAIL_WIDTH * 0.6,
+			},
+			SurfaceType::WetTarmac => SurfaceProperties {
+				grip: 0.65,
+				rolling_resistance: 1.1,
+				ground_colour: Color { r: 195, g: 200, b: 205, a: 255 },
+				dust_colour: Color { r: 160, g: 170, b: 180, a: 255 },
+				dust_size: 0.8,
+				dust_lifespan: 0.6,
+				dust_rate: 0.8,
+				trail_colour: Color { r: 80, g: 85, b: 90, a: 120 },
+				trail_width: DRIFT_TRAIL_WIDTH,
+			},
+		}
+	}
+}
+
+#[derive(Clone)]
+pub struct SurfaceRegion {    // Rectangular patch of ground with a different surface
+	pub rect: Rectangle,
+	pub surface: SurfaceType,
+}
+
+impl SurfaceRegion {
+	pub fn new(rect: Rectangle, surface: SurfaceType) -> SurfaceRegion {
+		SurfaceRegion {
+			rect,
+			surface,
+		}
+	}
+
+	#[inline]
+	pub fn contains(&self, point: Vector2) -> bool {
+		self.rect.check_collision_point_rec(point)
+	}
+
+	pub fn draw<D: RaylibDraw>(&self, d: &mut D) {
+		d.draw_rectangle_rec(self.rect, self.surface.properties().ground_colour);
+	}
+}
@@ -0,0 +1,256 @@
+// Procedural sound generation. Nothing in here touches the audio device, so sounds can be
+// rendered offline (e.g. to a WAV buffer) as well as streamed while playing.
+
+use raylib::consts::PI;
+
+pub const SAMPLE_RATE: u32 = 44100;
+const TWO_PI: f32 = PI as f32 * 2.0;
+
+const ENGINE_CYLINDERS: f32 = 4.0;
+const ENGINE_BASE_VOLUME: f32 = 0.18;
+const ENGINE_THROTTLE_VOLUME: f32 = 0.14;
+const SQUEAL_BASE_FREQ: f32 = 900.0;     // Hz, at a pitch multiplier of 1
+const SQUEAL_WOBBLE_FREQ: f32 = 7.0;     // Hz, how fast the squeal's pitch warbles
+const SQUEAL_VOLUME: f32 = 0.2;
+const PARAM_SMOOTHING: f32 = 0.002;      // Fraction of the way to the target parameters moved per sample
+
+const CHIME_LENGTH: f32 = 0.5;           // In seconds
+const THUD_LENGTH: f32 = 0.3;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SynthParams {    // What the car is doing, set every frame
+	pub rpm: f32,
+	pub throttle: f32,      // 0 -> 1
+	pub squeal: f32,        // Tyre squeal volume, 0 -> 1
+	pub squeal_pitch: f32,  // Multiplier for the squeal's base frequency
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OneShot {
+	PillarComplete,
+	Collision,
+}
+
+impl OneShot {
+	#[inline]
+	fn length(&self) -> f32 {
+		match self {
+			OneShot::PillarComplete => CHIME_LENGTH,
+			OneShot::Collision => THUD_LENGTH,
+		}
+	}
+}
+
+pub struct Synth {
+	params: SynthParams,   // Smoothed towards the target to avoid clicks
+	target: SynthParams,
+	engine_phase: f32,
+	squeal_phase: f32,
+	wobble_phase: f32,
+	noise_state: u32,
+	noise_lowpass: f32,
+	one_shots: Vec<(OneShot, f32)>,  // Sound and time since it started
+}
+
+impl Default for Synth {
+	fn default() -> Synth {
+		Synth {
+			params: SynthParams::default(),
+			target: SynthParams::default(),
+			engine_phase: 0.0,
+			squeal_phase: 0.0,
+			wobble_phase: 0.0,
+			noise_state: 0x1234_5678,
+			noise_lowpass: 0.0,
+			one_shots: vec![],
+		}
+	}
+}
+
+impl Synth {
+	#[inline]
+	pub fn set_params(&mut self, params: SynthParams) {
+		self.target = params;
+	}
+
+	#[inline]
+	pub fn trigger(&mut self, shot: OneShot) {
+		self.one_shots.push((shot, 0.0));
+	}
+
+	// Fills the buffer with mono samples from -1 to 1
+	pub fn render(&mut self, out: &mut [f32]) {
+		let dt = 1.0/SAMPLE_RATE as f32;
+
+		for sample in out.iter_mut() {
+			self.smooth_params();
+			let engine = self.next_engine_sample(dt);
+			let squeal = self.next_squeal_sample(dt);
+			let shots = self.next_one_shot_sample(dt);
+
+			*sample = (engine + squeal + shots).clamp(-1.0, 1.0);
+		}
+
+		self.one_shots.retain(|(shot, t)| *t < shot.length());
+	}
+
+	#[inline]
+	fn smooth_params(&mut self) {
+		let lerp = |a: f32, b: f32| a + (b - a) * PARAM_SMOOTHING;
+		self.params.rpm = lerp(self.params.rpm, self.target.rpm);
+		self.params.throttle = lerp(self.params.throttle, self.target.throttle);
+		self.params.squeal = lerp(self.params.squeal, self.target.squeal);
+		self.params.squeal_pitch = lerp(self.params.squeal_pitch, self.target.squeal_pitch);
+	}
+
+	fn next_noise(&mut self) -> f32 {   // Xorshift, so rendering is deterministic
+		self.noise_state ^= self.noise_state << 13;
+		self.noise_state ^= self.noise_state >> 17;
+		self.noise_state ^= self.noise_state << 5;
+		(self.noise_state as f32/u32::MAX as f32) * 2.0 - 1.0
+	}
+
+	fn next_engine_sample(&mut self, dt: f32) -> f32 {
+		// Firing frequency of a four stroke engine is half the cylinders per revolution
+		let freq = self.params.rpm/60.0 * ENGINE_CYLINDERS/2.0;
+		self.engine_phase = (self.engine_phase + freq * dt) % 1.0;
+
+		let p = self.engine_phase * TWO_PI;
+		let tone = p.sin() + 0.5 * (2.0 * p).sin() + 0.3 * (3.0 * p).sin() + 0.15 * (5.0 * p).sin();
+		let rasp = self.next_noise() * 0.2 * self.params.throttle;
+		let volume = ENGINE_BASE_VOLUME + ENGINE_THROTTLE_VOLUME * self.params.throttle;
+
+		(tone/1.95 + rasp) * volume
+	}
+
+	fn next_squeal_sample(&mut self, dt: f32) -> f32 {
+		if self.params.squeal < 0.001 { return 0.0 }
+
+		self.wobble_phase = (self.wobble_phase + SQUEAL_WOBBLE_FREQ * dt) % 1.0;
+		let wobble = 1.0 + 0.03 * (self.wobble_phase * TWO_PI).sin();
+		let freq = SQUEAL_BASE_FREQ * self.params.squeal_pitch * wobble;
+		self.squeal_phase = (self.squeal_phase + freq * dt) % 1.0;
+
+		// Tone with some filtered noise to make it sound like rubber rather than a whistle
+		let noise = self.next_noise();
+		self.noise_lowpass += (noise - self.noise_lowpass) * 0.3;
+		let tone = (self.squeal_phase * TWO_PI).sin() * 0.7 + self.noise_lowpass * 0.5;
+
+		tone * self.params.squeal * SQUEAL_VOLUME
+	}
+
+	fn next_one_shot_sample(&mut self, dt: f32) -> f32 {
+		let mut total = 0.0;
+		for i in 0..self.one_shots.len() {
+			let (shot, t) = self.one_shots[i];
+			total += match shot {
+				OneShot::PillarComplete => {
+					// Two rising notes
+					let freq = if t < CHIME_LENGTH * 0.3 { 880.0 } else { 1320.0 };
+					let env = (-t * 6.0).exp();
+					(t * freq * TWO_PI).sin() * env * 0.3
+				},
+				OneShot::Collision => {
+					let env = (-t * 18.0).exp();
+					((t * 60.0 * TWO_PI).sin() * 0.6 + self.next_noise() * 0.4) * env * 0.6
+				},
+			};
+			self.one_shots[i].1 += dt;
+		}
+		total
+	}
+}
+
+// Encodes mono samples (-1 -> 1) as a 16 bit PCM WAV file
+pub fn encode_wav(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+	let data_len = (samples.len() * 2) as u32;
+	let mut wav = Vec::with_capacity(44 + data_len as usize);
+
+	wav.extend_from_slice(b"RIFF");
+	wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+	wav.extend_from_slice(b"WAVE");
+
+	wav.extend_from_slice(b"fmt ");
+	wav.extend_from_slice(&16u32.to_le_bytes());            // Chunk size
+	wav.extend_from_slice(&1u16.to_le_bytes());             // PCM
+	wav.extend_from_slice(&1u16.to_le_bytes());             // Channels
+	wav.extend_from_slice(&sample_rate.to_le_bytes());
+	wav.extend_from_slice(&(sample_rate * 2).to_le_bytes()); // Bytes per second
+	wav.extend_from_slice(&2u16.to_le_bytes());             // Bytes per frame
+	wav.extend_from_slice(&16u16.to_le_bytes());            // Bits per sample
+
+	wav.extend_from_slice(b"data");
+	wav.extend_from_slice(&data_len.to_le_bytes());
+	for s in samples.iter() {
+		wav.extend_from_slice(&to_i16(*s).to_le_bytes());
+	}
+	wav
+}
+
+#[inline]
+pub fn to_i16(sample: f32) -> i16 {
+	(sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+// Renders a short scripted run (rev up, slide, pillar, crash) so the synth can be checked without a window
+pub fn render_demo(seconds: f32) -> Vec<f32> {
+	let mut synth = Synth::default();
+	let total = (seconds * SAMPLE_RATE as f32) as usize;
+	let block = (SAMPLE_RATE/100) as usize;   // Change parameters every 10ms, like a game frame
+	let mut out = vec![0.0; total];
+
+	for (i, chunk) in out.chunks_mut(block).enumerate() {
+		let t = (i * block) as f32/total as f32;
+		let sliding = t > 0.4 && t < 0.8;
+		synth.set_params(SynthParams {
+			rpm: 900.0 + 6000.0 * (t * 2.0).min(1.0),
+			throttle: if t < 0.8 { 1.0 } else { 0.0 },
+			squeal: if sliding { 0.8 } else { 0.0 },
+			squeal_pitch: 0.8 + t * 0.4,
+		});
+		if i == total/block * 6/10 { synth.trigger(OneShot::PillarComplete) }
+		if i == total/block * 9/10 { synth.trigger(OneShot::Collision) }
+
+		synth.render(chunk);
+	}
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn wav_header_matches_samples() {
+		let wav = encode_wav(&[0.0, 1.0, -1.0], 22050);
+		let u32_at = |i: usize| u32::from_le_bytes([wav[i], wav[i + 1], wav[i + 2], wav[i + 3]]);
+		let u16_at = |i: usize| u16::from_le_bytes([wav[i], wav[i + 1]]);
+
+		assert_eq!(wav.len(), 44 + 6);
+		assert_eq!(&wav[0..4], b"RIFF");
+		assert_eq!(u32_at(4), wav.len() as u32 - 8);
+		assert_eq!(&wav[8..16], b"WAVEfmt ");
+		assert_eq!(u16_at(22), 1);         // Channels
+		assert_eq!(u32_at(24), 22050);
+		assert_eq!(u32_at(28), 22050 * 2);
+		assert_eq!(&wav[36..40], b"data");
+		assert_eq!(u32_at(40), 6);
+		assert_eq!(u16_at(46) as i16, i16::MAX);
+		assert_eq!(u16_at(48) as i16, -i16::MAX);
+	}
+
+	#[test]
+	fn one_shots_expire() {
+		let mut synth = Synth::default();
+		synth.trigger(OneShot::Collision);
+		synth.trigger(OneShot::PillarComplete);
+
+		let mut buffer = vec![0.0; (THUD_LENGTH * SAMPLE_RATE as f32) as usize + 10];
+		synth.render(&mut buffer);
+		assert_eq!(synth.one_shots.len(), 1);
+		assert_eq!(synth.one_shots[0].0, OneShot::PillarComplete);
+
+		synth.render(&mut buffer);
+		assert!(synth.one_shots.is_empty());
+	}
+}
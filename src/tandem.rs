@@ -0,0 +1,117 @@
+// Tandem: two cars drifting together, one leading and one chasing, then swapping for a second leg.
+// The leader scores for a tight line round the pillars at a big angle. The chaser scores for
+// staying close behind while matching the leader's drift angle and direction.
+
+use raylib::math::Vector2;
+
+use crate::{car::{self, Car}, level::Level, misc, pillar::Pillar, POINT_DIST_THRESHOLD};
+
+pub const LEG_TIME: f32 = 40.0;       // Seconds each leg lasts
+const LEAD_RATE: f32 = 600.0;         // Points a second for a perfect lead
+const CHASE_RATE: f32 = 600.0;        // Points a second for a perfect chase
+const IDEAL_GAP: f32 = car::CAR_H * 1.5;
+const MAX_GAP: f32 = car::CAR_H * 6.0;   // Chasing stops scoring this far back
+const START_GAP: f32 = car::CAR_H * 2.5;
+
+#[derive(Default)]
+pub struct Tandem {
+	pub leg: usize,            // 0 or 1
+	pub chase_quality: f32,    // 0 -> 1, how well the chaser is doing this tick
+	pub first_leg_time: f32,   // Kept for the results, since the run time restarts each leg
+	lead_points: [f32; 2],     // By player
+	chase_points: [f32; 2],
+}
+
+impl Tandem {
+	#[inline]
+	pub fn get_leader(&self) -> usize {   // Player one leads the first leg
+		self.leg
+	}
+
+	// cars are player one's then player two's
+	pub fn update(&mut self, dt: f32, cars: [&Car; 2], pillars: &[Pillar]) {
+		let lead = self.get_leader();
+		let (leader, chaser) = (cars[lead], cars[1 - lead]);
+
+		if leader.drifting {
+			let line = pillars.iter().map(|p| p.distance_to(leader.pos)).fold(f32::MAX, f32::min);
+			let line = (1.0 - line/POINT_DIST_THRESHOLD).max(0.0);
+			self.lead_points[lead] += dt * LEAD_RATE * line * leader.perp.abs().min(1.0);
+		}
+
+		let behind = (chaser.pos - leader.pos).dot(leader.vel) < 0.0;
+		let same_way = leader.perp.signum() == chaser.perp.signum();   // Drifting the other way scores nothing
+		self.chase_quality = if chaser.drifting && behind && same_way && leader.vel_mag > 0.0 && chaser.vel_mag > 0.0 {
+			let gap = leader.pos.distance_to(chaser.pos);
+			let proximity = 1.0 - ((gap - IDEAL_GAP)/(MAX_GAP - IDEAL_GAP)).clamp(0.0, 1.0);
+			let angle = 1.0 - ((leader.perp - chaser.perp).abs()/2.0).min(1.0);
+			let direction = (leader.vel/leader.vel_mag).dot(chaser.vel/chaser.vel_mag).max(0.0);
+			proximity * angle * direction
+		} else {
+			0.0
+		};
+		self.chase_points[1 - lead] += dt * CHASE_RATE * self.chase_quality;
+	}
+
+	#[inline]
+	pub fn get_total(&self, player: usize) -> u32 {
+		(self.lead_points[player] + self.chase_points[player]) as u32
+	}
+
+	pub fn get_objective(&self) -> String {
+		format!("Leg {}: P{} leads, chase {:.0}%", self.leg + 1, self.get_leader() + 1, self.chase_quality * 100.0)
+	}
+
+	pub fn get_breakdown(&self) -> Vec<String> {
+		(0..2).map(|i| format!("P{} lead: {:.0}, chase: {:.0}", i + 1, self.lead_points[i], self.chase_points[i])).collect()
+	}
+}
+
+// The leader starts on the level's start, the chaser a few car lengths behind
+pub fn get_start(level: &Level, leading: bool) -> (Vector2, f32) {
+	let back = if leading { 0.0 } else { -START_GAP };
+	(level.start_pos + misc::get_components(back, level.start_angle), level.start_angle)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{car_spec::CarSpec, particle_pool::DEF_PARTICLE_BUDGET};
+
+	// Both heading down the screen at the same speed and angle, the chaser `offset` from the leader
+	fn cars(offset: f32, chaser_perp: f32) -> (Car, Car) {
+		let make = |y: f32, perp: f32| {
+			let mut car = Car::new(&CarSpec::default(), Vector2::new(0.0, y), 0.0, 1, DEF_PARTICLE_BUDGET);
+			car.vel = Vector2::new(0.0, 300.0);
+			car.vel_mag = 300.0;
+			car.perp = perp;
+			car.drifting = true;
+			car
+		};
+		(make(0.0, 0.5), make(offset, chaser_perp))
+	}
+
+	fn chase(leader: &Car, chaser: &Car) -> f32 {
+		let mut tandem = Tandem::default();
+		tandem.update(1.0, [leader, chaser], &[]);
+		tandem.chase_quality
+	}
+
+	#[test]
+	fn close_behind_scores() {
+		let (leader, chaser) = cars(-IDEAL_GAP, 0.5);
+		assert!(chase(&leader, &chaser) > 0.99);
+	}
+
+	#[test]
+	fn ahead_scores_nothing() {
+		let (leader, chaser) = cars(IDEAL_GAP, 0.5);
+		assert_eq!(chase(&leader, &chaser), 0.0);
+	}
+
+	#[test]
+	fn drifting_the_other_way_scores_nothing() {
+		let (leader, chaser) = cars(-IDEAL_GAP, -0.4);
+		assert_eq!(chase(&leader, &chaser), 0.0);
+	}
+}
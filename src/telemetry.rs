@@ -0,0 +1,92 @@
+// Per-tick recording of the car's state for tuning the handling. Saved as CSV, with the car spec and
+// conditions in `#` comment lines at the top so files from different tuning sessions can be told apart.
+
+use std::fmt::Write;
+
+use crate::{car::Car, input::Input, misc};
+
+pub const TELEMETRY_DIR: &str = "telemetry";
+
+const COLUMNS: &str = "tick,time,pos_x,pos_y,vel_x,vel_y,vel_mag,angle,angular_vel,perp,drift_angle,throttle,drifting,accelerate,brake,steer,score";
+
+#[derive(Clone, Copy)]
+struct Sample {
+	tick: u32,
+	time: f32,
+	car: CarState,
+	input: Input,
+	score: u32,
+}
+
+#[derive(Clone, Copy)]
+struct CarState {
+	pos: (f32, f32),
+	vel: (f32, f32),
+	vel_mag: f32,
+	angle: f32,
+	angular_vel: f32,
+	perp: f32,
+	throttle: f32,
+	drifting: bool,
+}
+
+pub struct Telemetry {
+	header: Vec<String>,
+	samples: Vec<Sample>,
+}
+
+impl Telemetry {
+	pub fn new(car: &Car, level: &str, mode: &str, tick_rate: u32) -> Telemetry {
+		let s = &car.spec;
+		Telemetry {
+			header: vec![
+				format!("level = {}, mode = {}, tick_rate = {}", level, mode, tick_rate),
+				format!("car = {}, acceleration = {}, turn_speed = {:.3}, lateral_grip = {}, traction_control = {}",
+					s.name, s.acceleration, s.turn_speed, s.lateral_grip, car.traction_control),
+			],
+			samples: vec![],
+		}
+	}
+
+	pub fn record(&mut self, time: f32, car: &Car, input: Input, score: u32) {
+		self.samples.push(Sample {
+			tick: self.samples.len() as u32,
+			time,
+			car: CarState {
+				pos: (car.pos.x, car.pos.y),
+				vel: (car.vel.x, car.vel.y),
+				vel_mag: car.vel_mag,
+				angle: car.angle,
+				angular_vel: car.angular_vel,
+				perp: car.perp,
+				throttle: car.throttle,
+				drifting: car.drifting,
+			},
+			input,
+			score,
+		});
+	}
+
+	pub fn save(&self, path: &str) -> Result<(), String> {
+		let mut csv = String::new();
+		for line in self.header.iter() {
+			let _ = writeln!(csv, "# {}", line);
+		}
+		csv += COLUMNS;
+		csv += "\n";
+
+		for s in self.samples.iter() {
+			let c = &s.car;
+			let steer = s.input.steer_left as i32 - s.input.steer_right as i32;   // 1 is left
+			let _ = writeln!(csv, "{},{:.4},{:.2},{:.2},{:.2},{:.2},{:.2},{:.4},{:.4},{:.4},{:.2},{:.2},{},{},{},{},{}",
+				s.tick, s.time, c.pos.0, c.pos.1, c.vel.0, c.vel.1, c.vel_mag, c.angle, c.angular_vel, c.perp,
+				c.perp.clamp(-1.0, 1.0).asin().to_degrees(), c.throttle, c.drifting as u8,
+				s.input.accelerate as u8, s.input.brake as u8, steer, s.score);
+		}
+
+		if let Some(dir) = std::path::Path::new(path).parent() {
+			std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+		}
+		misc::write_atomic(path, &csv)
+	}
+}
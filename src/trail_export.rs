@@ -0,0 +1,82 @@
+// Records where the car went over a whole run, unlike the on screen trails which fade, and writes
+// it out as an SVG over a plan of the level.
+
+use std::fmt::Write;
+
+use raylib::{math::Vector2, color::Color};
+
+use crate::{car::{Car, DRIFT_TRAIL_WIDTH}, level::Level, misc};
+
+const MIN_POINT_DIST: f32 = 4.0;   // Pixels between recorded points, to keep files small
+
+#[derive(Default)]
+pub struct TrailLog {
+	path: Vec<Vector2>,              // Centre of the car, the whole run
+	strokes: Vec<Vec<Vector2>>,      // Rear wheels while drifting, a new stroke each time a drift starts
+	open: Option<[usize; 2]>,        // Strokes being added to for each rear wheel, while drifting
+}
+
+impl TrailLog {
+	pub fn update(&mut self, car: &Car) {
+		add_point(&mut self.path, car.pos);
+
+		if !car.drifting {
+			self.open = None;
+			return;
+		}
+		let wheels = car.get_wheel_positions();
+		let strokes = &mut self.strokes;
+		let open = *self.open.get_or_insert_with(|| {
+			strokes.push(vec![]);
+			strokes.push(vec![]);
+			[strokes.len() - 2, strokes.len() - 1]
+		});
+		for (stroke, wheel) in open.iter().zip(wheels[2..].iter()) {
+			add_point(&mut self.strokes[*stroke], *wheel);
+		}
+	}
+
+	pub fn write_svg(&self, path: &str, level: &Level) -> Result<(), String> {
+		let (w, h) = (level.size.x, level.size.y);
+		let mut svg = String::new();
+		let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#, w = w, h = h);
+		let _ = writeln!(svg, r#"<rect width="{}" height="{}" fill="{}"/>"#, w, h, hex(level.base_surface.properties().ground_colour));
+		for s in level.surfaces.iter() {
+			let _ = writeln!(svg, r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+				s.rect.x, s.rect.y, s.rect.width, s.rect.height, hex(s.surface.properties().ground_colour));
+		}
+		if let Some(start) = &level.start_line {
+			let _ = writeln!(svg, r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="6"/>"#,
+				start.from.x, start.from.y, start.to.x, start.to.y, hex(crate::CHARCOAL));
+		}
+
+		let _ = writeln!(svg, r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="1.5" stroke-opacity="0.5"/>"#,
+			points(&self.path), hex(crate::RED_2));
+		for stroke in self.strokes.iter().filter(|s| s.len() > 1) {
+			let _ = writeln!(svg, r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="{}" stroke-opacity="0.7" stroke-linejoin="round"/>"#,
+				points(stroke), hex(crate::CHARCOAL), DRIFT_TRAIL_WIDTH);
+		}
+
+		for p in level.pillars.iter() {
+			let _ = writeln!(svg, r#"<circle cx="{}" cy="{}" r="{}" fill="{}"/>"#, p.pos.x, p.pos.y, p.radius, hex(crate::RED_1));
+		}
+		svg += "</svg>\n";
+		misc::write_atomic(path, &svg)
+	}
+}
+
+#[inline]
+fn add_point(line: &mut Vec<Vector2>, point: Vector2) {
+	if line.last().map_or(true, |last| last.distance_to(point) >= MIN_POINT_DIST) {
+		line.push(point);
+	}
+}
+
+fn points(line: &[Vector2]) -> String {
+	line.iter().map(|p| format!("{:.1},{:.1}", p.x, p.y)).collect::<Vec<_>>().join(" ")
+}
+
+#[inline]
+fn hex(col: Color) -> String {
+	format!("#{:02x}{:02x}{:02x}", col.r, col.g, col.b)
+}
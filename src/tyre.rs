@@ -0,0 +1,95 @@
+use raylib::{math::{Vector2, Rectangle}, drawing::{RaylibDraw, RaylibDrawHandle}, color::Color};
+
+use crate::CHARCOAL;
+
+const AMBIENT_TEMP: f32 = 20.0;       // In degrees C
+const SMOKE_TEMP: f32 = 110.0;        // Tyres start to smoke heavily past this temperature
+const MAX_TEMP: f32 = 220.0;
+const HEAT_PER_SLIP: f32 = 0.4;       // Degrees per second gained for every pixel per second of slip
+const COOLING_RATE: f32 = 0.35;       // Fraction of the difference to ambient lost per second
+const WEAR_PER_SLIP: f32 = 0.000_004; // Wear gained per second for every pixel per second of slip
+const OVERHEAT_WEAR_MULT: f32 = 3.0;  // Extra wear at MAX_TEMP, scaled down to nothing at SMOKE_TEMP
+const WORN_GRIP: f32 = 0.6;           // Grip multiplier of a completely worn tyre
+
+const HUD_TYRE_W: f32 = 14.0;
+const HUD_TYRE_H: f32 = 24.0;
+const HUD_TYRE_GAP: f32 = 16.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Tyre {
+	pub temperature: f32,
+	pub wear: f32,   // 0 is new, 1 is completely worn
+}
+
+impl Default for Tyre {
+	fn default() -> Tyre {
+		Tyre {
+			temperature: AMBIENT_TEMP,
+			wear: 0.0,
+		}
+	}
+}
+
+impl Tyre {
+	// Slip is how fast the tyre's surface is moving over the ground, from sliding and wheelspin
+	pub fn update(&mut self, dt: f32, slip: f32) {
+		self.temperature += (slip * HEAT_PER_SLIP - (self.temperature - AMBIENT_TEMP) * COOLING_RATE) * dt;
+		self.temperature = self.temperature.min(MAX_TEMP);
+
+		let overheat = 1.0 + self.get_overheat() * OVERHEAT_WEAR_MULT;
+		self.wear = (self.wear + slip * WEAR_PER_SLIP * overheat * dt).min(1.0);
+	}
+
+	#[inline]
+	pub fn get_grip(&self) -> f32 {
+		1.0 - self.wear * (1.0 - WORN_GRIP)
+	}
+
+	#[inline]
+	fn get_overheat(&self) -> f32 {   // 0 below the smoke temperature, 1 at max temperature
+		((self.temperature - SMOKE_TEMP)/(MAX_TEMP - SMOKE_TEMP)).clamp(0.0, 1.0)
+	}
+
+	#[inline]
+	pub fn get_smoke_amount(&self) -> f32 {
+		self.get_overheat()
+	}
+
+	fn get_colour(&self) -> Color {   // Blue when cold, green when warm, red when overheating
+		let t = ((self.temperature - AMBIENT_TEMP)/(MAX_TEMP - AMBIENT_TEMP)).clamp(0.0, 1.0);
+		if t < 0.5 {
+			let f = t * 2.0;
+			Color::new(60, (120.0 + 80.0 * f) as u8, (200.0 * (1.0 - f)) as u8 + 40, 255)
+		} else {
+			let f = (t - 0.5) * 2.0;
+			Color::new((60.0 + 170.0 * f) as u8, (200.0 * (1.0 - f)) as u8 + 30, 40, 255)
+		}
+	}
+}
+
+// Draws the four tyres laid out like the car from above, coloured by temperature, with wear shown as a filled bar
+pub fn draw_tyre_state(d: &mut RaylibDrawHandle, tyres: &[Tyre; 4], pos: Vector2, scale: f32) {
+	let (tw, th, gap) = (HUD_TYRE_W * scale, HUD_TYRE_H * scale, HUD_TYRE_GAP * scale);
+	let offsets = [
+		Vector2 { x: 0.0, y: 0.0 },             // Left front
+		Vector2 { x: tw + gap, y: 0.0 },        // Right front
+		Vector2 { x: 0.0, y: th + gap },        // Left back
+		Vector2 { x: tw + gap, y: th + gap },   // Right back
+	];
+
+	for (t, off) in tyres.iter().zip(offsets.iter()) {
+		let rec = Rectangle::new(pos.x + off.x, pos.y + off.y, tw, th);
+		let worn_h = th * t.wear;
+
+		d.draw_rectangle_rec(rec, t.get_colour());
+		d.draw_rectangle_rec(Rectangle::new(rec.x, rec.y, tw, worn_h), Color::new(38, 38, 38, 160));
+		d.draw_rectangle_lines_ex(rec, 1, CHARCOAL);
+	}
+
+	let text_x = (pos.x + tw * 2.0 + gap + 10.0 * scale) as i32;
+	let font = (20.0 * scale) as i32;
+	let max_temp = tyres.iter().map(|t| t.temperature).fold(AMBIENT_TEMP, f32::max);
+	let max_wear = tyres.iter().map(|t| t.wear).fold(0.0, f32::max);
+	d.draw_text(format!("{:.0}C", max_temp).as_str(), text_x, pos.y as i32, font, CHARCOAL);
+	d.draw_text(format!("Wear {:.0}%", max_wear * 100.0).as_str(), text_x, (pos.y + th + gap) as i32, font, CHARCOAL);
+}
@@ -0,0 +1,160 @@
+// The world is drawn into a render texture at a fixed internal resolution, which is then scaled
+// to fit the window with bars at the sides if the aspect ratios differ. The HUD is drawn over the
+// top at the window's own resolution so text stays sharp however big the window is.
+
+use raylib::{
+	math::{Vector2, Rectangle},
+	drawing::{RaylibDraw, RaylibDrawHandle, RaylibBlendModeExt},
+	camera::Camera2D,
+	texture::{RenderTexture2D, RaylibRenderTexture2D, RaylibTexture2D},
+	core::window::{get_current_monitor, get_monitor_width, get_monitor_height},
+	consts::{TextureFilter, BlendMode},
+	color::Color,
+	RaylibHandle, RaylibThread,
+};
+
+// Render textures don't get MSAA, so render at double the level size and let filtering smooth the edges
+pub const DEF_INTERNAL_W: u32 = 2000;
+pub const DEF_INTERNAL_H: u32 = 1600;
+pub const MIN_WINDOW_W: i32 = 400;
+pub const MIN_WINDOW_H: i32 = 320;
+
+pub const MIN_UI_SCALE: f32 = 0.5;
+pub const MAX_UI_SCALE: f32 = 2.0;
+const UI_SCALE_STEP: f32 = 0.1;
+
+static LETTERBOX_COLOR: Color = Color { r: 20, g: 20, b: 20, a: 255 };
+
+pub struct View {
+	target: RenderTexture2D,
+	pub ui_scale: f32,               // Multiplier on top of the HUD's scaling with window height
+	windowed_size: (i32, i32),       // Window size to go back to when leaving fullscreen
+}
+
+impl View {
+	pub fn new(rl: &mut RaylibHandle, rl_thread: &RaylibThread, internal_w: u32, internal_h: u32, ui_scale: f32) -> View {
+		View {
+			target: load_target(rl, rl_thread, internal_w, internal_h),
+			ui_scale: ui_scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE),
+			windowed_size: (rl.get_screen_width(), rl.get_screen_height()),
+		}
+	}
+
+	#[inline]
+	pub fn get_internal_size(&self) -> Vector2 {
+		Vector2::new(self.target.texture.width as f32, self.target.texture.height as f32)
+	}
+
+	#[inline]
+	pub fn target_mut(&mut self) -> &mut RenderTexture2D {
+		&mut self.target
+	}
+
+	// Fits the whole world into the internal resolution, centred
+	pub fn get_camera(&self, world_size: Vector2) -> Camera2D {
+		let size = self.get_internal_size();
+		Camera2D {
+			offset: size/2.0,
+			target: world_size/2.0,
+			rotation: 0.0,
+			zoom: (size.x/world_size.x).min(size.y/world_size.y),
+		}
+	}
+
+	// Split screen areas of the internal render: side by side for two players, quarters for more.
+	// Three players leave the last quarter for an overview of the whole level.
+	pub fn get_viewports(&self, players: usize) -> Vec<Rectangle> {
+		let size = self.get_internal_size();
+		let (hw, hh) = (size.x/2.0, size.y/2.0);
+		match players {
+			0 | 1 => vec![Rectangle::new(0.0, 0.0, size.x, size.y)],
+			2 => vec![Rectangle::new(0.0, 0.0, hw, size.y), Rectangle::new(hw, 0.0, hw, size.y)],
+			_ => vec![
+				Rectangle::new(0.0, 0.0, hw, hh), Rectangle::new(hw, 0.0, hw, hh),
+				Rectangle::new(0.0, hh, hw, hh), Rectangle::new(hw, hh, hw, hh),
+			],
+		}
+	}
+
+	// Follows a point at the same scale as the whole level view, kept inside the level where it fits
+	pub fn get_follow_camera(&self, world_size: Vector2, viewport: Rectangle, focus: Vector2) -> Camera2D {
+		let zoom = self.get_camera(world_size).zoom;
+		let half = Vector2::new(viewport.width, viewport.height)/(2.0 * zoom);
+		let clamp = |v: f32, half: f32, max: f32| if half * 2.0 >= max { max/2.0 } else { v.clamp(half, max - half) };
+		Camera2D {
+			offset: Vector2::new(viewport.x + viewport.width/2.0, viewport.y + viewport.height/2.0),
+			target: Vector2::new(clamp(focus.x, half.x, world_size.x), clamp(focus.y, half.y, world_size.y)),
+			rotation: 0.0,
+			zoom,
+		}
+	}
+
+	// Fits the whole world into part of the internal render
+	pub fn get_overview_camera(&self, world_size: Vector2, viewport: Rectangle) -> Camera2D {
+		Camera2D {
+			offset: Vector2::new(viewport.x + viewport.width/2.0, viewport.y + viewport.height/2.0),
+			target: world_size/2.0,
+			rotation: 0.0,
+			zoom: (viewport.width/world_size.x).min(viewport.height/world_size.y),
+		}
+	}
+
+	// Where part of the internal render ends up on screen
+	pub fn to_screen_rect(&self, rect: Rectangle, screen_w: f32, screen_h: f32) -> Rectangle {
+		let dest = self.get_screen_rect(screen_w, screen_h);
+		let scale = dest.width/self.get_internal_size().x;
+		Rectangle::new(dest.x + rect.x * scale, dest.y + rect.y * scale, rect.width * scale, rect.height * scale)
+	}
+
+	// Where the internal render ends up on screen, as big as fits while keeping its aspect ratio
+	pub fn get_screen_rect(&self, screen_w: f32, screen_h: f32) -> Rectangle {
+		let size = self.get_internal_size();
+		let scale = (screen_w/size.x).min(screen_h/size.y);
+		let (w, h) = (size.x * scale, size.y * scale);
+		Rectangle::new((screen_w - w)/2.0, (screen_h - h)/2.0, w, h)
+	}
+
+	pub fn draw_to_screen(&self, d: &mut RaylibDrawHandle) {
+		let size = self.get_internal_size();
+		let dest = self.get_screen_rect(d.get_screen_width() as f32, d.get_screen_height() as f32);
+
+		d.clear_background(LETTERBOX_COLOR);
+
+		// Alpha blending into the target leaves it translucent wherever something translucent was drawn,
+		// so copy it over opaque instead: adding its colours to black is the same as not blending
+		d.draw_rectangle_rec(dest, Color::BLACK);
+		let mut d = d.begin_blend_mode(BlendMode::BLEND_ADD_COLORS);
+		d.draw_texture_pro(
+			self.target.texture(),
+			Rectangle::new(0.0, 0.0, size.x, -size.y),   // Render textures are stored upside down
+			dest,
+			Vector2::zero(),
+			0.0,
+			Color::WHITE
+		);
+	}
+
+	pub fn toggle_fullscreen(&mut self, rl: &mut RaylibHandle) {
+		if rl.is_window_fullscreen() {
+			rl.toggle_fullscreen();
+			rl.set_window_size(self.windowed_size.0, self.windowed_size.1);
+		} else {
+			// Fullscreen keeps the window's size as the video mode, so match the monitor first
+			self.windowed_size = (rl.get_screen_width(), rl.get_screen_height());
+			let monitor = get_current_monitor();
+			rl.set_window_size(get_monitor_width(monitor), get_monitor_height(monitor));
+			rl.toggle_fullscreen();
+		}
+	}
+
+	#[inline]
+	pub fn change_ui_scale(&mut self, steps: i32) {
+		self.ui_scale = (self.ui_scale + steps as f32 * UI_SCALE_STEP).clamp(MIN_UI_SCALE, MAX_UI_SCALE);
+	}
+}
+
+fn load_target(rl: &mut RaylibHandle, rl_thread: &RaylibThread, w: u32, h: u32) -> RenderTexture2D {
+	let target = rl.load_render_texture(rl_thread, w, h).expect("Couldn't create render texture.");
+	target.texture().set_texture_filter(rl_thread, TextureFilter::TEXTURE_FILTER_BILINEAR);
+	target
+}
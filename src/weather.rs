@@ -0,0 +1,67 @@
+use raylib::{math::Vector2, drawing::RaylibDraw, color::Color};
+
+const RAIN_BASE_GRIP: f32 = 0.75;
+const RAIN_MIN_GRIP: f32 = 0.35;
+const RAIN_GRIP_LOSS_PER_SPEED: f32 = 0.0006;  // Grip lost for every pixel per second the car is moving at
+const RAIN_TRAIL_DURATION_MULT: f64 = 0.4;
+
+pub const AQUAPLANE_SPEED: f32 = 250.0;  // Speed above which puddles cause aquaplaning
+pub const AQUAPLANE_GRIP: f32 = 0.1;    // Grip multiplier for a wheel that is aquaplaning
+
+pub const SPRAY_FULL_SPEED: f32 = 400.0;  // Speed at which spray is emitted at the full rate
+
+static PUDDLE_COLOR: Color = Color { r: 120, g: 140, b: 165, a: 90 };
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Weather {
+	Dry,
+	Rain,
+}
+
+impl Weather {
+	pub fn grip_multiplier(&self, speed: f32) -> f32 {
+		match self {
+			Weather::Dry => 1.0,
+			Weather::Rain => (RAIN_BASE_GRIP - speed * RAIN_GRIP_LOSS_PER_SPEED).max(RAIN_MIN_GRIP),
+		}
+	}
+
+	#[inline]
+	pub fn trail_duration_multiplier(&self) -> f64 {
+		match self {
+			Weather::Dry => 1.0,
+			Weather::Rain => RAIN_TRAIL_DURATION_MULT,
+		}
+	}
+
+	pub fn toggled(&self) -> Weather {
+		match self {
+			Weather::Dry => Weather::Rain,
+			Weather::Rain => Weather::Dry,
+		}
+	}
+}
+
+#[derive(Clone)]
+pub struct Puddle {    // Standing water that only exists when it is raining
+	pub pos: Vector2,
+	pub radius: f32,
+}
+
+impl Puddle {
+	pub fn new(pos: Vector2, radius: f32) -> Puddle {
+		Puddle {
+			pos,
+			radius,
+		}
+	}
+
+	#[inline]
+	pub fn contains(&self, point: Vector2) -> bool {
+		point.distance_to(self.pos) <= self.radius
+	}
+
+	pub fn draw<D: RaylibDraw>(&self, d: &mut D) {
+		d.draw_circle_v(self.pos, self.radius, PUDDLE_COLOR);
+	}
+}